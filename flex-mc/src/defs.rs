@@ -1 +1,354 @@
+use anyhow::Result;
+use ssmc_core::{
+    domain::McVanillaVersionId,
+    infra::vanilla::{McVanillaVersion, McVanillaVersionType},
+};
+use std::cmp::Ordering;
 
+/// Minecraftのバージョン文字列とプロトコル番号の対応表。
+/// 1.21.8までの正式リリースをリリース順に収録しており、リリース日時が分からないときの新旧の判断に使う。
+/// 番号は Protocol version numbers
+/// （<https://minecraft.wiki/w/Protocol_version_numbers>、旧wiki.vg）から取っており、
+/// 新しいリリースに対応するときはここに末尾から追加する
+const PROTOCOL_VERSIONS: &[(&str, i32)] = &[
+    ("1.8", 47),
+    ("1.8.1", 47),
+    ("1.8.2", 47),
+    ("1.8.3", 47),
+    ("1.8.4", 47),
+    ("1.8.5", 47),
+    ("1.8.6", 47),
+    ("1.8.7", 47),
+    ("1.8.8", 47),
+    ("1.8.9", 47),
+    ("1.9", 107),
+    ("1.9.1", 108),
+    ("1.9.2", 109),
+    ("1.9.3", 110),
+    ("1.9.4", 110),
+    ("1.10", 210),
+    ("1.10.1", 210),
+    ("1.10.2", 210),
+    ("1.11", 315),
+    ("1.11.1", 316),
+    ("1.11.2", 316),
+    ("1.12", 335),
+    ("1.12.1", 338),
+    ("1.12.2", 340),
+    ("1.13", 393),
+    ("1.13.1", 401),
+    ("1.13.2", 404),
+    ("1.14", 477),
+    ("1.14.1", 480),
+    ("1.14.2", 485),
+    ("1.14.3", 490),
+    ("1.14.4", 498),
+    ("1.15", 573),
+    ("1.15.1", 575),
+    ("1.15.2", 578),
+    ("1.16", 735),
+    ("1.16.1", 736),
+    ("1.16.2", 751),
+    ("1.16.3", 753),
+    ("1.16.4", 754),
+    ("1.16.5", 754),
+    ("1.17", 755),
+    ("1.17.1", 756),
+    ("1.18", 757),
+    ("1.18.1", 757),
+    ("1.18.2", 758),
+    ("1.19", 759),
+    ("1.19.1", 760),
+    ("1.19.2", 760),
+    ("1.19.3", 761),
+    ("1.19.4", 762),
+    ("1.20", 763),
+    ("1.20.1", 763),
+    ("1.20.2", 764),
+    ("1.20.3", 765),
+    ("1.20.4", 765),
+    ("1.20.5", 766),
+    ("1.20.6", 766),
+    ("1.21", 767),
+    ("1.21.1", 767),
+    ("1.21.2", 768),
+    ("1.21.3", 768),
+    ("1.21.4", 769),
+    ("1.21.5", 770),
+    ("1.21.6", 771),
+    ("1.21.7", 772),
+    ("1.21.8", 772),
+];
+
+// ブロックIDが名前空間付きの文字列に置き換えられた（The Flattening）バージョン
+const FLATTENING_VERSION: &str = "1.13";
+// 1.13のバージョンマニフェスト上のリリース日時
+const FLATTENING_RELEASE_TIME: &str = "2018-07-18T15:11:46+00:00";
+
+fn release_index(version: &McVanillaVersionId) -> Option<usize> {
+    PROTOCOL_VERSIONS
+        .iter()
+        .position(|(id, _)| *id == version.id())
+}
+
+// 平坦化より前のバージョンかどうか。マニフェストのリリース日時を1.13のものと比べ、
+// 日時がなければ対応表の順で判断する。どちらでも判断できないバージョンは平坦化前とみなす
+fn is_before_flattening(version: &McVanillaVersion) -> bool {
+    if let Some(release_time) = &version.release_time {
+        return release_time.as_str() < FLATTENING_RELEASE_TIME;
+    }
+    let flattening_index =
+        release_index(&McVanillaVersionId::new(FLATTENING_VERSION.to_string())).unwrap();
+    release_index(&version.version).is_none_or(|index| index < flattening_index)
+}
+
+// マニフェストのリリース日時で新旧を比べる。日時がなければ対応表の順で比べる
+fn compare_release(a: &McVanillaVersion, b: &McVanillaVersion) -> Option<Ordering> {
+    if let (Some(a_time), Some(b_time)) = (&a.release_time, &b.release_time) {
+        return Some(a_time.cmp(b_time));
+    }
+    Some(release_index(&a.version)?.cmp(&release_index(&b.version)?))
+}
+
+/// バージョン更新に伴うリスク
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VersionJumpRisk {
+    /// 新しいバージョンから古いバージョンへの更新
+    Downgrade,
+    /// 1.12以前から1.13以降への更新（ブロックIDの平坦化）
+    Flattening,
+    /// マニフェストでスナップショットとされているバージョンを含む更新
+    Snapshot(String),
+}
+
+impl VersionJumpRisk {
+    /// `allow_risky` なしでは更新を拒否するリスクかどうか
+    pub fn is_refused(&self) -> bool {
+        match self {
+            VersionJumpRisk::Downgrade | VersionJumpRisk::Snapshot(_) => true,
+            VersionJumpRisk::Flattening => false,
+        }
+    }
+}
+
+impl std::fmt::Display for VersionJumpRisk {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VersionJumpRisk::Downgrade => {
+                write!(f, "downgrading is not supported by the data fixer")
+            }
+            VersionJumpRisk::Flattening => write!(
+                f,
+                "crossing the {} flattening may not convert all block data cleanly",
+                FLATTENING_VERSION
+            ),
+            VersionJumpRisk::Snapshot(id) => {
+                write!(f, "{} is a snapshot and may be experimental", id)
+            }
+        }
+    }
+}
+
+/// 危険なバージョン更新を検出するポリシー
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VersionJumpPolicy {
+    allow_risky: bool,
+}
+
+impl VersionJumpPolicy {
+    /// `allow_risky` が真の場合、すべてのリスクを警告として扱う（`--allow-risky` に相当）
+    pub fn new(allow_risky: bool) -> Self {
+        VersionJumpPolicy { allow_risky }
+    }
+
+    /// 更新のリスクを列挙する。
+    /// リリースかどうかと新旧はバージョンマニフェストの種類とリリース日時で判断する
+    pub fn risks(old: &McVanillaVersion, new: &McVanillaVersion) -> Vec<VersionJumpRisk> {
+        let mut risks = Vec::new();
+        for version in [old, new] {
+            if matches!(version.version_type, McVanillaVersionType::Snapshot) {
+                risks.push(VersionJumpRisk::Snapshot(version.version.id().to_string()));
+            }
+        }
+        match compare_release(old, new) {
+            Some(Ordering::Greater) => risks.push(VersionJumpRisk::Downgrade),
+            // 平坦化前から平坦化以降への更新は、新旧を比べられなくても必ず新しくなる更新
+            _ if is_before_flattening(old) && !is_before_flattening(new) => {
+                risks.push(VersionJumpRisk::Flattening)
+            }
+            _ => {}
+        }
+        risks
+    }
+
+    /// 更新を許可する場合は警告すべきリスクを返し、拒否する場合はエラーを返す
+    pub fn check(
+        &self,
+        old: &McVanillaVersion,
+        new: &McVanillaVersion,
+    ) -> Result<Vec<VersionJumpRisk>> {
+        let risks = Self::risks(old, new);
+        if !self.allow_risky && risks.iter().any(VersionJumpRisk::is_refused) {
+            let reasons = risks
+                .iter()
+                .map(|risk| risk.to_string())
+                .collect::<Vec<_>>()
+                .join("; ");
+            anyhow::bail!(
+                "Refusing to update from {} to {}: {} (pass --allow-risky to proceed)",
+                old.version.id(),
+                new.version.id(),
+                reasons
+            );
+        }
+        Ok(risks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_protocol_versions_in_release_order() {
+        // 新旧の判断は対応表の並びに頼るため、プロトコル番号の順に並びバージョンが重複しないこと
+        assert!(
+            PROTOCOL_VERSIONS
+                .windows(2)
+                .all(|pair| pair[0].1 <= pair[1].1)
+        );
+        let ids: std::collections::HashSet<_> =
+            PROTOCOL_VERSIONS.iter().map(|(id, _)| *id).collect();
+        assert_eq!(ids.len(), PROTOCOL_VERSIONS.len());
+    }
+
+    fn release(id: &str, release_time: &str) -> McVanillaVersion {
+        McVanillaVersion {
+            version: McVanillaVersionId::new(id.to_string()),
+            version_type: McVanillaVersionType::Release,
+            release_time: Some(release_time.to_string()),
+        }
+    }
+
+    fn snapshot(id: &str, release_time: &str) -> McVanillaVersion {
+        McVanillaVersion {
+            version: McVanillaVersionId::new(id.to_string()),
+            version_type: McVanillaVersionType::Snapshot,
+            release_time: Some(release_time.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_version_jump_safe_minor() {
+        let risks = VersionJumpPolicy::new(false)
+            .check(
+                &release("1.20.1", "2023-06-12T13:25:51+00:00"),
+                &release("1.20.4", "2023-12-07T12:56:20+00:00"),
+            )
+            .unwrap();
+        assert!(risks.is_empty());
+    }
+
+    #[test]
+    fn test_version_jump_release_newer_than_table_allowed() {
+        // 対応表にない新しいリリースもマニフェストでリリースとされていれば拒否しない
+        let risks = VersionJumpPolicy::new(false)
+            .check(
+                &release("1.21.8", "2025-07-17T12:04:02+00:00"),
+                &release("1.21.10", "2025-10-07T09:17:23+00:00"),
+            )
+            .unwrap();
+        assert!(risks.is_empty());
+    }
+
+    #[test]
+    fn test_version_jump_flattening_warned() {
+        let risks = VersionJumpPolicy::new(false)
+            .check(
+                &release("1.12.2", "2017-09-18T08:39:46+00:00"),
+                &release("1.13.2", "2018-10-22T11:41:07+00:00"),
+            )
+            .unwrap();
+        assert_eq!(risks, vec![VersionJumpRisk::Flattening]);
+    }
+
+    #[test]
+    fn test_version_jump_flattening_from_release_older_than_table() {
+        let risks = VersionJumpPolicy::risks(
+            &release("1.7.10", "2014-05-14T17:29:23+00:00"),
+            &release("1.13", "2018-07-18T15:11:46+00:00"),
+        );
+        assert_eq!(risks, vec![VersionJumpRisk::Flattening]);
+    }
+
+    #[test]
+    fn test_version_jump_flattening_from_snapshot() {
+        let risks = VersionJumpPolicy::risks(
+            &snapshot("17w43a", "2017-10-25T14:41:01+00:00"),
+            &release("1.13", "2018-07-18T15:11:46+00:00"),
+        );
+        assert_eq!(
+            risks,
+            vec![
+                VersionJumpRisk::Snapshot("17w43a".to_string()),
+                VersionJumpRisk::Flattening
+            ]
+        );
+    }
+
+    #[test]
+    fn test_version_jump_flattening_without_release_time() {
+        // リリース日時がなく対応表にもないバージョンは平坦化前とみなす
+        let old = McVanillaVersion {
+            release_time: None,
+            ..release("1.7.10", "")
+        };
+        let new = McVanillaVersion {
+            release_time: None,
+            ..release("1.13", "")
+        };
+        assert_eq!(
+            VersionJumpPolicy::risks(&old, &new),
+            vec![VersionJumpRisk::Flattening]
+        );
+    }
+
+    #[test]
+    fn test_version_jump_snapshot_refused() {
+        let old = release("1.19.4", "2023-03-14T12:56:18+00:00");
+        let new = snapshot("23w18a", "2023-05-03T11:12:34+00:00");
+        let result = VersionJumpPolicy::new(false).check(&old, &new);
+        let message = result.unwrap_err().to_string();
+        assert!(message.contains("23w18a"));
+        assert!(message.contains("--allow-risky"));
+
+        let risks = VersionJumpPolicy::new(true).check(&old, &new).unwrap();
+        assert_eq!(risks, vec![VersionJumpRisk::Snapshot("23w18a".to_string())]);
+    }
+
+    #[test]
+    fn test_version_jump_downgrade_refused() {
+        let result = VersionJumpPolicy::new(false).check(
+            &release("1.20.1", "2023-06-12T13:25:51+00:00"),
+            &release("1.19.4", "2023-03-14T12:56:18+00:00"),
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_version_jump_downgrade_without_release_time() {
+        // リリース日時がなければ対応表の順で新旧を判断する
+        let old = McVanillaVersion {
+            release_time: None,
+            ..release("1.20.1", "")
+        };
+        let new = McVanillaVersion {
+            release_time: None,
+            ..release("1.19.4", "")
+        };
+        assert_eq!(
+            VersionJumpPolicy::risks(&old, &new),
+            vec![VersionJumpRisk::Downgrade]
+        );
+    }
+}
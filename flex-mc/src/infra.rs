@@ -1,5 +1,11 @@
 pub mod bot_spawner;
 pub mod chunk_generator;
+pub mod chunk_migrator;
+pub mod disk_space;
 pub mod flax_updater;
 pub mod free_port_finder;
+pub mod nbt;
+pub mod rcon;
 pub mod region_loader;
+pub mod server_process;
+pub mod snbt;
@@ -1,8 +1,9 @@
 use std::{
+    collections::HashMap,
     io::{BufRead, BufReader},
     net::IpAddr,
-    os::unix::fs::PermissionsExt,
     path::PathBuf,
+    sync::Arc,
     thread,
     time::Duration,
 };
@@ -11,7 +12,12 @@ use tokio::sync::mpsc;
 use anyhow::{Result, anyhow};
 use async_trait::async_trait;
 use serde::Deserialize;
-use ssmc_core::domain::McVanillaVersionId;
+use sha2::{Digest, Sha256};
+use ssmc_core::{
+    domain::McVanillaVersionId,
+    infra::url_fetcher::{DefaultUrlFetcher, UrlFetcher},
+};
+use url::Url;
 
 #[async_trait]
 pub trait BotSpawner {
@@ -33,14 +39,28 @@ pub struct AzaleaBotSpawner {
     bot_file_path: PathBuf,
     max_retries: u32,
     retry_delay: Duration,
+    auto_download: bool,
+    download_base_url: String,
+    bot_versions: HashMap<String, String>,
+    default_bot_version: Option<String>,
+    url_fetcher: Arc<dyn UrlFetcher>,
 }
 
+/// ボットの実行ファイルの配布元
+pub const DEFAULT_BOT_DOWNLOAD_BASE_URL: &str =
+    "https://github.com/txkodo/FlexUpdateMcBot/releases/download";
+
 impl AzaleaBotSpawner {
     pub fn new(bot_file_path: PathBuf) -> Self {
         AzaleaBotSpawner { 
             bot_file_path,
             max_retries: 3,
             retry_delay: Duration::from_secs(5),
+            auto_download: true,
+            download_base_url: DEFAULT_BOT_DOWNLOAD_BASE_URL.to_string(),
+            bot_versions: HashMap::new(),
+            default_bot_version: None,
+            url_fetcher: Arc::new(DefaultUrlFetcher),
         }
     }
 
@@ -49,8 +69,71 @@ impl AzaleaBotSpawner {
             bot_file_path,
             max_retries,
             retry_delay,
+            auto_download: true,
+            download_base_url: DEFAULT_BOT_DOWNLOAD_BASE_URL.to_string(),
+            bot_versions: HashMap::new(),
+            default_bot_version: None,
+            url_fetcher: Arc::new(DefaultUrlFetcher),
         }
     }
+
+    /// ボットの実行ファイルとチェックサムのダウンロード方法を差し替える
+    pub fn with_url_fetcher(mut self, url_fetcher: Arc<dyn UrlFetcher>) -> Self {
+        self.url_fetcher = url_fetcher;
+        self
+    }
+
+    /// `false` にすると `bot_file_path` が存在しない場合もダウンロードせずにエラーにする
+    pub fn with_auto_download(mut self, auto_download: bool) -> Self {
+        self.auto_download = auto_download;
+        self
+    }
+
+    /// ボットのダウンロード元を変更する。`{base}/v{ボットのバージョン}/{ファイル名}` から取得する
+    pub fn with_download_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.download_base_url = base_url.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    /// マインクラフトのバージョンに関わらず、指定したバージョンのボットを使う
+    pub fn with_bot_version(mut self, bot_version: impl Into<String>) -> Self {
+        self.default_bot_version = Some(bot_version.into());
+        self
+    }
+
+    /// 指定したマインクラフトのバージョンに対して使うボットのバージョンを指定する。
+    /// `with_bot_version` より優先される
+    pub fn with_bot_version_for(
+        mut self,
+        mc_version: impl Into<String>,
+        bot_version: impl Into<String>,
+    ) -> Self {
+        self.bot_versions.insert(mc_version.into(), bot_version.into());
+        self
+    }
+
+    /// マインクラフトのバージョンに対応するボットのバージョン。
+    /// 指定がなければマインクラフトのバージョンと同じものを使う
+    fn bot_version<'a>(&'a self, mc_version: &'a str) -> &'a str {
+        self.bot_versions
+            .get(mc_version)
+            .or(self.default_bot_version.as_ref())
+            .map(String::as_str)
+            .unwrap_or(mc_version)
+    }
+
+    fn bot_download_url(&self, mc_version: &str, os: &str, arch: &str) -> String {
+        let bot_version = self.bot_version(mc_version);
+        format!(
+            "{}/v{}/flex-update-mc-bot-{}-{}-{}{}",
+            self.download_base_url,
+            bot_version,
+            bot_version,
+            os,
+            arch,
+            if os == "windows" { ".exe" } else { "" }
+        )
+    }
 }
 
 #[derive(Deserialize)]
@@ -77,7 +160,16 @@ impl BotSpawner for AzaleaBotSpawner {
         
         let (child, mut lines, tx, rx) = loop {
             if !self.bot_file_path.exists() {
-                download_bot_executable(&self.bot_file_path, &version.id()).await?;
+                if !self.auto_download {
+                    return Err(anyhow!(
+                        "Bot executable not found: {}",
+                        self.bot_file_path.display()
+                    ));
+                }
+                let (os, arch) = get_os_and_arch()?;
+                let url = self.bot_download_url(&version.id(), &os, &arch);
+                download_bot_executable(self.url_fetcher.as_ref(), &self.bot_file_path, &url)
+                    .await?;
             }
             let mut command = std::process::Command::new(&self.bot_file_path);
 
@@ -230,39 +322,75 @@ impl Drop for AzaleaBotHandle {
     }
 }
 
-async fn download_bot_executable(bot_file_path: &PathBuf, version: &str) -> Result<()> {
-    let (os, arch) = get_os_and_arch()?;
-    let executable_name = format!(
-        "flex-update-mc-bot-{}-{}-{}{}",
-        version,
-        os,
-        arch,
-        if os == "windows" { ".exe" } else { "" }
-    );
-
-    let client = reqwest::Client::new();
-    let url = format!(
-        "https://github.com/txkodo/FlexUpdateMcBot/releases/download/v{}/{}",
-        version, executable_name
-    );
+// 実行ファイルをダウンロードし、リリースに添付された `.sha256` と一致する場合だけ書き込む。
+// `.sha256` が取得できない場合は警告して検証せずに書き込む
+async fn download_bot_executable(
+    url_fetcher: &dyn UrlFetcher,
+    bot_file_path: &PathBuf,
+    url: &str,
+) -> Result<()> {
     println!("Downloading bot executable from: {}", url);
 
-    let response = client.get(&url).send().await?;
-
-    if !response.status().is_success() {
-        return Err(anyhow!(
-            "Failed to download bot executable: HTTP {}",
-            response.status()
-        ));
+    let bytes = url_fetcher
+        .fetch_binary(&Url::parse(url)?)
+        .await
+        .map_err(|e| anyhow!("Failed to download bot executable: {}", e))?;
+
+    let checksum_url = format!("{}.sha256", url);
+    match url_fetcher.fetch_binary(&Url::parse(&checksum_url)?).await {
+        Ok(checksum) => {
+            let checksum = String::from_utf8_lossy(&checksum);
+            // `sha256sum` の出力形式（`<hash>  <file name>`）にも対応する
+            let expected = checksum
+                .split_whitespace()
+                .next()
+                .unwrap_or_default()
+                .to_ascii_lowercase();
+            let actual = Sha256::digest(&bytes)
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<String>();
+            if actual != expected {
+                // 以前の実行で残った不正なファイルも実行しないよう削除する
+                if bot_file_path.exists() {
+                    std::fs::remove_file(bot_file_path)?;
+                }
+                return Err(anyhow!(
+                    "Bot executable checksum mismatch: expected {}, got {} ({} bytes)",
+                    expected,
+                    actual,
+                    bytes.len()
+                ));
+            }
+        }
+        Err(e) => {
+            println!(
+                "Warning: Could not fetch {}, skipping checksum verification: {}",
+                checksum_url, e
+            );
+        }
     }
 
-    let bytes = response.bytes().await?;
     std::fs::write(bot_file_path, bytes)?;
+    make_executable(bot_file_path)?;
 
-    let mut perms = std::fs::metadata(bot_file_path)?.permissions();
+    Ok(())
+}
+
+// ダウンロードした実行ファイルに実行権限を付ける
+#[cfg(unix)]
+fn make_executable(path: &PathBuf) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut perms = std::fs::metadata(path)?.permissions();
     perms.set_mode(0o755);
-    std::fs::set_permissions(bot_file_path, perms)?;
+    std::fs::set_permissions(path, perms)?;
+    Ok(())
+}
 
+// Windowsでは `.exe` の拡張子で実行できるため何もしない
+#[cfg(not(unix))]
+fn make_executable(_path: &PathBuf) -> Result<()> {
     Ok(())
 }
 
@@ -282,3 +410,132 @@ fn get_os_and_arch() -> Result<(String, String)> {
 
     Ok((os.to_string(), arch.to_string()))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ssmc_core::infra::url_fetcher::DummyUrlFetcher;
+
+    #[tokio::test]
+    async fn test_spawn_bot_without_auto_download_requires_executable() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let bot_path = temp_dir.path().join("missing-bot");
+        let spawner = AzaleaBotSpawner::new(bot_path.clone()).with_auto_download(false);
+
+        let result = spawner
+            .spawn_bot(
+                &[127, 0, 0, 1].into(),
+                25565,
+                &McVanillaVersionId::new("1.20.1".to_string()),
+                "bot00",
+            )
+            .await;
+
+        let err = result.err().unwrap().to_string();
+        assert!(err.contains("Bot executable not found"), "{}", err);
+        assert!(!bot_path.exists());
+    }
+
+    const BOT_URL: &str = "https://example.com/v1.20.1/flex-update-mc-bot-1.20.1-linux-x64";
+
+    #[tokio::test]
+    async fn test_download_bot_executable_verifies_checksum() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let bot_path = temp_dir.path().join("bot");
+        let mut url_fetcher = DummyUrlFetcher::new();
+        url_fetcher.add_data(Url::parse(BOT_URL).unwrap(), b"bot binary".to_vec());
+        let checksum = Sha256::digest(b"bot binary")
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>();
+        url_fetcher.add_data(
+            Url::parse(&format!("{}.sha256", BOT_URL)).unwrap(),
+            format!("{}  flex-update-mc-bot-1.20.1-linux-x64\n", checksum),
+        );
+
+        download_bot_executable(&url_fetcher, &bot_path, BOT_URL)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read(&bot_path).unwrap(), b"bot binary");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_download_bot_executable_is_executable() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let bot_path = temp_dir.path().join("bot");
+        let mut url_fetcher = DummyUrlFetcher::new();
+        url_fetcher.add_data(Url::parse(BOT_URL).unwrap(), b"bot binary".to_vec());
+
+        download_bot_executable(&url_fetcher, &bot_path, BOT_URL)
+            .await
+            .unwrap();
+        let mode = std::fs::metadata(&bot_path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o755);
+    }
+
+    #[tokio::test]
+    async fn test_download_bot_executable_checksum_mismatch() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let bot_path = temp_dir.path().join("bot");
+        // 以前の実行で残った壊れたファイル
+        std::fs::write(&bot_path, b"partial").unwrap();
+        let mut url_fetcher = DummyUrlFetcher::new();
+        url_fetcher.add_data(Url::parse(BOT_URL).unwrap(), b"poisoned binary".to_vec());
+        url_fetcher.add_data(
+            Url::parse(&format!("{}.sha256", BOT_URL)).unwrap(),
+            "0000000000000000000000000000000000000000000000000000000000000000",
+        );
+
+        let err = download_bot_executable(&url_fetcher, &bot_path, BOT_URL)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("checksum mismatch"), "{}", err);
+        assert!(!bot_path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_download_bot_executable_without_checksum() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let bot_path = temp_dir.path().join("bot");
+        let mut url_fetcher = DummyUrlFetcher::new();
+        url_fetcher.add_data(Url::parse(BOT_URL).unwrap(), b"bot binary".to_vec());
+
+        download_bot_executable(&url_fetcher, &bot_path, BOT_URL)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read(&bot_path).unwrap(), b"bot binary");
+    }
+
+    #[test]
+    fn test_bot_download_url_defaults_to_minecraft_version() {
+        let spawner = AzaleaBotSpawner::new(PathBuf::from("bot"));
+        assert_eq!(
+            spawner.bot_download_url("1.20.1", "linux", "x64"),
+            "https://github.com/txkodo/FlexUpdateMcBot/releases/download/v1.20.1/flex-update-mc-bot-1.20.1-linux-x64"
+        );
+        assert_eq!(
+            spawner.bot_download_url("1.20.1", "windows", "x64"),
+            "https://github.com/txkodo/FlexUpdateMcBot/releases/download/v1.20.1/flex-update-mc-bot-1.20.1-windows-x64.exe"
+        );
+    }
+
+    #[test]
+    fn test_bot_download_url_custom_base_and_pinned_version() {
+        let spawner = AzaleaBotSpawner::new(PathBuf::from("bot"))
+            .with_download_base_url("https://mirror.example.com/bots/")
+            .with_bot_version("0.3.0")
+            .with_bot_version_for("1.21.4", "0.4.0");
+
+        assert_eq!(
+            spawner.bot_download_url("1.20.1", "linux", "arm64"),
+            "https://mirror.example.com/bots/v0.3.0/flex-update-mc-bot-0.3.0-linux-arm64"
+        );
+        assert_eq!(
+            spawner.bot_download_url("1.21.4", "macos", "arm64"),
+            "https://mirror.example.com/bots/v0.4.0/flex-update-mc-bot-0.4.0-macos-arm64"
+        );
+    }
+}
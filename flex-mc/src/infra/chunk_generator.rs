@@ -1,6 +1,5 @@
 use anyhow::Result;
 use java_properties;
-use rand::{SeedableRng, rngs::StdRng, seq::IteratorRandom};
 use ssmc_core::{
     domain::{McServerLoader, McVanillaVersionId, ServerRunOptions},
     infra::{
@@ -10,24 +9,28 @@ use ssmc_core::{
     util::file_trie::{Dir, Entry, File, Path as VirtualPath},
 };
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{HashMap, HashSet, VecDeque},
     io::Cursor,
     num::NonZeroUsize,
     path::PathBuf,
-    sync::Arc,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
     time::{Duration, Instant},
     vec,
 };
 use tokio::{
-    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
-    process::{ChildStdin, Command},
-    sync::{Mutex, mpsc},
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt},
+    sync::{broadcast, mpsc},
 };
 
 use crate::infra::{
-    bot_spawner::BotSpawner, free_port_finder::FreePortFinder, region_loader::ChunkPos,
+    bot_spawner::BotSpawner,
+    free_port_finder::FreePortFinder,
+    region_loader::{ChunkPos, Dimension, McDimension, chunks_in_area},
+    server_process::{ServerLauncher, ServerProcess, TokioServerLauncher},
 };
-use futures::future;
 
 // ヘルパー関数：ファイルの存在確認
 fn file_exists(dir: &Dir, path: &VirtualPath) -> bool {
@@ -45,23 +48,602 @@ fn write_file_content(dir: &mut Dir, path: &VirtualPath, data: &[u8]) -> Result<
     Ok(())
 }
 
+// ヘルパー関数：server.propertiesを読み込む。
+// マインクラフトはISO-8859-1とユニコードエスケープで書き込むが、エディタで保存したファイルは
+// BOM付きのUTF-8のことがあるため、BOMを取り除き、UTF-8として読めるものはUTF-8として解析する
+fn read_server_properties(data: &[u8]) -> Result<HashMap<String, String>> {
+    let data = data.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(data);
+    let encoding = if std::str::from_utf8(data).is_ok() {
+        encoding_rs::UTF_8
+    } else {
+        encoding_rs::WINDOWS_1252
+    };
+    let mut props = HashMap::new();
+    java_properties::PropertiesIter::new_with_encoding(Cursor::new(data), encoding).read_into(
+        |key, value| {
+            props.insert(key, value);
+        },
+    )?;
+    Ok(props)
+}
+
+// ヘルパー関数：サーバー出力を1行読み込む（UTF-8として不正なバイトは置換する）
+async fn read_lossy_line<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    buf: &mut Vec<u8>,
+) -> Result<Option<String>> {
+    buf.clear();
+    if reader.read_until(b'\n', buf).await? == 0 {
+        return Ok(None);
+    }
+    let line = String::from_utf8_lossy(buf);
+    Ok(Some(line.trim_end_matches(['\r', '\n']).to_string()))
+}
+
+/// サーバーの出力1行ごとに呼ばれるコールバック
+pub type ServerLogCallback = Arc<dyn Fn(&str) + Send + Sync>;
+
+// 保持するサーバー出力の行数
+const SERVER_LOG_CAPACITY: usize = 200;
+
+/// サーバーの直近の出力。起動失敗やクラッシュの原因調査に使う
+pub struct ServerLog {
+    lines: std::sync::Mutex<VecDeque<String>>,
+    capacity: usize,
+    on_line: Option<ServerLogCallback>,
+    // コマンドの応答を待つ購読者に新しい行を配る
+    subscribers: broadcast::Sender<String>,
+}
+
+impl ServerLog {
+    pub fn new(capacity: usize, on_line: Option<ServerLogCallback>) -> Self {
+        ServerLog {
+            lines: std::sync::Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+            on_line,
+            subscribers: broadcast::channel(capacity.max(1)).0,
+        }
+    }
+
+    fn push(&self, line: &str) {
+        if let Some(on_line) = &self.on_line {
+            on_line(line);
+        }
+        // 購読者がいなければ送信に失敗するが、記録は続ける
+        let _ = self.subscribers.send(line.to_string());
+        let mut lines = self.lines.lock().unwrap();
+        if lines.len() == self.capacity {
+            lines.pop_front();
+        }
+        lines.push_back(line.to_string());
+    }
+
+    /// これ以降に出力される行を受け取る
+    pub fn subscribe(&self) -> broadcast::Receiver<String> {
+        self.subscribers.subscribe()
+    }
+
+    /// 直近の出力を古い順に返す
+    pub fn recent_logs(&self) -> Vec<String> {
+        self.lines.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+// ヘルパー関数：サーバーの出力が閉じられるまで読み続けて記録する。
+// 読まれない出力でパイプが詰まり、サーバーが止まるのも防ぐ
+fn spawn_log_reader<R: AsyncBufRead + Unpin + Send + 'static>(
+    mut reader: R,
+    log: Arc<ServerLog>,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut buf = Vec::new();
+        while let Ok(Some(line)) = read_lossy_line(&mut reader, &mut buf).await {
+            log.push(&line);
+        }
+    })
+}
+
+// ヘルパー関数：サーバーの起動完了メッセージが出力されるまで待機
+async fn wait_for_server_ready<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    log: &ServerLog,
+) -> Result<()> {
+    let mut buf = Vec::new();
+    while let Some(line) = read_lossy_line(reader, &mut buf).await? {
+        log.push(&line);
+        if line.ends_with("For help, type \"help\"") {
+            return Ok(());
+        }
+    }
+    anyhow::bail!("Server output closed before the server became ready")
+}
+
+// 地形の生成結果が再現可能になるよう、時間・天候・ランダムティック・モブのスポーンを固定するコマンド
+const FREEZE_GAMEPLAY_COMMANDS: &[&str] = &[
+    "gamerule doDaylightCycle false",
+    "gamerule doWeatherCycle false",
+    "gamerule randomTickSpeed 0",
+    "gamerule doMobSpawning false",
+    "time set 6000",
+    "weather clear",
+];
+
+// ヘルパー関数：サーバーにコマンドを1行ずつ送信
+async fn send_commands<W: AsyncWrite + Unpin>(stdin: &mut W, commands: &[&str]) -> Result<()> {
+    for command in commands {
+        stdin.write_all(format!("{}\n", command).as_bytes()).await?;
+    }
+    stdin.flush().await?;
+    Ok(())
+}
+
+// ヘルパー関数：`list` コマンドの応答行からプレイヤー名を取り出す。
+// 応答は `There are 2 of a max of 20 players online: bot00, bot01` の形式で、応答行でなければ `None` を返す
+fn parse_player_list(line: &str) -> Option<Vec<String>> {
+    let rest = &line[line.find("There are ")? + "There are ".len()..];
+    let (count, rest) = rest.split_once(' ')?;
+    // 古いバージョンは `There are 1/20 players online:` の形式
+    let count: usize = count.split('/').next()?.parse().ok()?;
+    let (_, names) = rest.split_once("players online:")?;
+    let names: Vec<String> = names
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect();
+    (names.len() == count).then_some(names)
+}
+
+// ヘルパー関数：`list` コマンドを送り、サーバーに接続中のプレイヤー名を返す。
+// サーバーの出力は `spawn_log_reader` が読むため、応答は `ServerLog` から受け取る
+async fn query_players(
+    commands: &CommandSender,
+    log: &ServerLog,
+    timeout: Duration,
+) -> Result<Vec<String>> {
+    // 応答を取りこぼさないよう、送信する前に購読しておく
+    let mut lines = log.subscribe();
+    commands.send("list").await?;
+    tokio::time::timeout(timeout, async {
+        loop {
+            match lines.recv().await {
+                Ok(line) => {
+                    if let Some(players) = parse_player_list(&line) {
+                        return Ok(players);
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => {
+                    anyhow::bail!("Server output closed before the player list was received")
+                }
+            }
+        }
+    })
+    .await
+    .map_err(|_| anyhow::anyhow!("Server did not answer `list` within {:?}", timeout))?
+}
+
+// 書き込み待ちのコマンドの上限。超えると送信側が待たされる
+const COMMAND_QUEUE_CAPACITY: usize = 64;
+
+/// サーバーの標準入力にコマンドを送る。
+/// コマンドは1つのタスクが受け取った順に1行ずつ書き込むため、複数のボットから送っても行が混ざらない
+#[derive(Clone)]
+pub struct CommandSender {
+    tx: mpsc::Sender<String>,
+}
+
+impl CommandSender {
+    /// 標準入力に書き込むタスクを起動する。タスクはすべての `CommandSender` が破棄されると終了する
+    pub fn spawn<W: AsyncWrite + Unpin + Send + 'static>(
+        mut stdin: W,
+    ) -> (Self, tokio::task::JoinHandle<Result<()>>) {
+        let (tx, mut rx) = mpsc::channel::<String>(COMMAND_QUEUE_CAPACITY);
+        let writer = tokio::spawn(async move {
+            while let Some(command) = rx.recv().await {
+                stdin.write_all(format!("{}\n", command).as_bytes()).await?;
+                stdin.flush().await?;
+            }
+            Ok(())
+        });
+        (CommandSender { tx }, writer)
+    }
+
+    /// コマンドを書き込み待ちに追加する
+    pub async fn send(&self, command: impl Into<String>) -> Result<()> {
+        self.tx
+            .send(command.into())
+            .await
+            .map_err(|_| anyhow::anyhow!("Server stdin writer has stopped"))
+    }
+}
+
+// ヘルパー関数：サーバーの起動完了を待ってから起動時のコマンドを送信。
+// `timeout` 以内に起動が完了しなければエラーにする
+async fn start_up_server<R: AsyncBufRead + Unpin, W: AsyncWrite + Unpin>(
+    stdout: &mut R,
+    stdin: &mut W,
+    commands: &[&str],
+    timeout: Duration,
+    log: &ServerLog,
+) -> Result<()> {
+    tokio::time::timeout(timeout, wait_for_server_ready(stdout, log))
+        .await
+        .map_err(|_| anyhow::anyhow!("Server did not become ready within {:?}", timeout))??;
+    send_commands(stdin, commands).await
+}
+
+// 未生成チャンクの作業キュー。
+// ボットは未生成チャンクを1つずつ確保し、他のボットが確保中のチャンクからは離れたチャンクを優先して選ぶ。
+// ボットから報告済みのチャンクは別に記録し、重複した報告を読み飛ばす
+struct PendingChunks {
+    queue: Vec<ChunkPos>,
+    pending: HashSet<ChunkPos>,
+    claimed: HashSet<ChunkPos>,
+    confirmed: HashSet<ChunkPos>,
+    spread: isize,
+}
+
+impl PendingChunks {
+    // spread: 他のボットの確保チャンクからこの距離以内のチャンクは後回しにする
+    fn new(chunks: impl IntoIterator<Item = ChunkPos>, spread: isize) -> Self {
+        let mut pending = HashSet::new();
+        let queue = chunks
+            .into_iter()
+            .filter(|pos| pending.insert(*pos))
+            .collect();
+        PendingChunks {
+            queue,
+            pending,
+            claimed: HashSet::new(),
+            confirmed: HashSet::new(),
+            spread,
+        }
+    }
+
+    // 初めて報告された未生成チャンクであれば残りのチャンク数を返す
+    fn confirm(&mut self, pos: ChunkPos) -> Option<usize> {
+        if !self.confirmed.insert(pos) {
+            return None;
+        }
+        if self.pending.remove(&pos) {
+            Some(self.pending.len())
+        } else {
+            None
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    // 他のボットが確保していない未生成チャンクを確保する
+    fn claim(&mut self) -> Option<ChunkPos> {
+        let pending = &self.pending;
+        self.queue.retain(|pos| pending.contains(pos));
+
+        let is_far = |pos: &ChunkPos| {
+            self.claimed.iter().all(|other| {
+                (pos.x - other.x).abs() > self.spread || (pos.z - other.z).abs() > self.spread
+            })
+        };
+        let idx = self
+            .queue
+            .iter()
+            .position(is_far)
+            .or_else(|| (!self.queue.is_empty()).then_some(0))?;
+
+        let pos = self.queue.remove(idx);
+        self.claimed.insert(pos);
+        Some(pos)
+    }
+
+    // 確保を解除する。未生成のままであれば再びキューに戻す
+    fn release(&mut self, pos: ChunkPos) {
+        if self.claimed.remove(&pos) && self.pending.contains(&pos) {
+            self.queue.push(pos);
+        }
+    }
+}
+
+/// チャンクが1つ生成されたことを表す進捗
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkGenerated {
+    pub dimension: McDimension,
+    /// すべてのディメンションで残っている未生成チャンクの数
+    pub remaining_total: usize,
+    /// すべてのディメンションで生成済みのチャンクの数
+    pub generated: usize,
+    /// すべてのディメンションで生成するチャンクの数
+    pub total: usize,
+    /// 生成を始めてからの経過時間
+    pub elapsed: Duration,
+}
+
+// ディメンションごとの未生成チャンクの数を合計し、全体の進捗として通知する
+struct GenerationProgress {
+    remaining_total: std::sync::Mutex<usize>,
+    total: AtomicUsize,
+    started: Instant,
+    events: Option<mpsc::UnboundedSender<ChunkGenerated>>,
+}
+
+impl GenerationProgress {
+    fn new(events: Option<mpsc::UnboundedSender<ChunkGenerated>>) -> Self {
+        GenerationProgress {
+            remaining_total: std::sync::Mutex::new(0),
+            total: AtomicUsize::new(0),
+            started: Instant::now(),
+            events,
+        }
+    }
+
+    // ディメンションの未生成チャンクを追加する。ボットが生成を始める前に呼び出す
+    fn add_dimension(
+        self: &Arc<Self>,
+        dimension: McDimension,
+        chunks: impl IntoIterator<Item = ChunkPos>,
+        spread: isize,
+    ) -> Arc<DimensionChunks> {
+        let chunks = PendingChunks::new(chunks, spread);
+        *self.remaining_total.lock().unwrap() += chunks.pending.len();
+        self.total.fetch_add(chunks.pending.len(), Ordering::SeqCst);
+        Arc::new(DimensionChunks {
+            dimension,
+            chunks: std::sync::Mutex::new(chunks),
+            progress: self.clone(),
+        })
+    }
+
+    fn chunk_generated(&self, dimension: &McDimension) -> ChunkGenerated {
+        // 通知の順序が残りのチャンク数の順序と一致するよう、ロックしたまま通知する
+        let mut remaining_total = self.remaining_total.lock().unwrap();
+        *remaining_total -= 1;
+        let total = self.total.load(Ordering::SeqCst);
+        let event = ChunkGenerated {
+            dimension: dimension.clone(),
+            remaining_total: *remaining_total,
+            generated: total - *remaining_total,
+            total,
+            elapsed: self.started.elapsed(),
+        };
+        if let Some(events) = &self.events {
+            // 受信側が閉じていても生成は続ける
+            let _ = events.send(event.clone());
+        }
+        event
+    }
+}
+
+// 1つのディメンションの未生成チャンク
+struct DimensionChunks {
+    dimension: McDimension,
+    chunks: std::sync::Mutex<PendingChunks>,
+    progress: Arc<GenerationProgress>,
+}
+
+impl DimensionChunks {
+    // 初めて報告された未生成チャンクであれば全体の進捗を返す
+    fn confirm(&self, pos: ChunkPos) -> Option<ChunkGenerated> {
+        self.chunks.lock().unwrap().confirm(pos)?;
+        Some(self.progress.chunk_generated(&self.dimension))
+    }
+
+    // ボットをディメンション内のチャンクにテレポートするコマンド
+    fn teleport_command(&self, bot_id: &str, pos: ChunkPos) -> String {
+        let (block_x, block_z) = pos.center_block();
+        let tp = format!("tp {} {} 100 {}", bot_id, block_x, block_z);
+        match self.dimension {
+            McDimension::Overworld => tp,
+            _ => {
+                let (namespace, path) = self.dimension.namespaced_id();
+                format!("execute in {}:{} run {}", namespace, path, tp)
+            }
+        }
+    }
+}
+
 #[async_trait::async_trait]
 pub trait ChunkGenerator {
+    /// `dimension` 内のチャンクを生成する
+    async fn generate_chunks_in(
+        &self,
+        world_data: Dir,
+        version: &McVanillaVersionId,
+        dimension: &McDimension,
+        chunk_list: &[ChunkPos],
+    ) -> Result<Option<RunningServer>>;
+
+    /// オーバーワールドのチャンクを生成する
     async fn generate_chunks(
         &self,
         world_data: Dir,
         version: &McVanillaVersionId,
         chunk_list: &[ChunkPos],
-    ) -> Result<()>;
+    ) -> Result<Option<RunningServer>>
+    where
+        Self: Sync,
+    {
+        self.generate_chunks_in(world_data, version, &McDimension::Overworld, chunk_list)
+            .await
+    }
+
+    /// 2つの角のチャンクを含む矩形範囲のうち、`dimension` にまだ存在しないチャンクだけを生成する。
+    /// 不足するチャンクがなければサーバーを起動しない
+    async fn generate_missing_chunks(
+        &self,
+        world_data: Dir,
+        version: &McVanillaVersionId,
+        dimension: &Dimension,
+        corner1: ChunkPos,
+        corner2: ChunkPos,
+    ) -> Result<Option<RunningServer>>
+    where
+        Self: Sync,
+    {
+        let missing = dimension.missing_chunks(&chunks_in_area(corner1, corner2))?;
+        if missing.is_empty() {
+            return Ok(None);
+        }
+        self.generate_chunks_in(world_data, version, dimension.kind(), &missing)
+            .await
+    }
+}
+
+/// 生成されたチャンクを読み込めるか検査する
+pub trait ChunkVerifier: Send + Sync {
+    /// 要求されたチャンクのうち、存在しないか読み込めないものを返す
+    fn unreadable_chunks(&self, chunks: &[ChunkPos]) -> Result<Vec<ChunkPos>>;
+}
+
+impl ChunkVerifier for Dimension {
+    fn unreadable_chunks(&self, chunks: &[ChunkPos]) -> Result<Vec<ChunkPos>> {
+        Dimension::unreadable_chunks(self, chunks)
+    }
+}
+
+/// チャンクを生成した後にすべて読み込めるか検査し、読み込めないチャンクを再生成する。
+/// 検査はリージョンファイルが書き出された後に行う必要があるため、サーバーは毎回停止する。
+/// `max_rounds` 回生成しても読み込めないチャンクが残ればエラーにする
+pub async fn generate_chunks_verified(
+    generator: &(dyn ChunkGenerator + Sync),
+    world_data: Dir,
+    version: &McVanillaVersionId,
+    chunk_list: &[ChunkPos],
+    verifier: &dyn ChunkVerifier,
+    max_rounds: usize,
+) -> Result<()> {
+    let mut remaining = chunk_list.to_vec();
+    for round in 1..=max_rounds {
+        if let Some(server) = generator
+            .generate_chunks(world_data.clone(), version, &remaining)
+            .await?
+        {
+            server.stop(DEFAULT_STOP_TIMEOUT).await?;
+        }
+        remaining = verifier.unreadable_chunks(&remaining)?;
+        if remaining.is_empty() {
+            return Ok(());
+        }
+        println!(
+            "{} chunks could not be loaded after round {}/{}",
+            remaining.len(),
+            round,
+            max_rounds
+        );
+    }
+    anyhow::bail!(
+        "{} chunks could not be loaded after {} rounds: {:?}",
+        remaining.len(),
+        max_rounds,
+        remaining
+    )
+}
+
+/// 生成の完了後も起動したままのサーバー。
+/// `stop` を呼ばずに破棄するとプロセスは強制終了される
+pub struct RunningServer {
+    commands: CommandSender,
+    writer: tokio::task::JoinHandle<Result<()>>,
+    process: Box<dyn ServerProcess>,
+    log: Arc<ServerLog>,
+}
+
+impl RunningServer {
+    /// サーバーの直近の出力
+    pub fn recent_logs(&self) -> Vec<String> {
+        self.log.recent_logs()
+    }
+
+    /// `list` コマンドを送り、サーバーに接続中のプレイヤー名を返す。
+    /// ボットが実際にサーバーに参加しているかの確認に使う
+    pub async fn query_players(&self, timeout: Duration) -> Result<Vec<String>> {
+        query_players(&self.commands, &self.log, timeout).await
+    }
+
+    /// サーバーにコマンドを送る
+    pub async fn send_command(&self, command: impl Into<String>) -> Result<()> {
+        self.commands.send(command).await
+    }
+
+    /// サーバーに `stop` を送って終了を待つ。`timeout` 以内に終了しなければプロセスを強制終了する
+    pub async fn stop(self, timeout: Duration) -> Result<ServerShutdown> {
+        let RunningServer {
+            commands,
+            writer,
+            mut process,
+            ..
+        } = self;
+        commands.send("stop").await?;
+        drop(commands);
+        let graceful = tokio::time::timeout(timeout, async {
+            writer.await??;
+            process.wait().await
+        })
+        .await;
+        match graceful {
+            Ok(result) => {
+                result?;
+                Ok(ServerShutdown::Graceful)
+            }
+            Err(_) => {
+                println!("Server did not stop within {:?}, killing it", timeout);
+                process.kill().await?;
+                Ok(ServerShutdown::Forced)
+            }
+        }
+    }
 }
 
+/// サーバーの停止方法
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServerShutdown {
+    /// `stop` コマンドで終了した
+    Graceful,
+    /// 時間内に終了しなかったため強制終了した
+    Forced,
+}
+
+/// サーバーの終了を待つ時間のデフォルト
+pub const DEFAULT_STOP_TIMEOUT: Duration = Duration::from_secs(60);
+
+// 生成の完了後、keep_aliveであればサーバーを起動したまま返し、そうでなければ停止する
+async fn complete_generation(
+    server: RunningServer,
+    keep_alive: bool,
+    stop_timeout: Duration,
+) -> Result<Option<RunningServer>> {
+    if keep_alive {
+        return Ok(Some(server));
+    }
+    server.stop(stop_timeout).await?;
+    Ok(None)
+}
+
+// サーバーが受け付ける描画距離の範囲
+const MIN_VIEW_DISTANCE: u8 = 2;
+const MAX_VIEW_DISTANCE: u8 = 32;
+
 pub struct DefaultChunkGenerator {
     version_loader: VanillaVersionLoader,
     bot_spawner: Arc<dyn BotSpawner + Send + Sync>,
     free_port_finder: Box<dyn FreePortFinder + Send + Sync>,
     trie_loader: Arc<dyn TrieLoader + Send + Sync>,
+    server_launcher: Arc<dyn ServerLauncher>,
     work_dir: PathBuf,
     max_bot_count: NonZeroUsize,
+    view_distance: u8,
+    chunk_wait: Duration,
+    first_chunk_timeout: Duration,
+    max_stalled_teleports: usize,
+    freeze_gameplay: bool,
+    progress_events: Option<mpsc::UnboundedSender<ChunkGenerated>>,
+    keep_alive_on_completion: bool,
+    startup_timeout: Duration,
+    server_log_callback: Option<ServerLogCallback>,
+    stop_timeout: Duration,
+    cancel: Arc<AtomicBool>,
+    startup_commands: Vec<String>,
 }
 
 impl DefaultChunkGenerator {
@@ -78,23 +660,125 @@ impl DefaultChunkGenerator {
             bot_spawner,
             free_port_finder,
             trie_loader,
+            server_launcher: Arc::new(TokioServerLauncher),
             work_dir,
             max_bot_count,
+            view_distance: 5,
+            chunk_wait: Duration::from_secs(5),
+            first_chunk_timeout: Duration::from_secs(60),
+            max_stalled_teleports: 3,
+            freeze_gameplay: true,
+            progress_events: None,
+            keep_alive_on_completion: false,
+            startup_timeout: Duration::from_secs(300),
+            server_log_callback: None,
+            stop_timeout: DEFAULT_STOP_TIMEOUT,
+            cancel: Arc::new(AtomicBool::new(false)),
+            startup_commands: Vec::new(),
         }
     }
+
+    /// サーバーの起動方法を差し替える
+    pub fn with_server_launcher(mut self, server_launcher: Arc<dyn ServerLauncher>) -> Self {
+        self.server_launcher = server_launcher;
+        self
+    }
+
+    /// サーバーの描画距離（デフォルトは5）。ボットを中心に `(2 * view_distance + 1)^2` チャンクが生成される。
+    /// マインクラフトが受け付ける2から32の範囲外であれば `generate_chunks` がエラーを返す
+    pub fn with_view_distance(mut self, view_distance: u8) -> Self {
+        self.view_distance = view_distance;
+        self
+    }
+
+    /// ボットが各チャンクに留まって周囲のチャンクの生成を待つ時間（デフォルトは5秒）
+    pub fn with_chunk_wait(mut self, chunk_wait: Duration) -> Self {
+        self.chunk_wait = chunk_wait;
+        self
+    }
+
+    /// ボットが接続してから最初のチャンクを受信するまで待つ最大時間（デフォルトは60秒）。
+    /// 超えた場合はボットがこのバージョンに対応していないとみなしてエラーにする
+    pub fn with_first_chunk_timeout(mut self, first_chunk_timeout: Duration) -> Self {
+        self.first_chunk_timeout = first_chunk_timeout;
+        self
+    }
+
+    /// チャンクを受信しないテレポートがこの回数続いたボットは停止し、残りのボットに任せる（デフォルトは3回）
+    pub fn with_max_stalled_teleports(mut self, max_stalled_teleports: usize) -> Self {
+        self.max_stalled_teleports = max_stalled_teleports;
+        self
+    }
+
+    /// 生成中に時間・天候・ランダムティック・モブのスポーンを固定するかどうか（デフォルトは固定する）
+    pub fn with_freeze_gameplay(mut self, freeze_gameplay: bool) -> Self {
+        self.freeze_gameplay = freeze_gameplay;
+        self
+    }
+
+    /// チャンクが生成されるたびに進捗を送信する
+    pub fn with_progress_events(mut self, events: mpsc::UnboundedSender<ChunkGenerated>) -> Self {
+        self.progress_events = Some(events);
+        self
+    }
+
+    /// 生成の完了後もサーバーを停止せず、接続して調査できるようにする。
+    /// 起動したままのサーバーは `generate_chunks` の戻り値から停止できる
+    pub fn with_keep_alive_on_completion(mut self, keep_alive_on_completion: bool) -> Self {
+        self.keep_alive_on_completion = keep_alive_on_completion;
+        self
+    }
+
+    /// サーバーの起動完了を待つ最大時間（デフォルトは5分）。超えた場合はサーバーを停止してエラーにする
+    pub fn with_startup_timeout(mut self, startup_timeout: Duration) -> Self {
+        self.startup_timeout = startup_timeout;
+        self
+    }
+
+    /// サーバーの出力を1行ずつ受け取る
+    pub fn with_server_log_callback(mut self, callback: ServerLogCallback) -> Self {
+        self.server_log_callback = Some(callback);
+        self
+    }
+
+    /// 生成の完了後、サーバーの終了を待つ最大時間（デフォルトは60秒）。超えた場合は強制終了する
+    pub fn with_stop_timeout(mut self, stop_timeout: Duration) -> Self {
+        self.stop_timeout = stop_timeout;
+        self
+    }
+
+    /// `true` にすると生成を中断するフラグ。中断時はボットとサーバーを停止してエラーを返す
+    pub fn with_cancel_flag(mut self, cancel: Arc<AtomicBool>) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// サーバーの起動完了後、ボットの接続前に実行するコマンド（`datapack enable ...` など）
+    pub fn with_startup_commands(mut self, startup_commands: Vec<String>) -> Self {
+        self.startup_commands = startup_commands;
+        self
+    }
 }
 
 #[async_trait::async_trait]
 impl ChunkGenerator for DefaultChunkGenerator {
-    async fn generate_chunks(
+    async fn generate_chunks_in(
         &self,
         mut world_data: Dir,
         version: &McVanillaVersionId,
+        dimension: &McDimension,
         chunk_list: &[ChunkPos],
-    ) -> Result<()> {
-        // ボットを中心に 21 x 21 チャンクが生成される
-        let view_distance = 5;
-        let bot_count = 3;
+    ) -> Result<Option<RunningServer>> {
+        let view_distance = self.view_distance;
+        if !(MIN_VIEW_DISTANCE..=MAX_VIEW_DISTANCE).contains(&view_distance) {
+            anyhow::bail!(
+                "View distance must be between {} and {}, got {}",
+                MIN_VIEW_DISTANCE,
+                MAX_VIEW_DISTANCE,
+                view_distance
+            );
+        }
+        let bot_count = self.max_bot_count.get();
 
         let (new_world_data, command) = {
             let (new_world_data, command_factory) = self
@@ -104,6 +788,7 @@ impl ChunkGenerator for DefaultChunkGenerator {
                     &McVanillaVersion {
                         version: McVanillaVersionId::new(version.id().to_string()),
                         version_type: McVanillaVersionType::Release,
+                        release_time: None,
                     },
                 )
                 .await
@@ -115,7 +800,9 @@ impl ChunkGenerator for DefaultChunkGenerator {
         world_data = new_world_data;
 
         let host = [127, 0, 0, 1].into();
-        let port = self.free_port_finder.find_free_port(host)?;
+        // 他のサーバーに同じポートを使われないよう、サーバーが起動を終えて待ち受けを始めるまで予約しておく
+        let port_reservation = self.free_port_finder.reserve_free_port(host)?;
+        let port = port_reservation.port();
         {
             let properties_path = VirtualPath::from_str("server.properties");
             let mut props = {
@@ -128,7 +815,7 @@ impl ChunkGenerator for DefaultChunkGenerator {
                                 .unwrap_or(&File::inline(vec![], 0o644)),
                         )
                         .await?;
-                    java_properties::read(Cursor::new(properties))?
+                    read_server_properties(&properties)?
                 } else {
                     HashMap::new()
                 }
@@ -160,118 +847,194 @@ impl ChunkGenerator for DefaultChunkGenerator {
 
         println!("Starting server at {:?}", &tmpdir);
         println!("Starting server at {:?}", &command);
-        let mut child = Command::from(command)
-            .current_dir(&tmpdir)
-            .stdin(std::process::Stdio::piped())
-            .stdout(std::process::Stdio::piped())
-            .spawn()?;
-        let stdin = child.stdin.take().unwrap();
-
-        let stdout = child.stdout.take().unwrap();
-        let mut lines = BufReader::new(stdout).lines();
-        while let Some(line) = lines.next_line().await? {
-            if line.ends_with("For help, type \"help\"") {
-                break;
-            }
+        let mut process = self.server_launcher.start(command, &tmpdir).await?;
+        let mut stdin = process
+            .take_stdin()
+            .ok_or_else(|| anyhow::anyhow!("Failed to capture server stdin"))?;
+        let mut stdout = process
+            .take_stdout()
+            .ok_or_else(|| anyhow::anyhow!("Failed to capture server stdout"))?;
+        let mut startup_commands = if self.freeze_gameplay {
+            FREEZE_GAMEPLAY_COMMANDS.to_vec()
+        } else {
+            vec![]
+        };
+        startup_commands.extend(self.startup_commands.iter().map(String::as_str));
+        let log = Arc::new(ServerLog::new(
+            SERVER_LOG_CAPACITY,
+            self.server_log_callback.clone(),
+        ));
+        if let Err(e) = start_up_server(
+            &mut stdout,
+            &mut stdin,
+            &startup_commands,
+            self.startup_timeout,
+            &log,
+        )
+        .await
+        {
+            let _ = process.kill().await;
+            return Err(e.context(format!(
+                "Recent server output:\n{}",
+                log.recent_logs().join("\n")
+            )));
         }
+        port_reservation.release();
+        spawn_log_reader(stdout, log.clone());
 
-        let ungenarated_chunks = Arc::new(std::sync::Mutex::new(
-            chunk_list.iter().copied().collect::<HashSet<_>>(),
-        ));
-        let stdin_shared = Arc::new(Mutex::new(stdin));
+        let progress = Arc::new(GenerationProgress::new(self.progress_events.clone()));
+        let ungenarated_chunks = progress.add_dimension(
+            dimension.clone(),
+            chunk_list.iter().copied(),
+            view_distance as isize,
+        );
+        let (commands, writer) = CommandSender::spawn(stdin);
 
-        let bot_tasks = (0..bot_count).map(|idx| {
+        // 呼び出し元がこのfutureを破棄した場合も、JoinSetの破棄でボットのタスクは中断される
+        let mut bot_tasks = tokio::task::JoinSet::new();
+        for idx in 0..bot_count {
             let bot_id = format!("bot{:02}", idx);
             let bot_spawner = self.bot_spawner.clone();
             let version = version.clone();
-            let stdin_clone = stdin_shared.clone();
+            let commands = commands.clone();
             let host = host.clone();
             let port = port;
             let ungenarated_chunks = ungenarated_chunks.clone();
+            let settings = GenBotSettings {
+                chunk_wait: self.chunk_wait,
+                first_chunk_timeout: self.first_chunk_timeout,
+                max_stalled_teleports: self.max_stalled_teleports,
+            };
+            let cancel = self.cancel.clone();
 
-            tokio::spawn(async move {
+            bot_tasks.spawn(async move {
                 let (bot, rx) = bot_spawner
                     .spawn_bot(&host, port, &version, &bot_id)
                     .await?;
-                spawn_random_gen_bot(bot_id, ungenarated_chunks, rx, stdin_clone).await?;
+                // オフラインモードのボットはOPではないため、テレポートの前にOPにする
+                if let Err(e) = commands.send(format!("op {}", bot_id)).await {
+                    bot.stop()?;
+                    return Err(e);
+                }
+                let result =
+                    run_gen_bot(bot_id, ungenarated_chunks, rx, commands, settings, cancel).await;
+                // 失敗・中断した場合もボットは停止する
                 bot.stop()?;
-                anyhow::Ok(())
-            })
-        });
+                result
+            });
+        }
 
         // すべてのタスクの完了を待機
-
-        let results = future::join_all(bot_tasks).await;
-        for result in results {
-            result??
+        let mut results = Vec::new();
+        while let Some(result) = bot_tasks.join_next().await {
+            results.push(result);
         }
-
-        // サーバーを停止
-        {
-            let mut stdin_guard = stdin_shared.lock().await;
-            stdin_guard.write_all("stop\n".as_bytes()).await?;
-            stdin_guard.flush().await?;
+        let server = RunningServer {
+            commands,
+            writer,
+            process,
+            log,
+        };
+        if self.cancel.load(Ordering::SeqCst) {
+            server.stop(self.stop_timeout).await?;
+            anyhow::bail!("Chunk generation was cancelled");
+        }
+        let outcomes = results
+            .into_iter()
+            .map(|result| result?)
+            .collect::<Result<Vec<_>>>();
+        let outcomes = match outcomes {
+            Ok(outcomes) => outcomes,
+            Err(e) => {
+                // ボットが失敗してもサーバーを残さないよう、停止してからエラーを返す
+                if let Err(stop_error) = server.stop(self.stop_timeout).await {
+                    eprintln!("Failed to stop server: {}", stop_error);
+                }
+                return Err(e);
+            }
+        };
+        let stalled_bots = outcomes
+            .iter()
+            .filter(|outcome| **outcome == GenBotOutcome::Stalled)
+            .count();
+        if stalled_bots == bot_count {
+            server.stop(self.stop_timeout).await?;
+            anyhow::bail!(
+                "All {} bots stalled before all chunks were generated",
+                bot_count
+            );
         }
-        child.wait().await?;
 
-        Ok(())
+        complete_generation(server, self.keep_alive_on_completion, self.stop_timeout).await
     }
 }
 
-async fn spawn_random_gen_bot(
+// ボットの待ち時間の設定
+#[derive(Debug, Clone, Copy)]
+struct GenBotSettings {
+    // 各チャンクに留まって周囲のチャンクの生成を待つ時間
+    chunk_wait: Duration,
+    // 接続してから最初のチャンクを受信するまで待つ最大時間
+    first_chunk_timeout: Duration,
+    // チャンクを受信しないテレポートがこの回数続いたら、接続が固まったとみなす
+    max_stalled_teleports: usize,
+}
+
+// ボットが生成を終えた理由
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum GenBotOutcome {
+    // 未生成チャンクがなくなった、または中断された
+    Finished,
+    // チャンクを受信しなくなったため、残りを他のボットに任せた
+    Stalled,
+}
+
+async fn run_gen_bot(
     bot_id: String,
-    ungenarated_chunks: Arc<std::sync::Mutex<HashSet<ChunkPos>>>,
+    ungenarated_chunks: Arc<DimensionChunks>,
     mut rx: mpsc::Receiver<(i32, i32)>,
-    stdin_mutex: Arc<Mutex<ChildStdin>>,
-) -> anyhow::Result<()> {
-    let mut rng = StdRng::seed_from_u64(rand::random());
-
-    loop {
-        let random_chunk = {
-            match ungenarated_chunks.lock().unwrap().iter().choose(&mut rng) {
-                Some(chunk) => chunk.clone(),
-                None => break,
+    commands: CommandSender,
+    settings: GenBotSettings,
+    cancel: Arc<AtomicBool>,
+) -> anyhow::Result<GenBotOutcome> {
+    let duration = settings.chunk_wait;
+    let connected = Instant::now();
+    let mut received_any = false;
+    let mut stalled_teleports = 0;
+    while !cancel.load(Ordering::SeqCst) {
+        let target_chunk = {
+            let mut ungenarated_chunks = ungenarated_chunks.chunks.lock().unwrap();
+            if ungenarated_chunks.is_done() {
+                break;
             }
+            ungenarated_chunks.claim()
+        };
+        let Some(target_chunk) = target_chunk else {
+            // 残りのチャンクはすべて他のボットが確保中
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            continue;
         };
         // ボットをテレポート
-        {
-            let mut stdin = stdin_mutex.lock().await;
-            println!(
-                "tp {} {} 100 {}\n",
-                bot_id,
-                random_chunk.x * 16 + 8,
-                random_chunk.z * 16 + 8
-            );
-            stdin
-                .write_all(
-                    format!(
-                        "tp {} {} 100 {}\n",
-                        bot_id,
-                        random_chunk.x * 16 + 8,
-                        random_chunk.z * 16 + 8
-                    )
-                    .as_bytes(),
-                )
-                .await?;
-            stdin.flush().await?;
-        }
+        let command = ungenarated_chunks.teleport_command(&bot_id, target_chunk);
+        println!("{}", command);
+        commands.send(command).await?;
 
         let start = Instant::now();
-        let duration = Duration::from_secs(5);
-
-        while start.elapsed() < duration {
+        let mut received_in_window = false;
+        while start.elapsed() < duration && !cancel.load(Ordering::SeqCst) {
             let remaining = duration.saturating_sub(start.elapsed());
             match tokio::time::timeout(remaining.min(Duration::from_millis(500)), rx.recv()).await {
                 Ok(Some((x, z))) => {
-                    let mut ungenarated_chunks = ungenarated_chunks.lock().unwrap();
-                    ungenarated_chunks.remove(&ChunkPos::new(x as isize, z as isize));
-                    println!(
-                        "{} received chunk at ({}, {}) {}",
-                        bot_id,
-                        x,
-                        z,
-                        ungenarated_chunks.len()
-                    );
+                    received_any = true;
+                    received_in_window = true;
+                    let progress =
+                        ungenarated_chunks.confirm(ChunkPos::new(x as isize, z as isize));
+                    if let Some(progress) = progress {
+                        println!(
+                            "{} received chunk at ({}, {}) {}",
+                            bot_id, x, z, progress.remaining_total
+                        );
+                    }
                 }
                 Ok(None) => break, // channel closed
                 Err(_) => {
@@ -279,7 +1042,1384 @@ async fn spawn_random_gen_bot(
                 }
             }
         }
+        ungenarated_chunks
+            .chunks
+            .lock()
+            .unwrap()
+            .release(target_chunk);
+        if !received_any && connected.elapsed() >= settings.first_chunk_timeout {
+            anyhow::bail!(
+                "{} received no chunks within {:?} after connecting; \
+                 the bot build may be incompatible with this Minecraft version",
+                bot_id,
+                settings.first_chunk_timeout
+            );
+        }
+        // 一度はチャンクを受信したボットが受信しなくなった場合は、接続が固まったとみなす
+        if received_in_window || !received_any {
+            stalled_teleports = 0;
+        } else {
+            stalled_teleports += 1;
+            if stalled_teleports >= settings.max_stalled_teleports {
+                println!(
+                    "{} stalled after {} teleports without chunks",
+                    bot_id, stalled_teleports
+                );
+                return Ok(GenBotOutcome::Stalled);
+            }
+        }
     }
     println!("{} finished", bot_id,);
-    Ok::<(), anyhow::Error>(())
+    Ok(GenBotOutcome::Finished)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infra::{
+        bot_spawner::BotHandle, free_port_finder::DefaultFreePortFinder,
+        server_process::MockServerLauncher,
+    };
+    use ssmc_core::infra::{
+        fs_handler::{FsHandler, OnMemoryFsHandler},
+        mc_java::{JavaComponent, McJava, McJavaLoader},
+        trie_loader::DefaultTrieLoader,
+        url_fetcher::DummyUrlFetcher,
+    };
+    use tokio::io::BufReader;
+
+    #[tokio::test]
+    async fn test_wait_for_server_ready_with_invalid_utf8() {
+        let mut output = Vec::new();
+        output.extend_from_slice(b"[Server thread/INFO]: Preparing level \"world\"\r\n");
+        output.extend_from_slice(b"[Server thread/WARN]: \x82\xb1\x82\xf1\xff\xfe\r\n");
+        output
+            .extend_from_slice(b"[Server thread/INFO]: Done (1.234s)! For help, type \"help\"\r\n");
+        let mut reader = BufReader::new(Cursor::new(output));
+
+        assert!(
+            wait_for_server_ready(&mut reader, &ServerLog::new(10, None))
+                .await
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_start_up_server_freezes_gameplay_after_ready() {
+        let output = b"Loading libraries\nDone (2.0s)! For help, type \"help\"\n".to_vec();
+        let mut stdin = Vec::new();
+
+        start_up_server(
+            &mut BufReader::new(Cursor::new(output)),
+            &mut stdin,
+            FREEZE_GAMEPLAY_COMMANDS,
+            Duration::from_secs(1),
+            &ServerLog::new(10, None),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(stdin).unwrap(),
+            "gamerule doDaylightCycle false\n\
+             gamerule doWeatherCycle false\n\
+             gamerule randomTickSpeed 0\n\
+             gamerule doMobSpawning false\n\
+             time set 6000\n\
+             weather clear\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_start_up_server_sends_nothing_before_ready() {
+        let output = b"Loading libraries\n".to_vec();
+        let mut stdin = Vec::new();
+
+        let result = start_up_server(
+            &mut BufReader::new(Cursor::new(output)),
+            &mut stdin,
+            FREEZE_GAMEPLAY_COMMANDS,
+            Duration::from_secs(1),
+            &ServerLog::new(10, None),
+        )
+        .await;
+
+        assert!(result.is_err());
+        assert!(stdin.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_start_up_server_waits_for_ready_line() {
+        // 出力が閉じられないまま起動完了メッセージを出すプロセス
+        let (mut process_stdout, stdout) = tokio::io::duplex(1024);
+        let mut stdout = BufReader::new(stdout);
+        let mut stdin = Vec::new();
+        process_stdout
+            .write_all(b"Preparing spawn area: 83%\n")
+            .await
+            .unwrap();
+        let writer = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            process_stdout
+                .write_all(b"Done (3.1s)! For help, type \"help\"\n")
+                .await
+                .unwrap();
+            process_stdout
+        });
+
+        start_up_server(
+            &mut stdout,
+            &mut stdin,
+            &["say ready"],
+            Duration::from_secs(5),
+            &ServerLog::new(10, None),
+        )
+        .await
+        .unwrap();
+        assert_eq!(stdin, b"say ready\n");
+        drop(writer.await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_start_up_server_times_out() {
+        let (_process_stdout, stdout) = tokio::io::duplex(1024);
+        let mut stdin = Vec::new();
+
+        let err = start_up_server(
+            &mut BufReader::new(stdout),
+            &mut stdin,
+            FREEZE_GAMEPLAY_COMMANDS,
+            Duration::from_millis(50),
+            &ServerLog::new(10, None),
+        )
+        .await
+        .unwrap_err();
+
+        assert!(err.to_string().contains("did not become ready"), "{}", err);
+        assert!(stdin.is_empty());
+    }
+
+    #[test]
+    fn test_read_server_properties_with_bom() {
+        let data = b"\xEF\xBB\xBFmotd=Hello\nserver-port=25565\n";
+        let props = read_server_properties(data).unwrap();
+        assert_eq!(props.get("motd").map(String::as_str), Some("Hello"));
+        assert_eq!(props.get("server-port").map(String::as_str), Some("25565"));
+        assert_eq!(props.len(), 2);
+    }
+
+    #[test]
+    fn test_read_server_properties_encodings() {
+        // マインクラフトが書き込むISO-8859-1とユニコードエスケープ
+        let props = read_server_properties(b"motd=caf\xE9 \\u3042\n").unwrap();
+        assert_eq!(props.get("motd").map(String::as_str), Some("café あ"));
+
+        // エディタで保存したUTF-8
+        let props = read_server_properties("\u{feff}motd=café あ🎉\n".as_bytes()).unwrap();
+        assert_eq!(props.get("motd").map(String::as_str), Some("café あ🎉"));
+    }
+
+    #[tokio::test]
+    async fn test_read_lossy_line_replaces_invalid_bytes() {
+        let mut reader = BufReader::new(Cursor::new(b"abc\xffdef\nnext".to_vec()));
+        let mut buf = Vec::new();
+
+        let line = read_lossy_line(&mut reader, &mut buf).await.unwrap();
+        assert_eq!(line.as_deref(), Some("abc\u{FFFD}def"));
+        let line = read_lossy_line(&mut reader, &mut buf).await.unwrap();
+        assert_eq!(line.as_deref(), Some("next"));
+        let line = read_lossy_line(&mut reader, &mut buf).await.unwrap();
+        assert_eq!(line, None);
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_server_ready_output_closed() {
+        let mut reader = BufReader::new(Cursor::new(b"[Server thread/ERROR]: crashed\n".to_vec()));
+
+        assert!(
+            wait_for_server_ready(&mut reader, &ServerLog::new(10, None))
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_pending_chunks_ignores_duplicate_reports() {
+        let mut chunks = PendingChunks::new(
+            [
+                ChunkPos::new(0, 0),
+                ChunkPos::new(0, 1),
+                ChunkPos::new(1, 0),
+            ],
+            5,
+        );
+
+        assert_eq!(chunks.confirm(ChunkPos::new(0, 0)), Some(2));
+        assert_eq!(chunks.confirm(ChunkPos::new(0, 0)), None);
+        assert_eq!(chunks.confirm(ChunkPos::new(5, 5)), None);
+        assert_eq!(chunks.confirm(ChunkPos::new(0, 1)), Some(1));
+        assert_eq!(chunks.confirm(ChunkPos::new(0, 1)), None);
+        assert_eq!(chunks.confirm(ChunkPos::new(0, 0)), None);
+        assert_eq!(chunks.pending.len(), 1);
+
+        assert_eq!(chunks.confirm(ChunkPos::new(1, 0)), Some(0));
+        assert!(chunks.is_done());
+        assert_eq!(chunks.claim(), None);
+    }
+
+    #[test]
+    fn test_pending_chunks_claims_distant_chunks_first() {
+        let mut chunks = PendingChunks::new(
+            [
+                ChunkPos::new(0, 0),
+                ChunkPos::new(1, 0),
+                ChunkPos::new(20, 0),
+                ChunkPos::new(21, 0),
+            ],
+            5,
+        );
+
+        assert_eq!(chunks.claim(), Some(ChunkPos::new(0, 0)));
+        assert_eq!(chunks.claim(), Some(ChunkPos::new(20, 0)));
+        assert_eq!(chunks.claim(), Some(ChunkPos::new(1, 0)));
+
+        chunks.release(ChunkPos::new(0, 0));
+        assert_eq!(chunks.claim(), Some(ChunkPos::new(21, 0)));
+        assert_eq!(chunks.claim(), Some(ChunkPos::new(0, 0)));
+        assert_eq!(chunks.claim(), None);
+    }
+
+    fn test_bot_settings() -> GenBotSettings {
+        GenBotSettings {
+            chunk_wait: Duration::from_millis(50),
+            first_chunk_timeout: Duration::from_secs(60),
+            max_stalled_teleports: 3,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bot_without_chunk_events_fails_fast() {
+        let progress = Arc::new(GenerationProgress::new(None));
+        let chunks = progress.add_dimension(
+            McDimension::Overworld,
+            (0..3).map(|x| ChunkPos::new(x, 0)),
+            5,
+        );
+        let (server_stdin, _console) = tokio::io::duplex(4096);
+        let (commands, _writer) = CommandSender::spawn(server_stdin);
+        // 接続しているがチャンクを報告しないボット
+        let (_tx, rx) = mpsc::channel(100);
+
+        let result = tokio::time::timeout(
+            Duration::from_secs(5),
+            run_gen_bot(
+                "bot00".to_string(),
+                chunks,
+                rx,
+                commands,
+                GenBotSettings {
+                    chunk_wait: Duration::from_millis(20),
+                    first_chunk_timeout: Duration::from_millis(100),
+                    max_stalled_teleports: 3,
+                },
+                Arc::new(AtomicBool::new(false)),
+            ),
+        )
+        .await
+        .expect("bot kept running without chunk events");
+
+        let err = result.unwrap_err().to_string();
+        assert!(err.contains("bot00 received no chunks"), "{}", err);
+        assert!(err.contains("incompatible"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_bots_claim_disjoint_chunks() {
+        let chunk_list: Vec<ChunkPos> = (0..3)
+            .flat_map(|x| (0..3).map(move |z| ChunkPos::new(x, z)))
+            .collect();
+        let progress = Arc::new(GenerationProgress::new(None));
+        let ungenarated_chunks =
+            progress.add_dimension(McDimension::Overworld, chunk_list.iter().copied(), 5);
+        let (server_stdin, console) = tokio::io::duplex(4096);
+        let (commands, writer) = CommandSender::spawn(server_stdin);
+
+        let mut senders = HashMap::new();
+        let mut bot_tasks = Vec::new();
+        for idx in 0..3 {
+            let bot_id = format!("bot{:02}", idx);
+            let (tx, rx) = mpsc::channel(100);
+            senders.insert(bot_id.clone(), tx);
+            bot_tasks.push(tokio::spawn(run_gen_bot(
+                bot_id,
+                ungenarated_chunks.clone(),
+                rx,
+                commands.clone(),
+                test_bot_settings(),
+                Arc::new(AtomicBool::new(false)),
+            )));
+        }
+
+        // テレポートされたチャンクだけをボットに報告するモックサーバー
+        let server = tokio::spawn(async move {
+            let mut targets: HashMap<String, Vec<ChunkPos>> = HashMap::new();
+            let mut lines = BufReader::new(console).lines();
+            while let Some(line) = lines.next_line().await.unwrap() {
+                let parts: Vec<&str> = line.split(' ').collect();
+                assert_eq!(parts[0], "tp");
+                let x = parts[2].parse::<i32>().unwrap().div_euclid(16);
+                let z = parts[4].parse::<i32>().unwrap().div_euclid(16);
+                targets
+                    .entry(parts[1].to_string())
+                    .or_default()
+                    .push(ChunkPos::new(x as isize, z as isize));
+                senders[parts[1]].send((x, z)).await.unwrap();
+            }
+            targets
+        });
+
+        for task in bot_tasks {
+            task.await.unwrap().unwrap();
+        }
+        drop(commands);
+        writer.await.unwrap().unwrap();
+        let targets = server.await.unwrap();
+
+        let mut all_targets: Vec<ChunkPos> = targets.values().flatten().copied().collect();
+        assert_eq!(all_targets.len(), 9);
+        all_targets.sort_by_key(|pos| (pos.x, pos.z));
+        all_targets.dedup();
+        assert_eq!(all_targets.len(), 9);
+    }
+
+    #[tokio::test]
+    async fn test_stalled_bot_leaves_chunks_to_others() {
+        let chunk_list: Vec<ChunkPos> = (0..12).map(|x| ChunkPos::new(x * 20, 0)).collect();
+        let progress = Arc::new(GenerationProgress::new(None));
+        let ungenarated_chunks =
+            progress.add_dimension(McDimension::Overworld, chunk_list.iter().copied(), 5);
+        let (server_stdin, console) = tokio::io::duplex(4096);
+        let (commands, writer) = CommandSender::spawn(server_stdin);
+
+        let mut senders = HashMap::new();
+        let mut bot_tasks = Vec::new();
+        for idx in 0..2 {
+            let bot_id = format!("bot{:02}", idx);
+            let (tx, rx) = mpsc::channel(100);
+            senders.insert(bot_id.clone(), tx);
+            bot_tasks.push(tokio::spawn(run_gen_bot(
+                bot_id,
+                ungenarated_chunks.clone(),
+                rx,
+                commands.clone(),
+                test_bot_settings(),
+                Arc::new(AtomicBool::new(false)),
+            )));
+        }
+
+        // bot00は最初のテレポートの後に固まる
+        let server = tokio::spawn(async move {
+            let mut lines = BufReader::new(console).lines();
+            let mut wedged = false;
+            while let Some(line) = lines.next_line().await.unwrap() {
+                let parts: Vec<&str> = line.split(' ').collect();
+                if parts[1] == "bot00" {
+                    if wedged {
+                        continue;
+                    }
+                    wedged = true;
+                }
+                let x = parts[2].parse::<i32>().unwrap().div_euclid(16);
+                let z = parts[4].parse::<i32>().unwrap().div_euclid(16);
+                senders[parts[1]].send((x, z)).await.unwrap();
+            }
+        });
+
+        let mut outcomes = Vec::new();
+        for task in bot_tasks {
+            outcomes.push(task.await.unwrap().unwrap());
+        }
+        drop(commands);
+        writer.await.unwrap().unwrap();
+        server.await.unwrap();
+
+        assert_eq!(
+            outcomes,
+            vec![GenBotOutcome::Stalled, GenBotOutcome::Finished]
+        );
+        assert!(ungenarated_chunks.chunks.lock().unwrap().is_done());
+    }
+
+    #[tokio::test]
+    async fn test_progress_aggregates_dimensions() {
+        let (events_tx, mut events_rx) = mpsc::unbounded_channel();
+        let progress = Arc::new(GenerationProgress::new(Some(events_tx)));
+        let dimensions = [
+            progress.add_dimension(
+                McDimension::Overworld,
+                (0..2).flat_map(|x| (0..2).map(move |z| ChunkPos::new(x, z))),
+                5,
+            ),
+            progress.add_dimension(McDimension::Nether, (0..3).map(|x| ChunkPos::new(x, -1)), 5),
+        ];
+        assert_eq!(*progress.remaining_total.lock().unwrap(), 7);
+
+        let (server_stdin, console) = tokio::io::duplex(4096);
+        let (commands, writer) = CommandSender::spawn(server_stdin);
+
+        let mut senders = HashMap::new();
+        let mut bot_tasks = Vec::new();
+        for (dim_idx, chunks) in dimensions.iter().enumerate() {
+            for idx in 0..2 {
+                let bot_id = format!("bot{}{}", dim_idx, idx);
+                let (tx, rx) = mpsc::channel(100);
+                senders.insert(bot_id.clone(), tx);
+                bot_tasks.push(tokio::spawn(run_gen_bot(
+                    bot_id,
+                    chunks.clone(),
+                    rx,
+                    commands.clone(),
+                    test_bot_settings(),
+                    Arc::new(AtomicBool::new(false)),
+                )));
+            }
+        }
+
+        // テレポートされたチャンクだけをボットに報告するモックサーバー
+        let server = tokio::spawn(async move {
+            let mut lines = BufReader::new(console).lines();
+            while let Some(line) = lines.next_line().await.unwrap() {
+                let tp = match line.strip_prefix("execute in minecraft:the_nether run ") {
+                    Some(tp) => tp,
+                    None => line.as_str(),
+                };
+                let parts: Vec<&str> = tp.split(' ').collect();
+                assert_eq!(parts[0], "tp");
+                // ネザーのボットはネザーでテレポートされる
+                assert_eq!(parts[1].starts_with("bot1"), tp.len() != line.len());
+                let x = parts[2].parse::<i32>().unwrap().div_euclid(16);
+                let z = parts[4].parse::<i32>().unwrap().div_euclid(16);
+                senders[parts[1]].send((x, z)).await.unwrap();
+            }
+        });
+
+        for task in bot_tasks {
+            task.await.unwrap().unwrap();
+        }
+        drop(commands);
+        writer.await.unwrap().unwrap();
+        server.await.unwrap();
+        drop(dimensions);
+        drop(progress);
+
+        let mut events = Vec::new();
+        while let Some(event) = events_rx.recv().await {
+            events.push(event);
+        }
+        assert_eq!(events.len(), 7);
+        let remaining: Vec<usize> = events.iter().map(|e| e.remaining_total).collect();
+        assert_eq!(remaining, vec![6, 5, 4, 3, 2, 1, 0]);
+        // 生成済みの数と経過時間は単調に増え、最後は全体の数に達する
+        let generated: Vec<usize> = events.iter().map(|e| e.generated).collect();
+        assert_eq!(generated, vec![1, 2, 3, 4, 5, 6, 7]);
+        assert!(events.iter().all(|e| e.total == 7));
+        assert!(events.windows(2).all(|w| w[0].elapsed <= w[1].elapsed));
+        assert_eq!(
+            events
+                .iter()
+                .filter(|e| e.dimension == McDimension::Nether)
+                .count(),
+            3
+        );
+    }
+
+    async fn mock_server() -> (RunningServer, MockServerLauncher) {
+        let (launcher, _) = MockServerLauncher::new(&[]);
+        (start_mock_server(&launcher).await, launcher)
+    }
+
+    async fn start_mock_server(launcher: &MockServerLauncher) -> RunningServer {
+        let mut process = launcher
+            .start(
+                std::process::Command::new("java"),
+                std::path::Path::new("/"),
+            )
+            .await
+            .unwrap();
+        let (commands, writer) = CommandSender::spawn(process.take_stdin().unwrap());
+        RunningServer {
+            commands,
+            writer,
+            process,
+            log: Arc::new(ServerLog::new(10, None)),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stop_graceful() {
+        let (server, launcher) = mock_server().await;
+
+        let shutdown = server.stop(Duration::from_secs(5)).await.unwrap();
+        assert_eq!(shutdown, ServerShutdown::Graceful);
+        assert_eq!(launcher.commands(), vec!["stop"]);
+        assert!(!launcher.was_killed());
+    }
+
+    #[tokio::test]
+    async fn test_stop_kills_server_ignoring_stop() {
+        let (launcher, _) = MockServerLauncher::new(&[]);
+        let launcher = launcher.with_hang_until_killed();
+        let server = start_mock_server(&launcher).await;
+
+        let shutdown = server.stop(Duration::from_millis(50)).await.unwrap();
+        assert_eq!(shutdown, ServerShutdown::Forced);
+        assert_eq!(launcher.commands(), vec!["stop"]);
+        assert!(launcher.was_killed());
+    }
+
+    #[tokio::test]
+    async fn test_command_sender_keeps_lines_whole() {
+        // 小さなバッファで書き込みが細切れになるようにする
+        let (server_stdin, console) = tokio::io::duplex(7);
+        let (commands, writer) = CommandSender::spawn(server_stdin);
+
+        let tasks: Vec<_> = (0..4)
+            .map(|task| {
+                let commands = commands.clone();
+                tokio::spawn(async move {
+                    for idx in 0..50 {
+                        commands
+                            .send(format!("say task{} message{}", task, idx))
+                            .await
+                            .unwrap();
+                        tokio::task::yield_now().await;
+                    }
+                })
+            })
+            .collect();
+        drop(commands);
+
+        let reader = tokio::spawn(async move {
+            let mut lines = BufReader::new(console).lines();
+            let mut received = Vec::new();
+            while let Some(line) = lines.next_line().await.unwrap() {
+                received.push(line);
+            }
+            received
+        });
+        for task in tasks {
+            task.await.unwrap();
+        }
+        writer.await.unwrap().unwrap();
+        let received = reader.await.unwrap();
+
+        assert_eq!(received.len(), 200);
+        for task in 0..4 {
+            // 各タスクのコマンドは行単位で、送った順に届く
+            let lines: Vec<&String> = received
+                .iter()
+                .filter(|line| line.starts_with(&format!("say task{} ", task)))
+                .collect();
+            let expected: Vec<String> = (0..50)
+                .map(|idx| format!("say task{} message{}", task, idx))
+                .collect();
+            assert_eq!(lines, expected.iter().collect::<Vec<_>>());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_server_log_captures_lines_in_order() {
+        let output = b"[Server thread/INFO]: Starting minecraft server\n\
+            [Server thread/WARN]: **** FAILED TO BIND TO PORT!\n\
+            [Server thread/ERROR]: Exception in server tick loop\n"
+            .to_vec();
+        let received = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let callback: ServerLogCallback = {
+            let received = received.clone();
+            Arc::new(move |line: &str| received.lock().unwrap().push(line.to_string()))
+        };
+        let log = Arc::new(ServerLog::new(2, Some(callback)));
+
+        spawn_log_reader(BufReader::new(Cursor::new(output)), log.clone())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            *received.lock().unwrap(),
+            vec![
+                "[Server thread/INFO]: Starting minecraft server",
+                "[Server thread/WARN]: **** FAILED TO BIND TO PORT!",
+                "[Server thread/ERROR]: Exception in server tick loop",
+            ]
+        );
+        // 古い行から捨てられる
+        assert_eq!(
+            log.recent_logs(),
+            vec![
+                "[Server thread/WARN]: **** FAILED TO BIND TO PORT!",
+                "[Server thread/ERROR]: Exception in server tick loop",
+            ]
+        );
+    }
+
+    // 生成を要求されたチャンクを記録するジェネレーター
+    #[derive(Default)]
+    struct RecordingGenerator {
+        requests: std::sync::Mutex<Vec<Vec<ChunkPos>>>,
+        dimensions: std::sync::Mutex<Vec<McDimension>>,
+    }
+
+    #[async_trait::async_trait]
+    impl ChunkGenerator for RecordingGenerator {
+        async fn generate_chunks_in(
+            &self,
+            _world_data: Dir,
+            _version: &McVanillaVersionId,
+            dimension: &McDimension,
+            chunk_list: &[ChunkPos],
+        ) -> Result<Option<RunningServer>> {
+            self.requests.lock().unwrap().push(chunk_list.to_vec());
+            self.dimensions.lock().unwrap().push(dimension.clone());
+            Ok(None)
+        }
+    }
+
+    // 指定したチャンクが決められた回数だけ読み込みに失敗する検査
+    struct FlakyVerifier {
+        failures: std::sync::Mutex<HashMap<ChunkPos, usize>>,
+    }
+
+    impl ChunkVerifier for FlakyVerifier {
+        fn unreadable_chunks(&self, chunks: &[ChunkPos]) -> Result<Vec<ChunkPos>> {
+            let mut failures = self.failures.lock().unwrap();
+            Ok(chunks
+                .iter()
+                .filter(|chunk| match failures.get_mut(chunk) {
+                    Some(count) if *count > 0 => {
+                        *count -= 1;
+                        true
+                    }
+                    _ => false,
+                })
+                .copied()
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generate_chunks_verified_regenerates_unreadable_chunks() {
+        let generator = RecordingGenerator::default();
+        let verifier = FlakyVerifier {
+            failures: std::sync::Mutex::new(HashMap::from([(ChunkPos::new(1, 0), 1)])),
+        };
+        let chunk_list = chunks_in_area(ChunkPos::new(0, 0), ChunkPos::new(1, 1));
+
+        generate_chunks_verified(
+            &generator,
+            Dir::new(),
+            &McVanillaVersionId::new("1.20.1".to_string()),
+            &chunk_list,
+            &verifier,
+            3,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            *generator.requests.lock().unwrap(),
+            vec![chunk_list.clone(), vec![ChunkPos::new(1, 0)]]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_generate_chunks_verified_gives_up() {
+        let generator = RecordingGenerator::default();
+        let verifier = FlakyVerifier {
+            failures: std::sync::Mutex::new(HashMap::from([(ChunkPos::new(0, 0), 10)])),
+        };
+
+        let err = generate_chunks_verified(
+            &generator,
+            Dir::new(),
+            &McVanillaVersionId::new("1.20.1".to_string()),
+            &[ChunkPos::new(0, 0)],
+            &verifier,
+            2,
+        )
+        .await
+        .unwrap_err();
+
+        assert!(err.to_string().contains("after 2 rounds"), "{}", err);
+        assert_eq!(generator.requests.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_generate_missing_chunks_uses_dimension_kind() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dimension =
+            Dimension::new(temp_dir.path().join("DIM-1/region")).with_kind(McDimension::Nether);
+        let generator = RecordingGenerator::default();
+
+        generator
+            .generate_missing_chunks(
+                Dir::new(),
+                &McVanillaVersionId::new("1.20.1".to_string()),
+                &dimension,
+                ChunkPos::new(0, 0),
+                ChunkPos::new(0, 1),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            *generator.dimensions.lock().unwrap(),
+            vec![McDimension::Nether]
+        );
+        assert_eq!(
+            *generator.requests.lock().unwrap(),
+            vec![vec![ChunkPos::new(0, 0), ChunkPos::new(0, 1)]]
+        );
+    }
+
+    #[test]
+    fn test_parse_player_list() {
+        assert_eq!(
+            parse_player_list(
+                "[12:00:00] [Server thread/INFO]: There are 2 of a max of 20 players online: bot00, bot01"
+            ),
+            Some(vec!["bot00".to_string(), "bot01".to_string()])
+        );
+        assert_eq!(
+            parse_player_list("There are 1/20 players online: bot00"),
+            Some(vec!["bot00".to_string()])
+        );
+        assert_eq!(
+            parse_player_list("There are 0 of a max of 20 players online:"),
+            Some(vec![])
+        );
+        assert_eq!(parse_player_list("bot00 joined the game"), None);
+    }
+
+    #[tokio::test]
+    async fn test_query_players() {
+        let (launcher, mut command_rx) = MockServerLauncher::new(&[]);
+        let server = start_mock_server(&launcher).await;
+        // `list` を受け取ると応答を出力するサーバー。出力は生成中と同じくログの読み取りタスクが読む
+        let (mut stdout_writer, stdout) = tokio::io::duplex(4096);
+        spawn_log_reader(tokio::io::BufReader::new(stdout), server.log.clone());
+        let responder = tokio::spawn(async move {
+            while let Some(command) = command_rx.recv().await {
+                if command == "list" {
+                    stdout_writer
+                        .write_all(
+                            b"[12:00:01] [Server thread/INFO]: bot01 joined the game\n\
+                              [12:00:02] [Server thread/INFO]: There are 2 of a max of 20 players online: bot00, bot01\n",
+                        )
+                        .await
+                        .unwrap();
+                }
+            }
+        });
+
+        let players = server.query_players(Duration::from_secs(5)).await.unwrap();
+        assert_eq!(players, vec!["bot00", "bot01"]);
+        assert_eq!(launcher.commands(), vec!["list"]);
+        assert!(
+            server
+                .recent_logs()
+                .iter()
+                .any(|line| line.contains("There are 2"))
+        );
+        responder.abort();
+    }
+
+    #[tokio::test]
+    async fn test_query_players_times_out_without_answer() {
+        let (server, launcher) = mock_server().await;
+
+        let err = server
+            .query_players(Duration::from_millis(50))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("did not answer"), "{}", err);
+        assert_eq!(launcher.commands(), vec!["list"]);
+    }
+
+    #[tokio::test]
+    async fn test_complete_generation_keep_alive() {
+        let (server, launcher) = mock_server().await;
+
+        let server = complete_generation(server, true, DEFAULT_STOP_TIMEOUT)
+            .await
+            .unwrap()
+            .unwrap();
+        // 何も送信されていない
+        assert!(launcher.commands().is_empty());
+
+        assert_eq!(
+            server.stop(DEFAULT_STOP_TIMEOUT).await.unwrap(),
+            ServerShutdown::Graceful
+        );
+        assert_eq!(launcher.commands(), vec!["stop"]);
+    }
+
+    #[tokio::test]
+    async fn test_complete_generation_stops_server() {
+        let (server, launcher) = mock_server().await;
+
+        assert!(
+            complete_generation(server, false, DEFAULT_STOP_TIMEOUT)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(launcher.commands(), vec!["stop"]);
+    }
+
+    type BotSenders = Arc<std::sync::Mutex<HashMap<String, mpsc::Sender<(i32, i32)>>>>;
+
+    struct MockBotSpawner {
+        senders: BotSenders,
+        stopped: Arc<std::sync::Mutex<Vec<String>>>,
+    }
+
+    impl MockBotSpawner {
+        fn new(senders: BotSenders) -> Self {
+            MockBotSpawner {
+                senders,
+                stopped: Arc::new(std::sync::Mutex::new(Vec::new())),
+            }
+        }
+    }
+
+    struct MockBot {
+        name: String,
+        stopped: Arc<std::sync::Mutex<Vec<String>>>,
+    }
+
+    impl BotHandle for MockBot {
+        fn name(&self) -> String {
+            self.name.clone()
+        }
+
+        fn stop(self: Box<Self>) -> Result<()> {
+            self.stopped.lock().unwrap().push(self.name.clone());
+            Ok(())
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl BotSpawner for MockBotSpawner {
+        async fn spawn_bot(
+            &self,
+            _host: &std::net::IpAddr,
+            _port: u16,
+            _version: &McVanillaVersionId,
+            name: &str,
+        ) -> Result<(Box<dyn BotHandle>, mpsc::Receiver<(i32, i32)>)> {
+            let (tx, rx) = mpsc::channel(100);
+            self.senders.lock().unwrap().insert(name.to_string(), tx);
+            let bot = MockBot {
+                name: name.to_string(),
+                stopped: self.stopped.clone(),
+            };
+            Ok((Box::new(bot), rx))
+        }
+    }
+
+    struct MockPortFinder;
+
+    impl FreePortFinder for MockPortFinder {
+        fn find_free_port(&self, _host: std::net::IpAddr) -> Result<u16, std::io::Error> {
+            Ok(25565)
+        }
+    }
+
+    struct MockJavaLoader;
+
+    #[async_trait::async_trait]
+    impl McJavaLoader for MockJavaLoader {
+        async fn list_runtimes(&self) -> Result<Vec<McJava>, String> {
+            Ok(vec![])
+        }
+
+        async fn ready_runtime(&self, _component: &JavaComponent) -> Result<PathBuf, String> {
+            Ok(PathBuf::from("/usr/bin/java"))
+        }
+    }
+
+    fn mock_url_fetcher() -> DummyUrlFetcher {
+        let mut url_fetcher = DummyUrlFetcher::new();
+        url_fetcher.add_data(
+            url::Url::parse("https://piston-meta.mojang.com/mc/game/version_manifest_v2.json")
+                .unwrap(),
+            r#"{"versions": [{"id": "1.20.1", "type": "release", "url": "https://example.com/1.20.1.json"}]}"#,
+        );
+        url_fetcher.add_data(
+            url::Url::parse("https://example.com/1.20.1.json").unwrap(),
+            r#"{"downloads": {"server": {"url": "https://example.com/server.jar"}}}"#,
+        );
+        url_fetcher.add_data(
+            url::Url::parse("https://example.com/server.jar").unwrap(),
+            b"server jar".to_vec(),
+        );
+        url_fetcher
+    }
+
+    const READY_LINE: &str = "Done (1.0s)! For help, type \"help\"";
+
+    // 生成のテストで共通のモック一式。各テストは生成器の設定とモックサーバーの応答だけを指定する
+    struct GeneratorHarness {
+        fs_handler: Arc<OnMemoryFsHandler>,
+        senders: BotSenders,
+        bot_spawner: Arc<MockBotSpawner>,
+        launcher: Arc<MockServerLauncher>,
+        command_rx: Option<mpsc::UnboundedReceiver<String>>,
+    }
+
+    impl GeneratorHarness {
+        // 起動完了を出力するモックサーバーで作成する
+        fn new() -> Self {
+            Self::with_launcher(MockServerLauncher::new(&[READY_LINE]))
+        }
+
+        fn with_launcher(
+            (launcher, command_rx): (MockServerLauncher, mpsc::UnboundedReceiver<String>),
+        ) -> Self {
+            let senders: BotSenders = Arc::new(std::sync::Mutex::new(HashMap::new()));
+            GeneratorHarness {
+                fs_handler: Arc::new(OnMemoryFsHandler::new()),
+                bot_spawner: Arc::new(MockBotSpawner::new(senders.clone())),
+                senders,
+                launcher: Arc::new(launcher),
+                command_rx: Some(command_rx),
+            }
+        }
+
+        // `bot_count` 台のボットでモックサーバーを使う生成器
+        fn generator(&self, bot_count: usize) -> DefaultChunkGenerator {
+            self.generator_with_port_finder(bot_count, Box::new(MockPortFinder))
+        }
+
+        fn generator_with_port_finder(
+            &self,
+            bot_count: usize,
+            free_port_finder: Box<dyn FreePortFinder + Send + Sync>,
+        ) -> DefaultChunkGenerator {
+            let url_fetcher = Arc::new(mock_url_fetcher());
+            DefaultChunkGenerator::new(
+                VanillaVersionLoader::new(url_fetcher.clone(), Arc::new(MockJavaLoader)),
+                self.bot_spawner.clone(),
+                free_port_finder,
+                Arc::new(DefaultTrieLoader::new(self.fs_handler.clone(), url_fetcher)),
+                PathBuf::from("/work"),
+                NonZeroUsize::new(bot_count).unwrap(),
+            )
+            .with_server_launcher(self.launcher.clone())
+        }
+
+        // テレポートのコマンドを受け取るたびに `respond` を呼び、真を返したらそのチャンクをボットに報告する
+        fn serve(
+            &mut self,
+            mut respond: impl FnMut(&str, ChunkPos) -> bool + Send + 'static,
+        ) -> tokio::task::JoinHandle<()> {
+            let mut command_rx = self.command_rx.take().unwrap();
+            let senders = self.senders.clone();
+            tokio::spawn(async move {
+                while let Some(command) = command_rx.recv().await {
+                    let parts: Vec<&str> = command.split(' ').collect();
+                    if parts[0] != "tp" {
+                        continue;
+                    }
+                    let x = parts[2].parse::<i32>().unwrap().div_euclid(16);
+                    let z = parts[4].parse::<i32>().unwrap().div_euclid(16);
+                    if !respond(parts[1], ChunkPos::new(x as isize, z as isize)) {
+                        continue;
+                    }
+                    let sender = senders.lock().unwrap()[parts[1]].clone();
+                    sender.send((x, z)).await.unwrap();
+                }
+            })
+        }
+
+        fn stopped_bots(&self) -> Vec<String> {
+            let mut stopped = self.bot_spawner.stopped.lock().unwrap().clone();
+            stopped.sort();
+            stopped
+        }
+
+        fn server_properties(&self) -> String {
+            let properties = self
+                .fs_handler
+                .read(&PathBuf::from("/work/server/server.properties"))
+                .unwrap();
+            String::from_utf8(properties).unwrap()
+        }
+    }
+
+    fn version_1_20_1() -> McVanillaVersionId {
+        McVanillaVersionId::new("1.20.1".to_string())
+    }
+
+    #[tokio::test]
+    async fn test_generate_chunks_with_mock_server() {
+        let mut harness = GeneratorHarness::with_launcher(MockServerLauncher::new(&[
+            "Loading libraries",
+            READY_LINE,
+        ]));
+        let generator = harness
+            .generator(3)
+            .with_chunk_wait(Duration::from_millis(50))
+            .with_startup_commands(vec!["datapack enable \"file/gen\"".to_string()]);
+        // テレポートされたチャンクだけをボットに報告するモックサーバー
+        let server = harness.serve(|_, _| true);
+
+        let chunk_list: Vec<ChunkPos> = (0..3)
+            .flat_map(|x| (0..3).map(move |z| ChunkPos::new(x, z)))
+            .collect();
+        let running = generator
+            .generate_chunks(Dir::new(), &version_1_20_1(), &chunk_list)
+            .await
+            .unwrap();
+        assert!(running.is_none());
+        server.abort();
+
+        let commands = harness.launcher.commands();
+        assert_eq!(commands[0], "gamerule doDaylightCycle false");
+        // 設定のコマンドはすべて、最初のテレポートより前に送られる
+        let first_tp = commands.iter().position(|c| c.starts_with("tp ")).unwrap();
+        for setup_command in FREEZE_GAMEPLAY_COMMANDS
+            .iter()
+            .copied()
+            .chain(["datapack enable \"file/gen\""])
+        {
+            let position = commands
+                .iter()
+                .position(|c| c == setup_command)
+                .unwrap_or_else(|| panic!("{} was not sent: {:?}", setup_command, commands));
+            assert!(position < first_tp, "{:?}", commands);
+        }
+        // 各ボットはテレポートされる前にOPにされる
+        for bot_id in ["bot00", "bot01", "bot02"] {
+            let op = commands
+                .iter()
+                .position(|c| *c == format!("op {}", bot_id))
+                .unwrap();
+            let first_tp = commands
+                .iter()
+                .position(|c| c.starts_with(&format!("tp {} ", bot_id)))
+                .unwrap();
+            assert!(op < first_tp, "{:?}", commands);
+        }
+        assert_eq!(commands.last().map(String::as_str), Some("stop"));
+        assert_eq!(commands.iter().filter(|c| c.starts_with("tp ")).count(), 9);
+
+        assert!(harness.server_properties().contains("server-port=25565"));
+        assert_eq!(
+            harness
+                .fs_handler
+                .read(&PathBuf::from("/work/server/server.jar"))
+                .unwrap(),
+            b"server jar"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_generate_missing_chunks_skips_existing() {
+        // (0,0), (1,1), (2,2) だけが生成済みのリージョン
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let region_dir = temp_dir.path().join("region");
+        std::fs::create_dir_all(&region_dir).unwrap();
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(region_dir.join("r.0.0.mca"))
+            .unwrap();
+        let mut region = fastanvil::Region::new(file).unwrap();
+        let chunk = fastnbt::to_bytes(&fastnbt::nbt!({ "Status": "minecraft:full" })).unwrap();
+        for i in 0..3 {
+            region.write_chunk(i, i, &chunk).unwrap();
+        }
+        drop(region);
+        let dimension = Dimension::new(region_dir);
+
+        let mut harness = GeneratorHarness::new();
+        let generator = harness
+            .generator(3)
+            .with_chunk_wait(Duration::from_millis(50));
+        // テレポート先を記録し、そのチャンクだけをボットに報告するモックサーバー
+        let teleported = Arc::new(std::sync::Mutex::new(HashSet::new()));
+        let server = {
+            let teleported = teleported.clone();
+            harness.serve(move |_, chunk| {
+                teleported.lock().unwrap().insert(chunk);
+                true
+            })
+        };
+
+        let version = version_1_20_1();
+        let running = generator
+            .generate_missing_chunks(
+                Dir::new(),
+                &version,
+                &dimension,
+                ChunkPos::new(0, 0),
+                ChunkPos::new(2, 2),
+            )
+            .await
+            .unwrap();
+        assert!(running.is_none());
+        server.abort();
+
+        let expected: HashSet<ChunkPos> = chunks_in_area(ChunkPos::new(0, 0), ChunkPos::new(2, 2))
+            .into_iter()
+            .filter(|chunk| chunk.x != chunk.z)
+            .collect();
+        assert_eq!(*teleported.lock().unwrap(), expected);
+        let server_starts = harness.launcher.commands().len();
+
+        // 範囲内がすべて生成済みならサーバーを起動しない
+        let running = generator
+            .generate_missing_chunks(
+                Dir::new(),
+                &version,
+                &dimension,
+                ChunkPos::new(1, 1),
+                ChunkPos::new(1, 1),
+            )
+            .await
+            .unwrap();
+        assert!(running.is_none());
+        assert_eq!(harness.launcher.commands().len(), server_starts);
+    }
+
+    #[tokio::test]
+    async fn test_generate_chunks_cancelled() {
+        let mut harness = GeneratorHarness::new();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let generator = harness
+            .generator(3)
+            .with_chunk_wait(Duration::from_secs(60))
+            .with_cancel_flag(cancel.clone());
+        // チャンクを報告しないモックサーバー。最初のテレポートで中断する
+        let server = {
+            let cancel = cancel.clone();
+            harness.serve(move |_, _| {
+                cancel.store(true, Ordering::SeqCst);
+                false
+            })
+        };
+
+        let chunk_list: Vec<ChunkPos> = (0..3)
+            .flat_map(|x| (0..3).map(move |z| ChunkPos::new(x, z)))
+            .collect();
+        let result = tokio::time::timeout(
+            Duration::from_secs(10),
+            generator.generate_chunks(Dir::new(), &version_1_20_1(), &chunk_list),
+        )
+        .await
+        .expect("generation did not stop after cancellation");
+        server.abort();
+
+        let err = result.err().unwrap();
+        assert!(err.to_string().contains("cancelled"), "{}", err);
+        assert_eq!(harness.stopped_bots(), vec!["bot00", "bot01", "bot02"]);
+        assert_eq!(
+            harness.launcher.commands().last().map(String::as_str),
+            Some("stop")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_generate_chunks_uses_configured_bots_and_view_distance() {
+        let mut harness = GeneratorHarness::new();
+        let generator = harness
+            .generator(5)
+            .with_chunk_wait(Duration::from_millis(50))
+            .with_view_distance(8);
+        let server = harness.serve(|_, _| true);
+
+        let chunk_list: Vec<ChunkPos> = (0..20).map(|x| ChunkPos::new(x * 20, 0)).collect();
+        generator
+            .generate_chunks(Dir::new(), &version_1_20_1(), &chunk_list)
+            .await
+            .unwrap();
+        server.abort();
+
+        assert_eq!(harness.senders.lock().unwrap().len(), 5);
+        assert!(harness.server_properties().contains("view-distance=8"));
+    }
+
+    #[tokio::test]
+    async fn test_generate_chunks_rejects_invalid_view_distance() {
+        let harness = GeneratorHarness::new();
+        let generator = harness.generator(3).with_view_distance(33);
+
+        let err = generator
+            .generate_chunks(Dir::new(), &version_1_20_1(), &[ChunkPos::new(0, 0)])
+            .await
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("View distance"), "{}", err);
+        // サーバーは起動していない
+        assert!(harness.launcher.commands().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_generate_chunks_fails_when_all_bots_stall() {
+        let mut harness = GeneratorHarness::new();
+        let generator = harness
+            .generator(3)
+            .with_chunk_wait(Duration::from_millis(20));
+        // 各ボットの最初のテレポートにだけ応答し、その後は何も報告しないモックサーバー
+        let mut answered = HashSet::new();
+        let server = harness.serve(move |bot_id, _| answered.insert(bot_id.to_string()));
+
+        let chunk_list: Vec<ChunkPos> = (0..9).map(|x| ChunkPos::new(x * 20, 0)).collect();
+        let result = tokio::time::timeout(
+            Duration::from_secs(10),
+            generator.generate_chunks(Dir::new(), &version_1_20_1(), &chunk_list),
+        )
+        .await
+        .expect("generation kept running with stalled bots");
+        server.abort();
+
+        let err = result.err().unwrap();
+        assert!(err.to_string().contains("All 3 bots stalled"), "{}", err);
+        assert_eq!(harness.stopped_bots().len(), 3);
+        assert_eq!(
+            harness.launcher.commands().last().map(String::as_str),
+            Some("stop")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_generate_chunks_stops_server_when_bot_fails() {
+        let harness = GeneratorHarness::new();
+        // チャンクを報告しないモックサーバー
+        let generator = harness
+            .generator(1)
+            .with_chunk_wait(Duration::from_millis(20))
+            .with_first_chunk_timeout(Duration::from_millis(100));
+
+        let result = tokio::time::timeout(
+            Duration::from_secs(10),
+            generator.generate_chunks(Dir::new(), &version_1_20_1(), &[ChunkPos::new(0, 0)]),
+        )
+        .await
+        .expect("generation kept running with a chunkless bot");
+
+        let err = result.err().unwrap().to_string();
+        assert!(err.contains("bot00 received no chunks"), "{}", err);
+        assert_eq!(harness.stopped_bots(), vec!["bot00"]);
+        assert_eq!(
+            harness.launcher.commands().last().map(String::as_str),
+            Some("stop")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dropping_generate_chunks_kills_server() {
+        let (launcher, command_rx) = MockServerLauncher::new(&[READY_LINE]);
+        let mut harness =
+            GeneratorHarness::with_launcher((launcher.with_hang_until_killed(), command_rx));
+        let generator = harness
+            .generator(1)
+            .with_chunk_wait(Duration::from_secs(60));
+        let mut command_rx = harness.command_rx.take().unwrap();
+
+        let version = version_1_20_1();
+        let chunk_list = [ChunkPos::new(0, 0)];
+        let mut generation = Box::pin(generator.generate_chunks(Dir::new(), &version, &chunk_list));
+        // ボットが最初のテレポートをするまで進めてから、futureを破棄する
+        loop {
+            tokio::select! {
+                result = &mut generation => panic!("generation finished: {:?}", result.err()),
+                command = command_rx.recv() => {
+                    if command.unwrap().starts_with("tp ") {
+                        break;
+                    }
+                }
+            }
+        }
+        drop(generation);
+
+        assert!(harness.launcher.was_killed());
+        assert!(!harness.launcher.commands().contains(&"stop".to_string()));
+    }
+
+    // 起動時に、生成器が使うポートを他のソケットが束縛できるかを記録するランチャー
+    struct PortCheckingLauncher {
+        inner: Arc<MockServerLauncher>,
+        fs_handler: Arc<OnMemoryFsHandler>,
+        port: std::sync::Mutex<Option<u16>>,
+        taken_by_others: std::sync::Mutex<Vec<bool>>,
+    }
+
+    #[async_trait::async_trait]
+    impl ServerLauncher for PortCheckingLauncher {
+        async fn start(
+            &self,
+            command: std::process::Command,
+            current_dir: &std::path::Path,
+        ) -> Result<Box<dyn ServerProcess>> {
+            let properties = self
+                .fs_handler
+                .read(&current_dir.join("server.properties"))
+                .unwrap();
+            let port: u16 = read_server_properties(&properties).unwrap()["server-port"]
+                .parse()
+                .unwrap();
+            *self.port.lock().unwrap() = Some(port);
+            let host = std::net::IpAddr::V4(std::net::Ipv4Addr::LOCALHOST);
+            {
+                let mut taken_by_others = self.taken_by_others.lock().unwrap();
+                taken_by_others.push(can_bind(port));
+                for _ in 0..20 {
+                    let reservation = DefaultFreePortFinder.reserve_free_port(host).unwrap();
+                    taken_by_others.push(reservation.port() == port);
+                }
+            }
+            self.inner.start(command, current_dir).await
+        }
+    }
+
+    // `SO_REUSEADDR` を設定せずにポートを束縛できるか
+    fn can_bind(port: u16) -> bool {
+        let socket = tokio::net::TcpSocket::new_v4().unwrap();
+        socket
+            .bind((std::net::Ipv4Addr::LOCALHOST, port).into())
+            .is_ok()
+    }
+
+    #[tokio::test]
+    async fn test_port_reserved_while_server_starts() {
+        let mut harness = GeneratorHarness::new();
+        let launcher = Arc::new(PortCheckingLauncher {
+            inner: harness.launcher.clone(),
+            fs_handler: harness.fs_handler.clone(),
+            port: std::sync::Mutex::new(None),
+            taken_by_others: std::sync::Mutex::new(Vec::new()),
+        });
+        let generator = harness
+            .generator_with_port_finder(1, Box::new(DefaultFreePortFinder))
+            .with_server_launcher(launcher.clone())
+            .with_chunk_wait(Duration::from_millis(20));
+        let server = harness.serve(|_, _| true);
+
+        generator
+            .generate_chunks(Dir::new(), &version_1_20_1(), &[ChunkPos::new(0, 0)])
+            .await
+            .unwrap();
+        server.abort();
+
+        // 起動中は他の予約や束縛に同じポートを取られない
+        let taken_by_others = launcher.taken_by_others.lock().unwrap().clone();
+        assert_eq!(taken_by_others.len(), 21);
+        assert!(taken_by_others.iter().all(|taken| !taken));
+        // 起動を終えたら予約は解放される
+        let port = launcher.port.lock().unwrap().unwrap();
+        assert!(can_bind(port));
+    }
 }
@@ -0,0 +1,351 @@
+use anyhow::Result;
+use fastnbt::Value;
+use std::collections::HashMap;
+
+use crate::infra::region_loader::{Block, Blockstates, Chunk, Section};
+
+/// 旧バージョンでプレイヤーが編集したチャンクを、新バージョンで生成したチャンクに移行する
+pub trait ChunkMigrator {
+    fn migrate(&self, old_edited: &Chunk, old_plain: &Chunk, new_plain: &Chunk) -> Result<Chunk>;
+}
+
+/// ブロック単位の3方向マージで移行する。
+/// `old_edited` と `old_plain` で異なるブロックはプレイヤーの編集とみなして `old_edited` のものを、
+/// それ以外は `new_plain` のものを使う。プレイヤーの編集と新バージョンの地形の変更が重なった場合はプレイヤーの編集を優先する。
+/// ブロックエンティティも座標ごとに同じように3方向マージし、マージ後のブロックと合わないものは捨てる。
+/// エンティティは1.17以降チャンクとは別の `entities` リージョンに保存されるため、ここでは移行しない
+pub struct DefaultChunkMigrator;
+
+impl ChunkMigrator for DefaultChunkMigrator {
+    fn migrate(&self, old_edited: &Chunk, old_plain: &Chunk, new_plain: &Chunk) -> Result<Chunk> {
+        let mut result = new_plain.clone();
+        // 保存されていないセクションは空気で埋まっているものとして、どちらかにあるすべての高さを移行する
+        let mut ys: Vec<i8> = [old_edited, new_plain]
+            .iter()
+            .flat_map(|chunk| chunk.sections().iter().filter_map(Section::y))
+            .collect();
+        ys.sort();
+        ys.dedup();
+        for y in ys {
+            let edited = section_blocks(old_edited, y);
+            let plain = section_blocks(old_plain, y);
+            if edited == plain {
+                continue;
+            }
+            let merged: Vec<_> = section_blocks(&result, y)
+                .into_iter()
+                .zip(edited.into_iter().zip(plain))
+                .map(|(new, (edited, plain))| if edited == plain { new } else { edited })
+                .collect();
+            let block_states = Blockstates::from_blocks(&merged)?;
+            match result
+                .sections_mut()
+                .iter_mut()
+                .find(|section| section.y() == Some(y))
+            {
+                Some(section) => section.set_block_states(block_states),
+                // 新バージョンにないセクションは、プレイヤーが編集したセクションをもとに作る
+                None => {
+                    let section = match find_section(old_edited, y) {
+                        Some(edited) => {
+                            let mut section = edited.clone();
+                            section.set_block_states(block_states);
+                            section
+                        }
+                        None => Section::new(y, block_states),
+                    };
+                    result.insert_section(section);
+                }
+            }
+        }
+        merge_block_entities(&mut result, old_edited, old_plain, new_plain);
+        Ok(result)
+    }
+}
+
+// ブロックエンティティを座標ごとに3方向マージする。
+// `old_edited` と `old_plain` で異なる座標はプレイヤーが置いた・変更した・壊したものとみなして `old_edited` のものを、
+// それ以外は `new_plain` のものを使う。取り出したチャンクとマージ後のチャンクでその座標のブロックが異なるものは捨てる
+fn merge_block_entities(
+    result: &mut Chunk,
+    old_edited: &Chunk,
+    old_plain: &Chunk,
+    new_plain: &Chunk,
+) {
+    let by_pos = |chunk| -> HashMap<(i32, i32, i32), &Value> {
+        list_tag(chunk, "block_entities")
+            .iter()
+            .filter_map(|entry| Some((block_entity_pos(entry)?, entry)))
+            .collect()
+    };
+    let edited = by_pos(old_edited);
+    let plain = by_pos(old_plain);
+
+    let from_new = list_tag(new_plain, "block_entities")
+        .iter()
+        .filter(|entry| {
+            block_entity_pos(entry).is_none_or(|pos| edited.get(&pos) == plain.get(&pos))
+        })
+        .map(|entry| (entry, new_plain));
+    let from_edited = list_tag(old_edited, "block_entities")
+        .iter()
+        .filter(|entry| block_entity_pos(entry).is_some_and(|pos| plain.get(&pos) != Some(entry)))
+        .map(|entry| (entry, old_edited));
+    let merged: Vec<Value> = from_new
+        .chain(from_edited)
+        .filter(|(entry, source)| {
+            block_entity_pos(entry)
+                .is_none_or(|pos| block_name_at(result, pos) == block_name_at(source, pos))
+        })
+        .map(|(entry, _)| entry.clone())
+        .collect();
+    if list_tag(result, "block_entities") != merged.as_slice() {
+        result.set_tag("block_entities", Value::List(merged));
+    }
+}
+
+fn block_entity_pos(entry: &Value) -> Option<(i32, i32, i32)> {
+    Some((
+        int_field(entry, "x")?,
+        int_field(entry, "y")?,
+        int_field(entry, "z")?,
+    ))
+}
+
+// ワールド座標のブロックの名前。保存されていないセクションは空気とみなす
+fn block_name_at(chunk: &Chunk, (x, y, z): (i32, i32, i32)) -> &str {
+    let Ok(section_y) = i8::try_from(y.div_euclid(16)) else {
+        return "minecraft:air";
+    };
+    find_section(chunk, section_y).map_or("minecraft:air", |section| {
+        section
+            .get_palette_item(
+                x.rem_euclid(16) as usize,
+                y.rem_euclid(16) as usize,
+                z.rem_euclid(16) as usize,
+            )
+            .name()
+    })
+}
+
+fn list_tag<'a>(chunk: &'a Chunk, tag: &str) -> &'a [Value] {
+    match chunk.get_tag(tag) {
+        Some(Value::List(list)) => list,
+        _ => &[],
+    }
+}
+
+fn int_field(entry: &Value, key: &str) -> Option<i32> {
+    match entry {
+        Value::Compound(fields) => match fields.get(key) {
+            Some(Value::Int(value)) => Some(*value),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// セクションのブロックを並べる。保存されていないセクションは空気で埋まっているものとみなす
+fn section_blocks(chunk: &Chunk, y: i8) -> Vec<Block> {
+    find_section(chunk, y)
+        .map(|section| section.block_states().blocks())
+        .unwrap_or_else(|| vec![Block::new("minecraft:air"); 4096])
+}
+
+fn find_section(chunk: &Chunk, y: i8) -> Option<&Section> {
+    chunk
+        .sections()
+        .iter()
+        .find(|section| section.y() == Some(y))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 石で埋めたセクション1つのチャンクに、指定したブロックを置く
+    fn chunk(blocks: &[((usize, usize, usize), &str)]) -> Chunk {
+        let mut states = vec![Block::new("minecraft:stone"); 4096];
+        for ((x, y, z), name) in blocks {
+            states[(y * 16 + z) * 16 + x] = Block::new(*name);
+        }
+        let section = Section::new(0, Blockstates::from_blocks(&states).unwrap());
+        Chunk::new(vec![section], "minecraft:full")
+    }
+
+    fn block_entity(id: &str, (x, y, z): (i32, i32, i32), items: i32) -> Value {
+        Value::Compound(HashMap::from([
+            ("id".to_string(), Value::String(id.to_string())),
+            ("x".to_string(), Value::Int(x)),
+            ("y".to_string(), Value::Int(y)),
+            ("z".to_string(), Value::Int(z)),
+            ("Items".to_string(), Value::Int(items)),
+        ]))
+    }
+
+    fn with_list(mut chunk: Chunk, tag: &str, entries: Vec<Value>) -> Chunk {
+        chunk.set_tag(tag, Value::List(entries));
+        chunk
+    }
+
+    fn block_name(chunk: &Chunk, x: usize, y: isize, z: usize) -> String {
+        chunk.get_block(x, y, z).unwrap().name().to_string()
+    }
+
+    #[test]
+    fn test_migrate_keeps_player_placed_block() {
+        let old_plain = chunk(&[]);
+        let old_edited = chunk(&[((1, 2, 3), "minecraft:torch"), ((4, 4, 4), "minecraft:air")]);
+        let new_plain = chunk(&[((5, 5, 5), "minecraft:deepslate")]);
+
+        let migrated = DefaultChunkMigrator
+            .migrate(&old_edited, &old_plain, &new_plain)
+            .unwrap();
+
+        assert_eq!(block_name(&migrated, 1, 2, 3), "minecraft:torch");
+        // 掘られたブロックも編集として残る
+        assert_eq!(block_name(&migrated, 4, 4, 4), "minecraft:air");
+        assert_eq!(block_name(&migrated, 5, 5, 5), "minecraft:deepslate");
+        assert_eq!(block_name(&migrated, 0, 0, 0), "minecraft:stone");
+    }
+
+    #[test]
+    fn test_migrate_terrain_only_change() {
+        let old_plain = chunk(&[((7, 1, 7), "minecraft:coal_ore")]);
+        let old_edited = chunk(&[((7, 1, 7), "minecraft:coal_ore")]);
+        let new_plain = chunk(&[
+            ((7, 1, 7), "minecraft:stone"),
+            ((2, 2, 2), "minecraft:copper_ore"),
+        ]);
+
+        let migrated = DefaultChunkMigrator
+            .migrate(&old_edited, &old_plain, &new_plain)
+            .unwrap();
+
+        assert_eq!(
+            migrated.sections()[0].block_states().blocks(),
+            new_plain.sections()[0].block_states().blocks()
+        );
+    }
+
+    #[test]
+    fn test_migrate_conflict_prefers_player_edit() {
+        let old_plain = chunk(&[]);
+        let old_edited = chunk(&[((0, 0, 0), "minecraft:chest")]);
+        let new_plain = chunk(&[((0, 0, 0), "minecraft:diamond_ore")]);
+
+        let migrated = DefaultChunkMigrator
+            .migrate(&old_edited, &old_plain, &new_plain)
+            .unwrap();
+
+        assert_eq!(block_name(&migrated, 0, 0, 0), "minecraft:chest");
+    }
+
+    #[test]
+    fn test_migrate_keeps_build_in_section_missing_from_new_chunk() {
+        // 旧バージョンの地形の最上部より上にプレイヤーが建てたセクション
+        let mut tower = vec![Block::new("minecraft:air"); 4096];
+        tower[0] = Block::new("minecraft:oak_planks");
+        let mut old_edited = chunk(&[]);
+        old_edited.insert_section(Section::new(1, Blockstates::from_blocks(&tower).unwrap()));
+        let old_plain = chunk(&[]);
+        let new_plain = chunk(&[((3, 3, 3), "minecraft:deepslate")]);
+
+        let migrated = DefaultChunkMigrator
+            .migrate(&old_edited, &old_plain, &new_plain)
+            .unwrap();
+
+        assert_eq!(
+            migrated
+                .sections()
+                .iter()
+                .map(|section| section.y())
+                .collect::<Vec<_>>(),
+            vec![Some(0), Some(1)]
+        );
+        assert_eq!(block_name(&migrated, 0, 16, 0), "minecraft:oak_planks");
+        assert_eq!(block_name(&migrated, 1, 16, 0), "minecraft:air");
+        assert_eq!(block_name(&migrated, 3, 3, 3), "minecraft:deepslate");
+    }
+
+    #[test]
+    fn test_migrate_keeps_placed_chest() {
+        let spawner = block_entity("minecraft:mob_spawner", (8, 10, 8), 0);
+        let old_plain = with_list(chunk(&[]), "block_entities", vec![spawner.clone()]);
+        let chest = block_entity("minecraft:chest", (1, 2, 3), 5);
+        let old_edited = with_list(
+            chunk(&[((1, 2, 3), "minecraft:chest")]),
+            "block_entities",
+            vec![spawner.clone(), chest.clone()],
+        );
+        // 新バージョンでも同じ構造物が再生成されている
+        let new_spawner = block_entity("minecraft:trial_spawner", (8, 10, 8), 0);
+        let new_plain = with_list(chunk(&[]), "block_entities", vec![new_spawner.clone()]);
+
+        let migrated = DefaultChunkMigrator
+            .migrate(&old_edited, &old_plain, &new_plain)
+            .unwrap();
+
+        assert_eq!(block_name(&migrated, 1, 2, 3), "minecraft:chest");
+        assert_eq!(list_tag(&migrated, "block_entities"), &[new_spawner, chest]);
+    }
+
+    #[test]
+    fn test_migrate_keeps_broken_chest_removed() {
+        // プレイヤーが壊したチェストは、新バージョンで再生成されても戻さない
+        let generated = block_entity("minecraft:chest", (4, 5, 6), 3);
+        let old_plain = with_list(
+            chunk(&[((4, 5, 6), "minecraft:chest")]),
+            "block_entities",
+            vec![generated.clone()],
+        );
+        let old_edited = with_list(chunk(&[]), "block_entities", vec![]);
+        let new_plain = with_list(
+            chunk(&[((4, 5, 6), "minecraft:chest")]),
+            "block_entities",
+            vec![generated],
+        );
+
+        let migrated = DefaultChunkMigrator
+            .migrate(&old_edited, &old_plain, &new_plain)
+            .unwrap();
+
+        assert_eq!(block_name(&migrated, 4, 5, 6), "minecraft:stone");
+        assert!(list_tag(&migrated, "block_entities").is_empty());
+    }
+
+    #[test]
+    fn test_migrate_drops_block_entity_under_player_edit() {
+        // 新バージョンで生成されたチェストの位置にプレイヤーがブロックを置いていた
+        let old_plain = chunk(&[]);
+        let old_edited = chunk(&[((2, 2, 2), "minecraft:cobblestone")]);
+        let new_plain = with_list(
+            chunk(&[((2, 2, 2), "minecraft:chest")]),
+            "block_entities",
+            vec![block_entity("minecraft:chest", (2, 2, 2), 4)],
+        );
+
+        let migrated = DefaultChunkMigrator
+            .migrate(&old_edited, &old_plain, &new_plain)
+            .unwrap();
+
+        assert_eq!(block_name(&migrated, 2, 2, 2), "minecraft:cobblestone");
+        assert!(list_tag(&migrated, "block_entities").is_empty());
+    }
+
+    #[test]
+    fn test_migrate_replaces_block_entity_at_same_position() {
+        // プレイヤーが中身を変えたチェストは、再生成された同じ座標のチェストを置き換える
+        let generated = block_entity("minecraft:chest", (4, 5, 6), 3);
+        let looted = block_entity("minecraft:chest", (4, 5, 6), 0);
+        let old_plain = with_list(chunk(&[]), "block_entities", vec![generated.clone()]);
+        let old_edited = with_list(chunk(&[]), "block_entities", vec![looted.clone()]);
+        let new_plain = with_list(chunk(&[]), "block_entities", vec![generated]);
+
+        let migrated = DefaultChunkMigrator
+            .migrate(&old_edited, &old_plain, &new_plain)
+            .unwrap();
+
+        assert_eq!(list_tag(&migrated, "block_entities"), &[looted]);
+    }
+}
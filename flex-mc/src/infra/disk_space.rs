@@ -0,0 +1,149 @@
+use std::path::Path;
+
+use anyhow::{Result, bail};
+
+// 生成済みチャンク1つあたりのリージョンファイル上のサイズの見積もり
+const BYTES_PER_CHUNK: u64 = 16 * 1024;
+// サーバー1つあたりのサーバーjar・ライブラリ・ログなどのサイズの見積もり
+const BYTES_PER_SERVER: u64 = 300 * 1024 * 1024;
+// ディメンション1つあたりのlevel.datやエンティティ・POIなどのサイズの見積もり
+const BYTES_PER_DIMENSION: u64 = 16 * 1024 * 1024;
+// 空き容量がこの割合を下回るほど見積もりに近ければ警告する
+const WARNING_MARGIN_PERCENT: u64 = 20;
+
+/// 作業ディレクトリのあるボリュームの空き容量を調べる
+pub trait FreeSpaceQuery {
+    fn available_space(&self, path: &Path) -> Result<u64>;
+}
+
+pub struct DefaultFreeSpaceQuery;
+
+impl FreeSpaceQuery for DefaultFreeSpaceQuery {
+    fn available_space(&self, path: &Path) -> Result<u64> {
+        // 作業ディレクトリはまだ作られていないことがあるため、存在する親ディレクトリを調べる
+        let existing = path
+            .ancestors()
+            .find(|ancestor| ancestor.exists())
+            .unwrap_or(path);
+        Ok(fs4::available_space(existing)?)
+    }
+}
+
+/// 生成中に必要になるディスク容量の見積もり
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiskUsageEstimate {
+    /// サーバー本体が使う容量
+    pub server_bytes: u64,
+    /// 中間サーバーのワールドと出力先のワールドが使う容量
+    pub world_bytes: u64,
+}
+
+impl DiskUsageEstimate {
+    /// `chunks_per_dimension` のチャンクを `server_count` 個の中間サーバーで生成し、
+    /// 出力先のワールドにまとめる場合の最大使用量を見積もる
+    pub fn new(chunks_per_dimension: &[usize], server_count: usize) -> Self {
+        let chunk_count: u64 = chunks_per_dimension.iter().map(|&n| n as u64).sum();
+        let world =
+            chunk_count * BYTES_PER_CHUNK + chunks_per_dimension.len() as u64 * BYTES_PER_DIMENSION;
+        DiskUsageEstimate {
+            server_bytes: server_count as u64 * BYTES_PER_SERVER,
+            // 中間サーバーそれぞれのワールドと出力先のワールドが同時に存在する
+            world_bytes: world * (server_count as u64 + 1),
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.server_bytes + self.world_bytes
+    }
+}
+
+/// 見積もりが作業ディレクトリの空き容量に収まるか確認する。
+/// 収まらなければエラーにし、余裕が少なければ警告する
+pub fn check_disk_space(
+    query: &dyn FreeSpaceQuery,
+    work_dir: &Path,
+    estimate: &DiskUsageEstimate,
+) -> Result<()> {
+    let available = query.available_space(work_dir)?;
+    let required = estimate.total();
+    if required > available {
+        bail!(
+            "Not enough disk space in {}: generation needs about {} MiB but only {} MiB is available",
+            work_dir.display(),
+            required / 1024 / 1024,
+            available / 1024 / 1024
+        );
+    }
+    if (available - required) * 100 < available * WARNING_MARGIN_PERCENT {
+        println!(
+            "Warning: generation needs about {} MiB of the {} MiB available in {}",
+            required / 1024 / 1024,
+            available / 1024 / 1024,
+            work_dir.display()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct MockFreeSpaceQuery(u64);
+
+    impl FreeSpaceQuery for MockFreeSpaceQuery {
+        fn available_space(&self, _path: &Path) -> Result<u64> {
+            Ok(self.0)
+        }
+    }
+
+    #[test]
+    fn test_estimate_disk_usage() {
+        let estimate = DiskUsageEstimate::new(&[1000, 500], 3);
+        assert_eq!(estimate.server_bytes, 3 * BYTES_PER_SERVER);
+        assert_eq!(
+            estimate.world_bytes,
+            4 * (1500 * BYTES_PER_CHUNK + 2 * BYTES_PER_DIMENSION)
+        );
+        assert_eq!(
+            estimate.total(),
+            estimate.server_bytes + estimate.world_bytes
+        );
+    }
+
+    #[test]
+    fn test_check_disk_space() {
+        let estimate = DiskUsageEstimate::new(&[10_000], 3);
+        let work_dir = Path::new("/work");
+
+        let err = check_disk_space(
+            &MockFreeSpaceQuery(estimate.total() - 1),
+            work_dir,
+            &estimate,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("Not enough disk space"), "{}", err);
+
+        // ぎりぎり収まる場合は警告のみ
+        assert!(
+            check_disk_space(&MockFreeSpaceQuery(estimate.total()), work_dir, &estimate).is_ok()
+        );
+        assert!(
+            check_disk_space(
+                &MockFreeSpaceQuery(estimate.total() * 10),
+                work_dir,
+                &estimate
+            )
+            .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_default_free_space_query_missing_dir() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let available = DefaultFreeSpaceQuery
+            .available_space(&temp_dir.path().join("not/created/yet"))
+            .unwrap();
+        assert!(available > 0);
+    }
+}
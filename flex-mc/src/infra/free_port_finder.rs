@@ -1,5 +1,60 @@
+/// 予約した空きポート。保持している間はポートを束縛したままにし、他のプロセスに割り当てられないようにする。
+/// 束縛には `SO_REUSEADDR` を設定するため、解放前でもサーバーは同じポートで待ち受けられる
+pub struct PortReservation {
+    port: u16,
+    socket: Option<tokio::net::TcpSocket>,
+}
+
+impl PortReservation {
+    /// ポートを束縛せずに予約として扱う（モックなど、実際のポートを使わない場合向け）
+    pub fn unheld(port: u16) -> Self {
+        PortReservation { port, socket: None }
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// ポートを解放する。サーバーが起動を終えて待ち受けを始めてから呼び出す
+    pub fn release(self) -> u16 {
+        drop(self.socket);
+        self.port
+    }
+}
+
 pub trait FreePortFinder {
     fn find_free_port(&self, host: std::net::IpAddr) -> Result<u16, std::io::Error>;
+
+    /// 空きポートを探し、サーバーに渡すまで束縛しておく
+    fn reserve_free_port(&self, host: std::net::IpAddr) -> Result<PortReservation, std::io::Error> {
+        Ok(PortReservation::unheld(self.find_free_port(host)?))
+    }
+
+    /// 互いに異なる空きポートを `count` 個探す。複数のサーバーを同時に起動するときに使う
+    fn find_free_ports(
+        &self,
+        host: std::net::IpAddr,
+        count: usize,
+    ) -> Result<Vec<u16>, std::io::Error> {
+        let mut ports = Vec::with_capacity(count);
+        // 同じポートばかり返される場合に終わらなくならないよう、試行回数を制限する
+        for _ in 0..count * 10 {
+            if ports.len() == count {
+                break;
+            }
+            let port = self.find_free_port(host)?;
+            if !ports.contains(&port) {
+                ports.push(port);
+            }
+        }
+        if ports.len() < count {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AddrInUse,
+                format!("Could not find {} distinct free ports", count),
+            ));
+        }
+        Ok(ports)
+    }
 }
 
 pub struct DefaultFreePortFinder;
@@ -12,4 +67,119 @@ impl FreePortFinder for DefaultFreePortFinder {
         };
         Ok(listener.local_addr()?.port())
     }
+
+    fn reserve_free_port(&self, host: std::net::IpAddr) -> Result<PortReservation, std::io::Error> {
+        let socket = match host {
+            std::net::IpAddr::V4(_) => tokio::net::TcpSocket::new_v4()?,
+            std::net::IpAddr::V6(_) => tokio::net::TcpSocket::new_v6()?,
+        };
+        socket.set_reuseaddr(true)?;
+        // 待ち受けはしないため、サーバーは同じポートで待ち受けを始められる
+        socket.bind((host, 0).into())?;
+        Ok(PortReservation {
+            port: socket.local_addr()?.port(),
+            socket: Some(socket),
+        })
+    }
+
+    fn find_free_ports(
+        &self,
+        host: std::net::IpAddr,
+        count: usize,
+    ) -> Result<Vec<u16>, std::io::Error> {
+        // すべてのポートを探し終えるまでリスナーを保持し、同じポートが2度割り当てられないようにする
+        let listeners = (0..count)
+            .map(|_| std::net::TcpListener::bind((host, 0)))
+            .collect::<Result<Vec<_>, _>>()?;
+        listeners
+            .iter()
+            .map(|listener| Ok(listener.local_addr()?.port()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        collections::HashSet,
+        net::{IpAddr, Ipv4Addr},
+    };
+
+    #[test]
+    fn test_find_free_ports_distinct() {
+        let ports = DefaultFreePortFinder
+            .find_free_ports(IpAddr::V4(Ipv4Addr::LOCALHOST), 3)
+            .unwrap();
+        assert_eq!(ports.len(), 3);
+        assert_eq!(ports.iter().collect::<HashSet<_>>().len(), 3);
+        assert!(ports.iter().all(|&port| port != 0));
+    }
+
+    #[test]
+    fn test_concurrent_reservations_are_distinct() {
+        let threads: Vec<_> = (0..2)
+            .map(|_| {
+                std::thread::spawn(|| {
+                    (0..20)
+                        .map(|_| {
+                            DefaultFreePortFinder
+                                .reserve_free_port(IpAddr::V4(Ipv4Addr::LOCALHOST))
+                                .unwrap()
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        // すべての予約を保持したまま比べる
+        let reservations: Vec<PortReservation> = threads
+            .into_iter()
+            .flat_map(|thread| thread.join().unwrap())
+            .collect();
+        let ports: HashSet<u16> = reservations.iter().map(|r| r.port()).collect();
+        assert_eq!(ports.len(), reservations.len());
+        assert!(reservations.iter().all(|r| r.socket.is_some()));
+
+        // 解放したポートでサーバーが待ち受けられる
+        let port = reservations.into_iter().next().unwrap().release();
+        std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, port)).unwrap();
+    }
+
+    #[test]
+    fn test_reserve_free_port_default_is_unheld() {
+        let finder = RepeatingPortFinder(std::sync::Mutex::new(vec![25565]));
+        let reservation = finder
+            .reserve_free_port(IpAddr::V4(Ipv4Addr::LOCALHOST))
+            .unwrap();
+        assert_eq!(reservation.port(), 25565);
+        assert!(reservation.socket.is_none());
+    }
+
+    // 同じポートを何度か返してから別のポートを返す
+    struct RepeatingPortFinder(std::sync::Mutex<Vec<u16>>);
+
+    impl FreePortFinder for RepeatingPortFinder {
+        fn find_free_port(&self, _host: IpAddr) -> Result<u16, std::io::Error> {
+            Ok(self.0.lock().unwrap().remove(0))
+        }
+    }
+
+    #[test]
+    fn test_find_free_ports_default_skips_duplicates() {
+        let finder = RepeatingPortFinder(std::sync::Mutex::new(vec![
+            25565, 25565, 25566, 25565, 25567,
+        ]));
+        assert_eq!(
+            finder
+                .find_free_ports(IpAddr::V4(Ipv4Addr::LOCALHOST), 3)
+                .unwrap(),
+            vec![25565, 25566, 25567]
+        );
+
+        let finder = RepeatingPortFinder(std::sync::Mutex::new(vec![25565; 100]));
+        let err = finder
+            .find_free_ports(IpAddr::V4(Ipv4Addr::LOCALHOST), 2)
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::AddrInUse);
+    }
 }
@@ -0,0 +1,503 @@
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+};
+
+use anyhow::Result;
+use fastnbt::Value;
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+use serde::{Serialize, de::DeserializeOwned};
+
+/// NBTを読み込むときの上限。壊れたファイルや悪意のあるファイルの長さフィールドによって
+/// 巨大な領域を確保したり、深い入れ子でスタックを使い切ったりしないようにする
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NbtReadLimits {
+    /// 読み込む（gzipの場合は展開後の）バイト数の上限
+    pub max_bytes: usize,
+    /// 配列・リストの要素数の上限
+    pub max_elements: usize,
+    /// Compound・Listの入れ子の深さの上限
+    pub max_depth: usize,
+}
+
+impl Default for NbtReadLimits {
+    fn default() -> Self {
+        NbtReadLimits {
+            max_bytes: 256 * 1024 * 1024,
+            max_elements: 16 * 1024 * 1024,
+            // マインクラフト本体と同じ
+            max_depth: 512,
+        }
+    }
+}
+
+/// 非圧縮のNBTを読み込む
+pub fn parse_nbt<R: Read, T: DeserializeOwned>(reader: R) -> Result<T> {
+    parse_nbt_with_limits(reader, &NbtReadLimits::default())
+}
+
+/// 上限を指定して非圧縮のNBTを読み込む。上限を超えた場合は `InvalidData` のエラーを返す
+pub fn parse_nbt_with_limits<R: Read, T: DeserializeOwned>(
+    reader: R,
+    limits: &NbtReadLimits,
+) -> Result<T> {
+    let mut bytes = Vec::new();
+    reader
+        .take((limits.max_bytes as u64).saturating_add(1))
+        .read_to_end(&mut bytes)?;
+    if bytes.len() > limits.max_bytes {
+        return Err(invalid_data(format!("NBT data exceeds {} bytes", limits.max_bytes)).into());
+    }
+    parse_nbt_bytes_with_limits(&bytes, limits)
+}
+
+/// メモリ上の非圧縮のNBTを読み込む
+pub fn parse_nbt_bytes<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+    parse_nbt_bytes_with_limits(bytes, &NbtReadLimits::default())
+}
+
+fn parse_nbt_bytes_with_limits<T: DeserializeOwned>(
+    bytes: &[u8],
+    limits: &NbtReadLimits,
+) -> Result<T> {
+    // fastnbtは長さフィールドをそのまま信用し、入れ子の深さも制限しないため、先に検査する
+    NbtValidator::new(bytes, limits).validate()?;
+    Ok(fastnbt::from_bytes(bytes)?)
+}
+
+/// 非圧縮のNBTを書き込む
+pub fn write_nbt<W: Write, T: Serialize>(writer: W, value: &T) -> Result<()> {
+    Ok(fastnbt::to_writer(writer, value)?)
+}
+
+/// gzip圧縮されたNBT（level.datなど）を読み込む
+pub fn parse_nbt_gzip<R: Read, T: DeserializeOwned>(reader: R) -> Result<T> {
+    parse_nbt(GzDecoder::new(reader))
+}
+
+/// NBTをgzip圧縮して書き込む
+pub fn write_nbt_gzip<W: Write, T: Serialize>(writer: W, value: &T) -> Result<()> {
+    let mut encoder = GzEncoder::new(writer, Compression::default());
+    write_nbt(&mut encoder, value)?;
+    encoder.finish()?;
+    Ok(())
+}
+
+fn invalid_data(message: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+const TAG_END: u8 = 0;
+const TAG_BYTE: u8 = 1;
+const TAG_SHORT: u8 = 2;
+const TAG_INT: u8 = 3;
+const TAG_LONG: u8 = 4;
+const TAG_FLOAT: u8 = 5;
+const TAG_DOUBLE: u8 = 6;
+const TAG_BYTE_ARRAY: u8 = 7;
+const TAG_STRING: u8 = 8;
+const TAG_LIST: u8 = 9;
+const TAG_COMPOUND: u8 = 10;
+const TAG_INT_ARRAY: u8 = 11;
+const TAG_LONG_ARRAY: u8 = 12;
+
+// 読み込み途中のCompound・List
+enum Frame {
+    Compound,
+    List { tag: u8, remaining: usize },
+}
+
+// NBTのバイト列を読み進めて、長さフィールドと入れ子の深さが上限に収まっているか確かめる。
+// 深い入れ子でもスタックを使い切らないよう、再帰せずに明示的なスタックで辿る
+struct NbtValidator<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    limits: &'a NbtReadLimits,
+    stack: Vec<Frame>,
+}
+
+impl<'a> NbtValidator<'a> {
+    fn new(bytes: &'a [u8], limits: &'a NbtReadLimits) -> Self {
+        NbtValidator {
+            bytes,
+            pos: 0,
+            limits,
+            stack: Vec::new(),
+        }
+    }
+
+    fn validate(mut self) -> std::io::Result<()> {
+        let tag = self.read_u8()?;
+        if tag == TAG_END {
+            return Ok(());
+        }
+        self.skip_string()?;
+        self.enter(tag)?;
+        while let Some(frame) = self.stack.last_mut() {
+            match frame {
+                Frame::Compound => {
+                    let tag = self.read_u8()?;
+                    if tag == TAG_END {
+                        self.stack.pop();
+                        continue;
+                    }
+                    self.skip_string()?;
+                    self.enter(tag)?;
+                }
+                Frame::List { tag, remaining } => {
+                    if *remaining == 0 {
+                        self.stack.pop();
+                        continue;
+                    }
+                    *remaining -= 1;
+                    let tag = *tag;
+                    self.enter(tag)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // 値を1つ読み飛ばす。Compound・Listは中身を読むためにスタックに積む
+    fn enter(&mut self, tag: u8) -> std::io::Result<()> {
+        match tag {
+            TAG_BYTE => self.skip(1),
+            TAG_SHORT => self.skip(2),
+            TAG_INT | TAG_FLOAT => self.skip(4),
+            TAG_LONG | TAG_DOUBLE => self.skip(8),
+            TAG_BYTE_ARRAY => self.skip_array(1),
+            TAG_INT_ARRAY => self.skip_array(4),
+            TAG_LONG_ARRAY => self.skip_array(8),
+            TAG_STRING => self.skip_string(),
+            TAG_LIST => {
+                let tag = self.read_u8()?;
+                let remaining = self.read_len()?;
+                self.push(Frame::List { tag, remaining })
+            }
+            TAG_COMPOUND => self.push(Frame::Compound),
+            other => Err(invalid_data(format!(
+                "Unknown NBT tag {} at byte {}",
+                other, self.pos
+            ))),
+        }
+    }
+
+    fn push(&mut self, frame: Frame) -> std::io::Result<()> {
+        if self.stack.len() >= self.limits.max_depth {
+            return Err(invalid_data(format!(
+                "NBT nesting exceeds depth {}",
+                self.limits.max_depth
+            )));
+        }
+        self.stack.push(frame);
+        Ok(())
+    }
+
+    fn skip_array(&mut self, element_size: usize) -> std::io::Result<()> {
+        let len = self.read_len()?;
+        // 長さはi32に収まるため、64ビット環境では桁あふれしない
+        self.skip(len.saturating_mul(element_size))
+    }
+
+    fn skip_string(&mut self) -> std::io::Result<()> {
+        let len = self.take(2)?;
+        let len = u16::from_be_bytes([len[0], len[1]]) as usize;
+        self.skip(len)
+    }
+
+    // 配列・リストの要素数を読み、負の値や上限を超える値を弾く
+    fn read_len(&mut self) -> std::io::Result<usize> {
+        let pos = self.pos;
+        let len = self.take(4)?;
+        let len = i32::from_be_bytes([len[0], len[1], len[2], len[3]]);
+        if len < 0 || len as usize > self.limits.max_elements {
+            return Err(invalid_data(format!(
+                "Invalid NBT length {} at byte {} (limit {})",
+                len, pos, self.limits.max_elements
+            )));
+        }
+        Ok(len as usize)
+    }
+
+    fn read_u8(&mut self) -> std::io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn skip(&mut self, n: usize) -> std::io::Result<()> {
+        self.take(n).map(|_| ())
+    }
+
+    fn take(&mut self, n: usize) -> std::io::Result<&'a [u8]> {
+        let bytes = self.bytes;
+        if bytes.len() - self.pos < n {
+            return Err(invalid_data(format!(
+                "NBT data ends early: {} bytes needed at byte {} but only {} remain",
+                n,
+                self.pos,
+                bytes.len() - self.pos
+            )));
+        }
+        let taken = &bytes[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(taken)
+    }
+}
+
+/// `fastnbt::Value` から型を確かめて値を取り出すヘルパー。型が異なる場合は `None` を返す
+pub trait NbtValueExt {
+    fn as_compound(&self) -> Option<&HashMap<String, Value>>;
+    fn as_list(&self) -> Option<&[Value]>;
+    /// Byte・Short・Intを `i32` として取り出す
+    fn as_i32(&self) -> Option<i32>;
+    fn as_str(&self) -> Option<&str>;
+    /// `Level.Sections[0].Y` のように、`.` 区切りのキーと `[添字]` で辿った先の値を返す
+    fn get_path(&self, path: &str) -> Option<&Value>;
+}
+
+impl NbtValueExt for Value {
+    fn as_compound(&self) -> Option<&HashMap<String, Value>> {
+        match self {
+            Value::Compound(fields) => Some(fields),
+            _ => None,
+        }
+    }
+
+    fn as_list(&self) -> Option<&[Value]> {
+        match self {
+            Value::List(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    fn as_i32(&self) -> Option<i32> {
+        match self {
+            Value::Byte(v) => Some(*v as i32),
+            Value::Short(v) => Some(*v as i32),
+            Value::Int(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn get_path(&self, path: &str) -> Option<&Value> {
+        let mut current = self;
+        for segment in path.split('.') {
+            // `Sections[0][1]` をキー `Sections` と `[0][1]` に分ける
+            let (key, mut indexes) = match segment.find('[') {
+                Some(i) => segment.split_at(i),
+                None => (segment, ""),
+            };
+            if !key.is_empty() {
+                current = current.as_compound()?.get(key)?;
+            }
+            while let Some(rest) = indexes.strip_prefix('[') {
+                let (index, rest) = rest.split_once(']')?;
+                current = current
+                    .as_list()?
+                    .get(index.trim().parse::<usize>().ok()?)?;
+                indexes = rest;
+            }
+            if !indexes.is_empty() {
+                return None;
+            }
+        }
+        Some(current)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gzip_round_trip() {
+        let mut data = HashMap::new();
+        data.insert("DataVersion".to_string(), Value::Int(3465));
+        data.insert("LevelName".to_string(), Value::String("world".to_string()));
+        let mut root = HashMap::new();
+        root.insert("Data".to_string(), Value::Compound(data));
+        let value = Value::Compound(root);
+
+        let mut bytes = Vec::new();
+        write_nbt_gzip(&mut bytes, &value).unwrap();
+        // gzipのマジックナンバー
+        assert_eq!(&bytes[..2], &[0x1f, 0x8b]);
+
+        let parsed: Value = parse_nbt_gzip(bytes.as_slice()).unwrap();
+        assert_eq!(parsed, value);
+    }
+
+    fn assert_invalid_data<T: std::fmt::Debug>(result: Result<T>) {
+        let err = result.unwrap_err();
+        let kind = err.downcast_ref::<std::io::Error>().map(|e| e.kind());
+        assert_eq!(kind, Some(std::io::ErrorKind::InvalidData), "{}", err);
+    }
+
+    #[test]
+    fn test_parse_rejects_oversized_length() {
+        // 名前が空のルートCompoundに、20億要素と称するByteArrayが1つだけある
+        let mut bytes = vec![TAG_COMPOUND, 0, 0, TAG_BYTE_ARRAY, 0, 1, b'a'];
+        bytes.extend_from_slice(&2_000_000_000i32.to_be_bytes());
+        bytes.extend_from_slice(&[1, 2, 3, TAG_END]);
+        assert_invalid_data(parse_nbt::<_, Value>(bytes.as_slice()));
+
+        // 上限内でもデータが足りなければ確保しない
+        let limits = NbtReadLimits {
+            max_elements: usize::MAX,
+            ..NbtReadLimits::default()
+        };
+        assert_invalid_data(parse_nbt_with_limits::<_, Value>(bytes.as_slice(), &limits));
+
+        // 負の長さのリスト
+        let mut bytes = vec![TAG_COMPOUND, 0, 0, TAG_LIST, 0, 1, b'l', TAG_INT];
+        bytes.extend_from_slice(&(-1i32).to_be_bytes());
+        bytes.push(TAG_END);
+        assert_invalid_data(parse_nbt::<_, Value>(bytes.as_slice()));
+    }
+
+    #[test]
+    fn test_parse_rejects_deep_nesting() {
+        // 名前が空のCompoundを10万段入れ子にする
+        let depth = 100_000;
+        let mut bytes = vec![TAG_COMPOUND, 0, 0];
+        for _ in 0..depth {
+            bytes.extend_from_slice(&[TAG_COMPOUND, 0, 0]);
+        }
+        bytes.extend(std::iter::repeat_n(TAG_END, depth + 1));
+        assert_invalid_data(parse_nbt::<_, Value>(bytes.as_slice()));
+
+        let limits = NbtReadLimits {
+            max_depth: 4,
+            ..NbtReadLimits::default()
+        };
+        let nested = crate::infra::snbt::from_snbt("{a:{b:{c:[{d:1b}]}}}").unwrap();
+        let mut bytes = Vec::new();
+        write_nbt(&mut bytes, &nested).unwrap();
+        assert_eq!(parse_nbt::<_, Value>(bytes.as_slice()).unwrap(), nested);
+        assert_invalid_data(parse_nbt_with_limits::<_, Value>(bytes.as_slice(), &limits));
+    }
+
+    #[test]
+    fn test_parse_rejects_input_over_max_bytes() {
+        let value = crate::infra::snbt::from_snbt("{data:[B;1b,2b,3b,4b]}").unwrap();
+        let mut bytes = Vec::new();
+        write_nbt(&mut bytes, &value).unwrap();
+        let limits = NbtReadLimits {
+            max_bytes: bytes.len() - 1,
+            ..NbtReadLimits::default()
+        };
+        assert_invalid_data(parse_nbt_with_limits::<_, Value>(bytes.as_slice(), &limits));
+        let limits = NbtReadLimits {
+            max_bytes: bytes.len(),
+            ..NbtReadLimits::default()
+        };
+        assert_eq!(
+            parse_nbt_with_limits::<_, Value>(bytes.as_slice(), &limits).unwrap(),
+            value
+        );
+    }
+
+    #[test]
+    fn test_empty_list_round_trip() {
+        let value = Value::Compound(HashMap::from([("l".to_string(), Value::List(vec![]))]));
+        let mut bytes = Vec::new();
+        write_nbt(&mut bytes, &value).unwrap();
+        // 空のリストは要素の型をTAG_End、長さを0として書き込む
+        assert_eq!(&bytes[3..12], &[TAG_LIST, 0, 1, b'l', TAG_END, 0, 0, 0, 0]);
+        assert_eq!(parse_nbt::<_, Value>(bytes.as_slice()).unwrap(), value);
+    }
+
+    #[test]
+    fn test_long_list_round_trip() {
+        let value = Value::Compound(HashMap::from([(
+            "l".to_string(),
+            Value::List(vec![
+                Value::Long(i64::MIN),
+                Value::Long(0),
+                Value::Long(i64::MAX),
+            ]),
+        )]));
+        let mut bytes = Vec::new();
+        write_nbt(&mut bytes, &value).unwrap();
+        // LongArrayではなく要素の型がTAG_LongのListになる
+        assert_eq!(&bytes[3..12], &[TAG_LIST, 0, 1, b'l', TAG_LONG, 0, 0, 0, 3]);
+        assert_eq!(parse_nbt::<_, Value>(bytes.as_slice()).unwrap(), value);
+    }
+
+    #[test]
+    fn test_modified_utf8_strings() {
+        let text = "a\0b😀";
+        let value = Value::Compound(HashMap::from([(
+            "s".to_string(),
+            Value::String(text.to_string()),
+        )]));
+        let mut bytes = Vec::new();
+        write_nbt(&mut bytes, &value).unwrap();
+        // NULは0xC0 0x80、U+FFFFを超える文字はサロゲートペアをそれぞれ3バイトで書き込む
+        assert_eq!(
+            &bytes[7..],
+            &[
+                0, 10, b'a', 0xC0, 0x80, b'b', 0xED, 0xA0, 0xBD, 0xED, 0xB8, 0x80, TAG_END
+            ]
+        );
+        assert_eq!(parse_nbt::<_, Value>(bytes.as_slice()).unwrap(), value);
+    }
+
+    #[test]
+    fn test_get_path() {
+        let value = crate::infra::snbt::from_snbt(
+            r#"{Level:{Status:"full",Sections:[{Y:-4b,palette:[{Name:"stone"}]},{Y:5b}]}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            value.get_path("Level.Sections[0].Y").unwrap().as_i32(),
+            Some(-4)
+        );
+        assert_eq!(
+            value.get_path("Level.Sections[1].Y").unwrap().as_i32(),
+            Some(5)
+        );
+        assert_eq!(
+            value
+                .get_path("Level.Sections[0].palette[0].Name")
+                .and_then(|v| v.as_str()),
+            Some("stone")
+        );
+        assert_eq!(
+            value
+                .get_path("Level.Sections")
+                .unwrap()
+                .as_list()
+                .unwrap()
+                .len(),
+            2
+        );
+        assert!(value.get_path("Level").unwrap().as_compound().is_some());
+
+        // 存在しないキー・添字や型の不一致は None
+        assert!(value.get_path("Level.Missing").is_none());
+        assert!(value.get_path("Level.Sections[2]").is_none());
+        assert!(value.get_path("Level.Status[0]").is_none());
+        assert!(value.get_path("Level.Status.Y").is_none());
+        assert!(value.get_path("Level.Sections[x]").is_none());
+        assert_eq!(value.get_path("Level.Status").unwrap().as_i32(), None);
+        assert_eq!(
+            value.get_path("Level.Sections[0].Y").unwrap().as_str(),
+            None
+        );
+        assert!(
+            value
+                .get_path("Level.Sections[0]")
+                .unwrap()
+                .as_list()
+                .is_none()
+        );
+    }
+}
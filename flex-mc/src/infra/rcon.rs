@@ -0,0 +1,262 @@
+use std::net::SocketAddr;
+
+use anyhow::{Result, bail};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::TcpStream,
+};
+
+// パケット種別
+const PACKET_TYPE_RESPONSE: i32 = 0;
+const PACKET_TYPE_COMMAND: i32 = 2;
+const PACKET_TYPE_AUTH_RESPONSE: i32 = 2;
+const PACKET_TYPE_AUTH: i32 = 3;
+
+// 長さを除いたパケットの最大サイズ（マインクラフトサーバーの受信バッファに合わせる）
+const MAX_PACKET_SIZE: usize = 4096 + 10;
+
+/// RCONのパケット
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RconPacket {
+    pub request_id: i32,
+    pub packet_type: i32,
+    pub body: String,
+}
+
+impl RconPacket {
+    /// `長さ, リクエストID, 種別, 本文, 終端の0x00 0x00` の形式にエンコードする。数値はリトルエンディアン
+    pub fn encode(&self) -> Vec<u8> {
+        let body = self.body.as_bytes();
+        let length = (4 + 4 + body.len() + 2) as i32;
+        let mut buf = Vec::with_capacity(4 + length as usize);
+        buf.extend_from_slice(&length.to_le_bytes());
+        buf.extend_from_slice(&self.request_id.to_le_bytes());
+        buf.extend_from_slice(&self.packet_type.to_le_bytes());
+        buf.extend_from_slice(body);
+        buf.extend_from_slice(&[0, 0]);
+        buf
+    }
+
+    pub async fn read_from<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Self> {
+        let length = reader.read_i32_le().await?;
+        if length < 10 || length as usize > MAX_PACKET_SIZE {
+            bail!("Invalid RCON packet length: {}", length);
+        }
+        let mut buf = vec![0u8; length as usize];
+        reader.read_exact(&mut buf).await?;
+        if buf[buf.len() - 2..] != [0, 0] {
+            bail!("RCON packet is not null-terminated");
+        }
+        let request_id = i32::from_le_bytes(buf[0..4].try_into()?);
+        let packet_type = i32::from_le_bytes(buf[4..8].try_into()?);
+        let body = String::from_utf8_lossy(&buf[8..buf.len() - 2]).to_string();
+        Ok(RconPacket {
+            request_id,
+            packet_type,
+            body,
+        })
+    }
+}
+
+/// RCONでマインクラフトサーバーにコマンドを送るクライアント。
+/// 標準入力に接続できない、切り離して起動されたサーバーの操作に使う
+pub struct RconClient<S = TcpStream> {
+    stream: S,
+    next_request_id: i32,
+}
+
+impl RconClient<TcpStream> {
+    /// `server.properties` の `rcon.port` に接続し、`rcon.password` で認証する
+    pub async fn connect(addr: SocketAddr, password: &str) -> Result<Self> {
+        let stream = TcpStream::connect(addr).await?;
+        Self::authenticate(stream, password).await
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> RconClient<S> {
+    /// 接続済みのストリーム上で認証する
+    pub async fn authenticate(stream: S, password: &str) -> Result<Self> {
+        let mut client = RconClient {
+            stream,
+            next_request_id: 1,
+        };
+        let request_id = client.send(PACKET_TYPE_AUTH, password).await?;
+        loop {
+            let response = RconPacket::read_from(&mut client.stream).await?;
+            if response.packet_type != PACKET_TYPE_AUTH_RESPONSE {
+                // 認証応答の前に空の応答を返すサーバーもある
+                continue;
+            }
+            if response.request_id == -1 {
+                bail!("RCON authentication failed");
+            }
+            if response.request_id != request_id {
+                bail!(
+                    "Unexpected RCON auth response id: {} (expected {})",
+                    response.request_id,
+                    request_id
+                );
+            }
+            return Ok(client);
+        }
+    }
+
+    /// コマンドを実行し、その出力を返す
+    pub async fn command(&mut self, command: &str) -> Result<String> {
+        let request_id = self.send(PACKET_TYPE_COMMAND, command).await?;
+        let response = RconPacket::read_from(&mut self.stream).await?;
+        if response.packet_type != PACKET_TYPE_RESPONSE || response.request_id != request_id {
+            bail!(
+                "Unexpected RCON response: type {}, id {} (expected id {})",
+                response.packet_type,
+                response.request_id,
+                request_id
+            );
+        }
+        Ok(response.body)
+    }
+
+    /// サーバーを停止する。停止したサーバーは応答を返さずに切断することがある
+    pub async fn stop(mut self) -> Result<()> {
+        self.send(PACKET_TYPE_COMMAND, "stop").await?;
+        Ok(())
+    }
+
+    async fn send(&mut self, packet_type: i32, body: &str) -> Result<i32> {
+        let request_id = self.next_request_id;
+        self.next_request_id += 1;
+        let packet = RconPacket {
+            request_id,
+            packet_type,
+            body: body.to_string(),
+        };
+        self.stream.write_all(&packet.encode()).await?;
+        self.stream.flush().await?;
+        Ok(request_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packet_encode() {
+        let packet = RconPacket {
+            request_id: 1,
+            packet_type: PACKET_TYPE_AUTH,
+            body: "pw".to_string(),
+        };
+        assert_eq!(
+            packet.encode(),
+            vec![
+                12, 0, 0, 0, // 長さ
+                1, 0, 0, 0, // リクエストID
+                3, 0, 0, 0, // 種別
+                b'p', b'w', // 本文
+                0, 0
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_packet_roundtrip() {
+        let packet = RconPacket {
+            request_id: -1,
+            packet_type: PACKET_TYPE_RESPONSE,
+            body: "There are 0 of a max of 20 players online".to_string(),
+        };
+        let encoded = packet.encode();
+        let decoded = RconPacket::read_from(&mut encoded.as_slice())
+            .await
+            .unwrap();
+        assert_eq!(decoded, packet);
+    }
+
+    // 受信したパケットを記録し、認証とコマンドに応答するモックRCONサーバー
+    async fn mock_rcon_server<S: AsyncRead + AsyncWrite + Unpin>(
+        mut stream: S,
+        password: &str,
+    ) -> Vec<RconPacket> {
+        let mut received = Vec::new();
+        while let Ok(packet) = RconPacket::read_from(&mut stream).await {
+            let response = match packet.packet_type {
+                PACKET_TYPE_AUTH => RconPacket {
+                    request_id: if packet.body == password {
+                        packet.request_id
+                    } else {
+                        -1
+                    },
+                    packet_type: PACKET_TYPE_AUTH_RESPONSE,
+                    body: String::new(),
+                },
+                _ => RconPacket {
+                    request_id: packet.request_id,
+                    packet_type: PACKET_TYPE_RESPONSE,
+                    body: format!("ran {}", packet.body),
+                },
+            };
+            received.push(packet);
+            // 停止後はクライアントが切断していることがある
+            if stream.write_all(&response.encode()).await.is_err() {
+                break;
+            }
+        }
+        received
+    }
+
+    #[tokio::test]
+    async fn test_rcon_client_auth_and_command() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            mock_rcon_server(stream, "secret").await
+        });
+
+        let mut client = RconClient::connect(addr, "secret").await.unwrap();
+        assert_eq!(
+            client.command("time set 6000").await.unwrap(),
+            "ran time set 6000"
+        );
+        client.stop().await.unwrap();
+
+        let received = server.await.unwrap();
+        assert_eq!(
+            received,
+            vec![
+                RconPacket {
+                    request_id: 1,
+                    packet_type: PACKET_TYPE_AUTH,
+                    body: "secret".to_string(),
+                },
+                RconPacket {
+                    request_id: 2,
+                    packet_type: PACKET_TYPE_COMMAND,
+                    body: "time set 6000".to_string(),
+                },
+                RconPacket {
+                    request_id: 3,
+                    packet_type: PACKET_TYPE_COMMAND,
+                    body: "stop".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rcon_client_wrong_password() {
+        let (client_stream, server_stream) = tokio::io::duplex(4096);
+        let server = tokio::spawn(mock_rcon_server(server_stream, "secret"));
+
+        let result = RconClient::authenticate(client_stream, "wrong").await;
+        assert!(
+            result
+                .err()
+                .unwrap()
+                .to_string()
+                .contains("authentication failed")
+        );
+        server.await.unwrap();
+    }
+}
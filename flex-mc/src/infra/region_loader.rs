@@ -1,16 +1,187 @@
+use crate::infra::nbt::{parse_nbt_bytes, parse_nbt_gzip};
 use anyhow::Result;
 use fastanvil;
 use fastnbt::Value;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, fs::File, path::PathBuf, sync::RwLock};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{File, OpenOptions},
+    io::{Read, Seek, Write},
+    path::PathBuf,
+    sync::RwLock,
+};
+
+/// ディメンションの種類
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum McDimension {
+    Overworld,
+    Nether,
+    TheEnd,
+    /// データパックなどで追加されたディメンション（例: `mymod:mining`）
+    Custom(String),
+}
+
+impl McDimension {
+    /// ディメンションIDから作成する。名前空間が省略された場合は `minecraft` とみなす
+    pub fn from_id(id: &str) -> Self {
+        match id {
+            "minecraft:overworld" | "overworld" => McDimension::Overworld,
+            "minecraft:the_nether" | "the_nether" => McDimension::Nether,
+            "minecraft:the_end" | "the_end" => McDimension::TheEnd,
+            other => McDimension::Custom(other.to_string()),
+        }
+    }
+
+    /// (名前空間, パス) に分解したディメンションID
+    pub fn namespaced_id(&self) -> (&str, &str) {
+        match self {
+            McDimension::Overworld => ("minecraft", "overworld"),
+            McDimension::Nether => ("minecraft", "the_nether"),
+            McDimension::TheEnd => ("minecraft", "the_end"),
+            McDimension::Custom(id) => id.split_once(':').unwrap_or(("minecraft", id)),
+        }
+    }
+}
+
+/// サーバーディレクトリ内のワールドデータの構成
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServerDirStructure {
+    /// すべてのディメンションを1つのワールドディレクトリに保存する（バニラ）
+    Vanilla,
+    /// ディメンションごとに `world_nether` などの別のワールドディレクトリに保存する（Bukkit系）
+    Plugin,
+}
+
+/// サーバーディレクトリ内のリージョンディレクトリの配置
+pub struct ServerLayout {
+    server_dir: PathBuf,
+    level_name: String,
+    structure: ServerDirStructure,
+    region_dir_overrides: HashMap<McDimension, PathBuf>,
+}
+
+impl ServerLayout {
+    pub fn new(
+        server_dir: PathBuf,
+        level_name: impl Into<String>,
+        structure: ServerDirStructure,
+    ) -> Self {
+        ServerLayout {
+            server_dir,
+            level_name: level_name.into(),
+            structure,
+            region_dir_overrides: HashMap::new(),
+        }
+    }
+
+    /// ディメンションのリージョンディレクトリを明示的に指定する（Multiverseなどの独自の配置向け）。
+    /// 相対パスはサーバーディレクトリからのパスとして扱う
+    pub fn with_region_dir_override(mut self, dimension: McDimension, path: PathBuf) -> Self {
+        self.region_dir_overrides.insert(dimension, path);
+        self
+    }
+
+    pub fn region_dir(&self, dimension: &McDimension) -> PathBuf {
+        if let Some(path) = self.region_dir_overrides.get(dimension) {
+            return self.server_dir.join(path);
+        }
+        let world_dir = self.server_dir.join(&self.level_name);
+        match self.structure {
+            ServerDirStructure::Vanilla => world_dir.join(Self::dimension_dir(dimension)),
+            ServerDirStructure::Plugin => {
+                let world_dir = match dimension {
+                    McDimension::Overworld => world_dir,
+                    McDimension::Nether => {
+                        self.server_dir.join(format!("{}_nether", self.level_name))
+                    }
+                    McDimension::TheEnd => {
+                        self.server_dir.join(format!("{}_the_end", self.level_name))
+                    }
+                    McDimension::Custom(_) => {
+                        let (_, path) = dimension.namespaced_id();
+                        self.server_dir
+                            .join(format!("{}_{}", self.level_name, path))
+                    }
+                };
+                world_dir.join(Self::dimension_dir(dimension))
+            }
+        }
+    }
+
+    pub fn dimension(&self, dimension: &McDimension) -> Dimension {
+        Dimension::new(self.region_dir(dimension)).with_kind(dimension.clone())
+    }
+
+    pub fn level_dat_path(&self) -> PathBuf {
+        self.server_dir.join(&self.level_name).join("level.dat")
+    }
+
+    /// level.datに記録されたワールドのDataVersionを読み込む
+    pub fn get_data_version(&self) -> Result<i32> {
+        #[derive(Deserialize)]
+        struct LevelDat {
+            #[serde(rename = "Data")]
+            data: LevelData,
+        }
+        #[derive(Deserialize)]
+        struct LevelData {
+            #[serde(rename = "DataVersion")]
+            data_version: Option<i32>,
+        }
+
+        let path = self.level_dat_path();
+        let file = File::open(&path)
+            .map_err(|e| anyhow::anyhow!("Failed to open {}: {}", path.display(), e))?;
+        let level_dat: LevelDat = parse_nbt_gzip(file)?;
+        level_dat.data.data_version.ok_or_else(|| {
+            // DataVersionは1.9から記録されている
+            anyhow::anyhow!(
+                "{} has no Data.DataVersion tag; worlds older than 1.9 are not supported",
+                path.display()
+            )
+        })
+    }
+
+    // ワールドディレクトリからリージョンディレクトリへの相対パス
+    fn dimension_dir(dimension: &McDimension) -> PathBuf {
+        match dimension {
+            McDimension::Overworld => PathBuf::from("region"),
+            McDimension::Nether => PathBuf::from("DIM-1/region"),
+            McDimension::TheEnd => PathBuf::from("DIM1/region"),
+            McDimension::Custom(_) => {
+                let (namespace, path) = dimension.namespaced_id();
+                PathBuf::from("dimensions")
+                    .join(namespace)
+                    .join(path)
+                    .join("region")
+            }
+        }
+    }
+}
 
 pub struct Dimension {
     path: PathBuf,
+    kind: McDimension,
 }
 
 impl Dimension {
+    /// オーバーワールドのリージョンディレクトリとして作成する
     pub fn new(path: PathBuf) -> Self {
-        Dimension { path }
+        Dimension {
+            path,
+            kind: McDimension::Overworld,
+        }
+    }
+
+    /// リージョンディレクトリがどのディメンションのものかを指定する
+    pub fn with_kind(mut self, kind: McDimension) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    /// リージョンディレクトリのディメンション
+    pub fn kind(&self) -> &McDimension {
+        &self.kind
     }
 
     pub fn load_region(&self, pos: impl Into<RegionPos>) -> Result<Region> {
@@ -18,28 +189,313 @@ impl Dimension {
         let path = self.path.join(pos.to_file_name());
 
         if !path.exists() {
-            return Ok(Region::from_raw(
-                pos,
-                fastanvil::Region::new(File::open(&path)?)?,
-            ));
+            std::fs::create_dir_all(&self.path)?;
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create_new(true)
+                .open(&path)?;
+            return Region::create(pos, file);
         }
-        Ok(Region::from_raw(
-            pos,
-            fastanvil::Region::from_stream(File::open(&path)?)?,
-        ))
+        let file = OpenOptions::new().read(true).write(true).open(&path)?;
+        Region::from_stream(pos, file)
     }
+
+    /// リージョンファイルの整合性を検査する
+    pub fn validate_region(&self, pos: impl Into<RegionPos>) -> Result<Vec<RegionIssue>> {
+        let pos = pos.into();
+        let mut file = File::open(self.path.join(pos.to_file_name()))?;
+        validate_region(pos, &mut file)
+    }
+
+    /// チャンクの書き換えで使われなくなったセクターを取り除き、リージョンファイルを詰めて書き直す。
+    /// 一時ファイルに書き出してから置き換えるため、途中で失敗しても元のファイルは壊れない
+    pub fn compact_region(&self, pos: impl Into<RegionPos>) -> Result<()> {
+        let pos = pos.into();
+        let path = self.path.join(pos.to_file_name());
+        let tmp_path = self.path.join(format!("{}.tmp", pos.to_file_name()));
+        let result = (|| {
+            let mut src = File::open(&path)?;
+            let mut dst = File::create(&tmp_path)?;
+            compact_region(&mut src, &mut dst)?;
+            dst.sync_all()?;
+            std::fs::rename(&tmp_path, &path)?;
+            Ok(())
+        })();
+        if result.is_err() {
+            let _ = std::fs::remove_file(&tmp_path);
+        }
+        result
+    }
+
+    /// ディメンション内に存在するチャンクを列挙する
+    pub fn list_chunks(&self) -> Result<Vec<ChunkPos>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        let mut chunks = Vec::new();
+        for entry in std::fs::read_dir(&self.path)? {
+            let entry = entry?;
+            let file_name = entry.file_name();
+            // リージョンファイル以外は無視する
+            let Ok(pos) = RegionPos::try_parse_file_name(&file_name.to_string_lossy()) else {
+                continue;
+            };
+            // チャンクのデータは読まず、ヘッダーだけを見る
+            let mut file = File::open(entry.path())?;
+            chunks.extend(list_region_chunks(pos, &mut file)?);
+        }
+        Ok(chunks)
+    }
+
+    /// 要求されたチャンクのうち、ディメンション内に存在するものだけを残す
+    pub fn retain_existing_chunks(&self, chunks: &[ChunkPos]) -> Result<Vec<ChunkPos>> {
+        let existing: HashSet<ChunkPos> = self.list_chunks()?.into_iter().collect();
+        Ok(chunks
+            .iter()
+            .filter(|chunk| existing.contains(chunk))
+            .copied()
+            .collect())
+    }
+
+    /// 要求されたチャンクのうち、存在しないか読み込めないものを列挙する
+    pub fn unreadable_chunks(&self, chunks: &[ChunkPos]) -> Result<Vec<ChunkPos>> {
+        let mut unreadable = Vec::new();
+        for (pos, region_chunks) in group_chunks_by_region(chunks) {
+            let Ok(file) = File::open(self.path.join(pos.to_file_name())) else {
+                unreadable.extend(region_chunks);
+                continue;
+            };
+            let Ok(mut region) = Region::from_stream(pos, file) else {
+                unreadable.extend(region_chunks);
+                continue;
+            };
+            for chunk in region_chunks {
+                if !matches!(region.load_chunk(chunk), Ok(Some(_))) {
+                    unreadable.push(chunk);
+                }
+            }
+        }
+        Ok(unreadable)
+    }
+
+    /// 要求されたチャンクのうち、`InhabitedTime` が `threshold` tickを超えるものだけを残す。
+    /// プレイヤーが訪れていないチャンクは再生成しても同じ地形になるため、移行の対象から外すのに使う。
+    /// 存在しないチャンクや `InhabitedTime` が記録されていないチャンクは訪れていないものとみなす
+    pub fn inhabited_chunks(&self, chunks: &[ChunkPos], threshold: i64) -> Result<Vec<ChunkPos>> {
+        let mut inhabited = HashSet::new();
+        for (pos, region_chunks) in group_chunks_by_region(chunks) {
+            let path = self.path.join(pos.to_file_name());
+            if !path.exists() {
+                continue;
+            }
+            let mut region = Region::from_stream(pos, File::open(path)?)?;
+            for chunk in region_chunks {
+                let Some(activity) = region.load_chunk_activity(chunk)? else {
+                    continue;
+                };
+                if activity.inhabited_time.is_some_and(|time| time > threshold) {
+                    inhabited.insert(chunk);
+                }
+            }
+        }
+        Ok(chunks
+            .iter()
+            .filter(|chunk| inhabited.contains(chunk))
+            .copied()
+            .collect())
+    }
+
+    /// 要求されたチャンクのうち、ディメンション内でまだ生成を終えていないものだけを残す。
+    /// 前回の生成範囲の縁に残った生成途中のチャンク（`Status` が `full` でないもの）や
+    /// 読み込めないチャンクも生成し直すため、存在しないものとみなす
+    pub fn missing_chunks(&self, chunks: &[ChunkPos]) -> Result<Vec<ChunkPos>> {
+        let mut generated = HashSet::new();
+        for (pos, region_chunks) in group_chunks_by_region(chunks) {
+            let path = self.path.join(pos.to_file_name());
+            if !path.exists() {
+                continue;
+            }
+            let Ok(mut region) = Region::from_stream(pos, File::open(path)?) else {
+                continue;
+            };
+            for chunk in region_chunks {
+                if let Ok(Some(status)) = region.load_chunk_status(chunk)
+                    && is_full_status(&status)
+                {
+                    generated.insert(chunk);
+                }
+            }
+        }
+        Ok(chunks
+            .iter()
+            .filter(|chunk| !generated.contains(chunk))
+            .copied()
+            .collect())
+    }
+}
+/// リージョンファイルの読み書き先
+pub trait RegionStream: Read + Write + Seek {
+    /// 書き込んだ内容をストレージに永続化する
+    fn sync(&mut self) -> std::io::Result<()>;
+}
+
+impl RegionStream for File {
+    fn sync(&mut self) -> std::io::Result<()> {
+        self.sync_all()
+    }
+}
+
+/// リージョンファイルのヘッダーのサイズ
+const REGION_HEADER_SIZE: u64 = 8192;
+
+/// チャンクのデータを永続化してからヘッダーを書き換える書き込み先。
+/// 新しく追加したチャンクや、元のセクターに収まらずファイルの末尾に移されたチャンクは、
+/// データの書き込み中にプロセスが終了してもヘッダーが書きかけのデータを指すことはなく、
+/// 存在しないもの（移された場合は書き換え前のもの）として読み込まれる。
+/// 元のセクターに収まる書き換えはfastanvilがその場で上書きするため、この保護の対象外で、
+/// 書き込み中に終了するとチャンクが壊れることがある
+pub struct HeaderLastStream<S: RegionStream> {
+    inner: S,
+    has_unsynced_data: bool,
 }
-pub struct Region {
+
+impl<S: RegionStream> HeaderLastStream<S> {
+    pub fn new(inner: S) -> Self {
+        HeaderLastStream {
+            inner,
+            has_unsynced_data: false,
+        }
+    }
+}
+
+impl<S: RegionStream> Read for HeaderLastStream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl<S: RegionStream> Write for HeaderLastStream<S> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.inner.stream_position()? < REGION_HEADER_SIZE {
+            if self.has_unsynced_data {
+                self.inner.flush()?;
+                self.inner.sync()?;
+                self.has_unsynced_data = false;
+            }
+        } else {
+            self.has_unsynced_data = true;
+        }
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<S: RegionStream> Seek for HeaderLastStream<S> {
+    fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+impl<S: RegionStream> RegionStream for HeaderLastStream<S> {
+    fn sync(&mut self) -> std::io::Result<()> {
+        self.has_unsynced_data = false;
+        self.inner.sync()
+    }
+}
+
+pub struct Region<S: RegionStream = File> {
     pos: RegionPos,
-    raw: fastanvil::Region<File>,
+    raw: fastanvil::Region<HeaderLastStream<S>>,
+    // 開いた時点でヘッダーが壊れていたチャンクの、リージョン内の座標と理由
+    corrupt_locations: HashMap<(usize, usize), &'static str>,
 }
-impl Region {
-    fn from_raw(pos: RegionPos, raw: fastanvil::Region<File>) -> Self {
-        Region { pos, raw }
+impl<S: RegionStream> Region<S> {
+    /// 既存のリージョンファイルを開く。
+    /// ヘッダーがファイルの範囲外を指すチャンクは、読み込むときにエラーになる
+    pub fn from_stream(pos: RegionPos, mut stream: S) -> Result<Self> {
+        let corrupt_locations = find_corrupt_locations(&mut stream)
+            .map_err(|e| e.context(format!("region {}", pos.to_file_name())))?;
+        Ok(Region {
+            pos,
+            raw: fastanvil::Region::from_stream(HeaderLastStream::new(stream))?,
+            corrupt_locations,
+        })
+    }
+
+    /// 空のリージョンファイルを作成する。書き込み先の内容は上書きされる
+    pub fn create(pos: RegionPos, stream: S) -> Result<Self> {
+        Ok(Region {
+            pos,
+            raw: fastanvil::Region::new(HeaderLastStream::new(stream))?,
+            corrupt_locations: HashMap::new(),
+        })
+    }
+
+    /// 書き込んだ内容をフラッシュしてストレージに同期し、リージョンを閉じる
+    pub fn close(self) -> Result<()> {
+        let mut stream = self.raw.into_inner()?;
+        stream.flush()?;
+        stream.sync()?;
+        Ok(())
     }
 
     pub fn load_chunk(&mut self, pos: impl Into<ChunkPos>) -> Result<Option<Chunk>> {
-        let pos = pos.into();
+        let Some(bytes) = self.read_chunk_bytes(pos.into())? else {
+            return Ok(None);
+        };
+        Ok(Some(parse_nbt_bytes(&bytes)?))
+    }
+
+    /// チャンクの `LastUpdate` と `InhabitedTime` を読み込む。
+    /// 1.18より前の `Level` 以下に記録された形式のチャンクも読み込める
+    pub fn load_chunk_activity(
+        &mut self,
+        pos: impl Into<ChunkPos>,
+    ) -> Result<Option<ChunkActivity>> {
+        #[derive(Deserialize)]
+        struct StoredChunk {
+            #[serde(flatten)]
+            activity: ChunkActivity,
+            #[serde(rename = "Level")]
+            level: Option<ChunkActivity>,
+        }
+
+        let Some(bytes) = self.read_chunk_bytes(pos.into())? else {
+            return Ok(None);
+        };
+        let stored: StoredChunk = parse_nbt_bytes(&bytes)?;
+        Ok(Some(stored.level.unwrap_or(stored.activity)))
+    }
+
+    /// チャンクの生成段階（`Status`）を読み込む。
+    /// 1.18より前の `Level` 以下に記録された形式のチャンクも読み込める
+    pub fn load_chunk_status(&mut self, pos: impl Into<ChunkPos>) -> Result<Option<String>> {
+        #[derive(Deserialize)]
+        struct StoredStatus {
+            #[serde(rename = "Status")]
+            status: Option<String>,
+        }
+        #[derive(Deserialize)]
+        struct StoredChunk {
+            #[serde(flatten)]
+            status: StoredStatus,
+            #[serde(rename = "Level")]
+            level: Option<StoredStatus>,
+        }
+
+        let Some(bytes) = self.read_chunk_bytes(pos.into())? else {
+            return Ok(None);
+        };
+        let stored: StoredChunk = parse_nbt_bytes(&bytes)?;
+        Ok(stored.level.unwrap_or(stored.status).status)
+    }
+
+    // 展開したチャンクのNBTを読み込む
+    fn read_chunk_bytes(&mut self, pos: ChunkPos) -> Result<Option<Vec<u8>>> {
         if pos.region() != self.pos {
             anyhow::bail!(
                 "Different region requested: {:?} != {:?}",
@@ -49,13 +505,16 @@ impl Region {
         }
 
         let (ox, oz) = pos.region_offset();
-
-        let bytes = self.raw.read_chunk(ox, oz)?;
-
-        if let Some(bytes) = bytes {
-            return Ok(Some(fastnbt::from_bytes(&bytes)?));
+        if let Some(reason) = self.corrupt_locations.get(&(ox, oz)) {
+            anyhow::bail!(
+                "region {}: chunk ({}, {}) {}",
+                self.pos.to_file_name(),
+                pos.x,
+                pos.z,
+                reason
+            );
         }
-        return Ok(None);
+        Ok(self.raw.read_chunk(ox, oz)?)
     }
 
     pub fn save_chunk(&mut self, pos: ChunkPos, chunk: &Chunk) -> Result<()> {
@@ -68,11 +527,28 @@ impl Region {
         }
         let (ox, oz) = pos.region_offset();
         self.raw.write_chunk(ox, oz, &fastnbt::to_bytes(&chunk)?)?;
+        self.corrupt_locations.remove(&(ox, oz));
         Ok(())
     }
 }
 
-#[derive(Serialize, Deserialize)]
+// 生成を終えたチャンクの `Status`。1.20より前は名前空間なしで記録される
+fn is_full_status(status: &str) -> bool {
+    matches!(status, "minecraft:full" | "full")
+}
+
+/// プレイヤーがチャンクを訪れたかどうかの判断に使う記録
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub struct ChunkActivity {
+    /// チャンクが最後に保存されたときのゲーム内時刻（tick）
+    #[serde(rename = "LastUpdate")]
+    pub last_update: Option<i64>,
+    /// プレイヤーがチャンクの近くにいた累計時間（tick）
+    #[serde(rename = "InhabitedTime")]
+    pub inhabited_time: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Chunk {
     sections: Vec<Section>,
 
@@ -84,6 +560,14 @@ pub struct Chunk {
 }
 
 impl Chunk {
+    pub fn new(sections: Vec<Section>, status: impl Into<String>) -> Self {
+        Chunk {
+            sections,
+            status: status.into(),
+            other: HashMap::new(),
+        }
+    }
+
     pub fn get_block(&self, x: usize, y: isize, z: usize) -> Result<&Block> {
         if y < 0 || y >= 384 {
             anyhow::bail!("Y coordinate out of bounds: {}", y);
@@ -95,6 +579,43 @@ impl Chunk {
         let sect_y = y.rem_euclid(16) as usize;
         Ok(self.sections[sect_idx].block_states.get_block(x, sect_y, z))
     }
+
+    /// セクション・ステータス以外のタグ
+    pub fn get_tag(&self, name: &str) -> Option<&Value> {
+        self.other.get(name)
+    }
+
+    pub fn set_tag(&mut self, name: impl Into<String>, value: Value) {
+        self.other.insert(name.into(), value);
+    }
+
+    /// `LastUpdate` と `InhabitedTime` のタグ
+    pub fn activity(&self) -> ChunkActivity {
+        let long_tag = |name| match self.other.get(name) {
+            Some(Value::Long(value)) => Some(*value),
+            _ => None,
+        };
+        ChunkActivity {
+            last_update: long_tag("LastUpdate"),
+            inhabited_time: long_tag("InhabitedTime"),
+        }
+    }
+
+    pub fn sections(&self) -> &[Section] {
+        &self.sections
+    }
+
+    pub fn sections_mut(&mut self) -> &mut [Section] {
+        &mut self.sections
+    }
+
+    /// セクションをY座標の順を保って追加する
+    pub fn insert_section(&mut self, section: Section) {
+        let index = self
+            .sections
+            .partition_point(|existing| existing.y() < section.y());
+        self.sections.insert(index, section);
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
@@ -107,18 +628,18 @@ impl RegionPos {
         RegionPos { x, z }
     }
 
-    pub fn try_parse_file_name(file_name: &str) -> Result<ChunkPos, String> {
+    pub fn try_parse_file_name(file_name: &str) -> Result<RegionPos, String> {
         let parts: Vec<&str> = file_name.split('.').collect();
-        if parts.len() != 3 || parts[0] != "r" || parts[2] != "mca" {
-            return Err("Invalid region file name format".to_string());
+        if parts.len() != 4 || parts[0] != "r" || parts[3] != "mca" {
+            return Err(format!("Invalid region file name format: {}", file_name));
         }
-        let coords: Vec<&str> = parts[1].split('_').collect();
-        if coords.len() != 2 {
-            return Err("Invalid region coordinates format".to_string());
-        }
-        let x = coords[0].parse::<isize>().expect("Invalid x coordinate");
-        let z = coords[1].parse::<isize>().expect("Invalid z coordinate");
-        Ok(ChunkPos::new(x, z))
+        let x = parts[1]
+            .parse::<isize>()
+            .map_err(|e| format!("Invalid x coordinate in {}: {}", file_name, e))?;
+        let z = parts[2]
+            .parse::<isize>()
+            .map_err(|e| format!("Invalid z coordinate in {}: {}", file_name, e))?;
+        Ok(RegionPos::new(x, z))
     }
 
     pub fn to_file_name(&self) -> String {
@@ -146,14 +667,23 @@ impl ChunkPos {
         ChunkPos { x, z }
     }
     pub fn region(&self) -> RegionPos {
-        RegionPos::new(self.x / 32, self.z / 32)
+        RegionPos::new(self.x.div_euclid(32), self.z.div_euclid(32))
     }
     pub fn region_offset(&self) -> (usize, usize) {
         (
             self.x.rem_euclid(32) as usize,
-            self.x.rem_euclid(32) as usize,
+            self.z.rem_euclid(32) as usize,
         )
     }
+    /// チャンクの北西端のブロック座標 (x, z)
+    pub fn origin_block(&self) -> (isize, isize) {
+        (self.x * 16, self.z * 16)
+    }
+    /// チャンクの中心のブロック座標 (x, z)
+    pub fn center_block(&self) -> (isize, isize) {
+        let (x, z) = self.origin_block();
+        (x + 8, z + 8)
+    }
 }
 
 impl From<(isize, isize)> for ChunkPos {
@@ -162,16 +692,328 @@ impl From<(isize, isize)> for ChunkPos {
     }
 }
 
-#[derive(Serialize, Deserialize)]
+/// 2つの角のチャンクを含む矩形範囲内のチャンクを列挙する
+pub fn chunks_in_area(corner1: ChunkPos, corner2: ChunkPos) -> Vec<ChunkPos> {
+    let (min_x, max_x) = (corner1.x.min(corner2.x), corner1.x.max(corner2.x));
+    let (min_z, max_z) = (corner1.z.min(corner2.z), corner1.z.max(corner2.z));
+    (min_x..=max_x)
+        .flat_map(|x| (min_z..=max_z).map(move |z| ChunkPos::new(x, z)))
+        .collect()
+}
+
+/// チャンクを所属するリージョンごとにまとめる
+pub fn group_chunks_by_region(chunks: &[ChunkPos]) -> HashMap<RegionPos, Vec<ChunkPos>> {
+    let mut groups: HashMap<RegionPos, Vec<ChunkPos>> = HashMap::new();
+    for chunk in chunks {
+        groups.entry(chunk.region()).or_default().push(*chunk);
+    }
+    groups
+}
+
+/// リージョンファイルのセクターのサイズ
+const SECTOR_SIZE: u64 = 4096;
+
+/// リージョンファイルの不整合
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegionIssue {
+    /// 2つのチャンクが同じセクターを使用している
+    OverlappingSectors { first: ChunkPos, second: ChunkPos },
+    /// チャンクの開始セクターが先頭2セクターのヘッダーを指している
+    OffsetInHeader { chunk: ChunkPos },
+    /// チャンクのデータがファイルの終端を越えている
+    OffsetPastEnd { chunk: ChunkPos },
+    /// ヘッダーでは存在するが、データの長さが0のチャンク
+    EmptyChunk { chunk: ChunkPos },
+    /// チャンクに記録された座標が格納位置と一致しない
+    CoordinateMismatch { chunk: ChunkPos, stored: ChunkPos },
+    /// チャンクのデータを展開・解析できない
+    UnreadableChunk { chunk: ChunkPos, reason: String },
+}
+
+// 位置テーブルのエントリが指すデータの不整合
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LocationFault {
+    /// 開始セクターが先頭2セクターのヘッダーを指している
+    InHeader,
+    /// セクター数が0
+    NoSectors,
+    /// 開始セクターがファイルの終端より後にある
+    OffsetPastEnd,
+    /// データの長さが0
+    EmptyPayload,
+    /// データがファイルの終端を越えている
+    DataPastEnd,
+}
+
+impl LocationFault {
+    fn reason(self) -> &'static str {
+        match self {
+            LocationFault::InHeader => "offset points into the header",
+            LocationFault::NoSectors => "has no sectors",
+            LocationFault::OffsetPastEnd => "offset beyond file length",
+            LocationFault::EmptyPayload => "has an empty payload",
+            LocationFault::DataPastEnd => "data beyond file length",
+        }
+    }
+}
+
+// 位置テーブルのエントリが指すデータがファイルの範囲内に収まっているか確かめる。
+// 収まっていればデータの長さ（圧縮形式の1バイトを含む）を返し、ストリームは圧縮形式の位置に置かれる
+fn check_location<S: Read + Seek>(
+    stream: &mut S,
+    file_len: u64,
+    offset: u64,
+    sectors: u64,
+) -> Result<Result<u64, LocationFault>> {
+    let start = offset * SECTOR_SIZE;
+    if offset < REGION_HEADER_SIZE / SECTOR_SIZE {
+        return Ok(Err(LocationFault::InHeader));
+    }
+    if sectors == 0 {
+        return Ok(Err(LocationFault::NoSectors));
+    }
+    if start + 5 > file_len {
+        return Ok(Err(LocationFault::OffsetPastEnd));
+    }
+    stream.seek(std::io::SeekFrom::Start(start))?;
+    let mut length = [0u8; 4];
+    stream.read_exact(&mut length)?;
+    let length = u32::from_be_bytes(length) as u64;
+    if length == 0 {
+        return Ok(Err(LocationFault::EmptyPayload));
+    }
+    if start + 4 + length > file_len {
+        return Ok(Err(LocationFault::DataPastEnd));
+    }
+    Ok(Ok(length))
+}
+
+// ヘッダーが指すデータがファイルの範囲内に収まっているか確かめ、
+// 収まっていないチャンクのリージョン内の座標と理由を返す
+fn find_corrupt_locations<S: Read + Seek>(
+    stream: &mut S,
+) -> Result<HashMap<(usize, usize), &'static str>> {
+    let file_len = stream.seek(std::io::SeekFrom::End(0))?;
+    let mut corrupt = HashMap::new();
+    for (offset, sectors, chunk) in read_locations(RegionPos::new(0, 0), stream)? {
+        if let Err(fault) = check_location(stream, file_len, offset, sectors)? {
+            corrupt.insert((chunk.x as usize, chunk.z as usize), fault.reason());
+        }
+    }
+    Ok(corrupt)
+}
+
+// リージョンファイルの先頭の位置テーブルを読み、
+// 開始セクターかセクター数が0でないエントリを (開始セクター, セクター数, チャンク) として返す
+fn read_locations<S: Read + Seek>(
+    pos: RegionPos,
+    stream: &mut S,
+) -> Result<Vec<(u64, u64, ChunkPos)>> {
+    let file_len = stream.seek(std::io::SeekFrom::End(0))?;
+    if file_len < REGION_HEADER_SIZE {
+        anyhow::bail!(
+            "file is truncated: {} bytes, but the header needs {}",
+            file_len,
+            REGION_HEADER_SIZE
+        );
+    }
+    stream.seek(std::io::SeekFrom::Start(0))?;
+    let mut header = vec![0u8; REGION_HEADER_SIZE as usize / 2];
+    stream.read_exact(&mut header)?;
+
+    let mut locations = Vec::new();
+    for (index, entry) in header.chunks_exact(4).enumerate() {
+        let offset = u32::from_be_bytes([0, entry[0], entry[1], entry[2]]) as u64;
+        let sectors = entry[3] as u64;
+        if offset == 0 && sectors == 0 {
+            continue;
+        }
+        let chunk = pos.chunk_at((index % 32) as isize, (index / 32) as isize);
+        locations.push((offset, sectors, chunk));
+    }
+    Ok(locations)
+}
+
+/// リージョンファイルのヘッダーから、データが存在するチャンクを列挙する。
+/// チャンクのデータは読み込まないため、中身が壊れていても列挙される
+pub fn list_region_chunks<S: Read + Seek>(pos: RegionPos, stream: &mut S) -> Result<Vec<ChunkPos>> {
+    Ok(read_locations(pos, stream)?
+        .into_iter()
+        .filter(|(offset, sectors, _)| *offset != 0 && *sectors != 0)
+        .map(|(_, _, chunk)| chunk)
+        .collect())
+}
+
+/// 存在するチャンクだけを隙間なく並べたリージョンファイルを `dst` に書き出す。
+/// チャンクのデータは展開せずにそのまま、タイムスタンプも元のまま書き写す
+pub fn compact_region<S: Read + Seek, W: Write>(src: &mut S, dst: &mut W) -> Result<()> {
+    let file_len = src.seek(std::io::SeekFrom::End(0))?;
+    src.seek(std::io::SeekFrom::Start(0))?;
+    let mut header = vec![0u8; REGION_HEADER_SIZE as usize];
+    src.read_exact(&mut header)?;
+
+    let mut body = Vec::new();
+    let mut next_sector = REGION_HEADER_SIZE / SECTOR_SIZE;
+    for index in 0..1024 {
+        let entry = &mut header[index * 4..index * 4 + 4];
+        let offset = u32::from_be_bytes([0, entry[0], entry[1], entry[2]]) as u64;
+        let sectors = entry[3];
+        if offset == 0 || sectors == 0 {
+            entry.copy_from_slice(&[0; 4]);
+            continue;
+        }
+        let start = offset * SECTOR_SIZE;
+        if start + 4 > file_len {
+            anyhow::bail!("Chunk {} points past the end of the region file", index);
+        }
+        src.seek(std::io::SeekFrom::Start(start))?;
+        let mut length = [0u8; 4];
+        src.read_exact(&mut length)?;
+        let length = u32::from_be_bytes(length) as u64;
+        if length == 0 || start + 4 + length > file_len {
+            anyhow::bail!("Chunk {} has an invalid length {}", index, length);
+        }
+        let mut data = vec![0u8; 4 + length as usize];
+        data[..4].copy_from_slice(&(length as u32).to_be_bytes());
+        src.read_exact(&mut data[4..])?;
+
+        let new_sectors = (4 + length).div_ceil(SECTOR_SIZE);
+        if new_sectors > u8::MAX as u64 {
+            anyhow::bail!("Chunk {} has an invalid length {}", index, length);
+        }
+        entry[..3].copy_from_slice(&(next_sector as u32).to_be_bytes()[1..]);
+        entry[3] = new_sectors as u8;
+        body.extend_from_slice(&data);
+        body.resize(body.len().next_multiple_of(SECTOR_SIZE as usize), 0);
+        next_sector += new_sectors;
+    }
+    dst.write_all(&header)?;
+    dst.write_all(&body)?;
+    dst.flush()?;
+    Ok(())
+}
+
+/// リージョンファイルのヘッダーとチャンクのデータの整合性を検査する
+pub fn validate_region<S: Read + Seek>(pos: RegionPos, stream: &mut S) -> Result<Vec<RegionIssue>> {
+    let file_len = stream.seek(std::io::SeekFrom::End(0))?;
+    let mut locations = read_locations(pos, stream)?;
+
+    let mut issues = Vec::new();
+    for &(offset, sectors, chunk) in &locations {
+        let length = match check_location(stream, file_len, offset, sectors)? {
+            Ok(length) => length,
+            Err(LocationFault::InHeader) => {
+                issues.push(RegionIssue::OffsetInHeader { chunk });
+                continue;
+            }
+            Err(LocationFault::NoSectors | LocationFault::EmptyPayload) => {
+                issues.push(RegionIssue::EmptyChunk { chunk });
+                continue;
+            }
+            Err(LocationFault::OffsetPastEnd | LocationFault::DataPastEnd) => {
+                issues.push(RegionIssue::OffsetPastEnd { chunk });
+                continue;
+            }
+        };
+        let mut compression = [0u8; 1];
+        stream.read_exact(&mut compression)?;
+        // 外部ファイル (.mcc) に保存されたチャンクは検査しない
+        if compression[0] & 0x80 != 0 {
+            continue;
+        }
+        let mut data = vec![0u8; length as usize - 1];
+        stream.read_exact(&mut data)?;
+        match read_stored_chunk_pos(compression[0], &data) {
+            Ok(stored) if stored != chunk => {
+                issues.push(RegionIssue::CoordinateMismatch { chunk, stored })
+            }
+            Ok(_) => {}
+            Err(e) => issues.push(RegionIssue::UnreadableChunk {
+                chunk,
+                reason: e.to_string(),
+            }),
+        }
+    }
+
+    locations.sort_by_key(|(offset, _, _)| *offset);
+    for pair in locations.windows(2) {
+        let (first_offset, first_sectors, first) = pair[0];
+        let (second_offset, _, second) = pair[1];
+        if first_offset + first_sectors > second_offset {
+            issues.push(RegionIssue::OverlappingSectors { first, second });
+        }
+    }
+    Ok(issues)
+}
+
+// チャンクのデータに記録された座標を読み込む。1.18より前は Level 以下に記録されている
+fn read_stored_chunk_pos(compression: u8, data: &[u8]) -> Result<ChunkPos> {
+    #[derive(Deserialize)]
+    struct StoredPos {
+        #[serde(rename = "xPos")]
+        x: i32,
+        #[serde(rename = "zPos")]
+        z: i32,
+    }
+    #[derive(Deserialize)]
+    struct StoredChunk {
+        #[serde(flatten)]
+        pos: Option<StoredPos>,
+        #[serde(rename = "Level")]
+        level: Option<StoredPos>,
+    }
+
+    let mut bytes = Vec::new();
+    match compression {
+        1 => flate2::read::GzDecoder::new(data).read_to_end(&mut bytes)?,
+        2 => flate2::read::ZlibDecoder::new(data).read_to_end(&mut bytes)?,
+        3 => {
+            bytes.extend_from_slice(data);
+            data.len()
+        }
+        other => anyhow::bail!("Unknown compression scheme: {}", other),
+    };
+    let stored: StoredChunk = parse_nbt_bytes(&bytes)?;
+    let pos = stored
+        .pos
+        .or(stored.level)
+        .ok_or_else(|| anyhow::anyhow!("Chunk has no xPos/zPos tags"))?;
+    Ok(ChunkPos::new(pos.x as isize, pos.z as isize))
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Section {
     block_states: Blockstates,
     #[serde(flatten)]
     other: HashMap<String, Value>,
 }
 impl Section {
+    pub fn new(y: i8, block_states: Blockstates) -> Self {
+        Section {
+            block_states,
+            other: HashMap::from([("Y".to_string(), Value::Byte(y))]),
+        }
+    }
+
     pub fn get_palette_item(&self, x: usize, y: usize, z: usize) -> &Block {
         self.block_states.get_block(x, y, z)
     }
+
+    /// セクションのY座標（16ブロック単位）
+    pub fn y(&self) -> Option<i8> {
+        match self.other.get("Y") {
+            Some(Value::Byte(y)) => Some(*y),
+            _ => None,
+        }
+    }
+
+    pub fn block_states(&self) -> &Blockstates {
+        &self.block_states
+    }
+
+    pub fn set_block_states(&mut self, block_states: Blockstates) {
+        self.block_states = block_states;
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -184,21 +1026,97 @@ pub struct Blockstates {
     #[serde(skip)]
     bits_per_block: RwLock<Option<u32>>,
 }
+
+impl Clone for Blockstates {
+    fn clone(&self) -> Self {
+        Blockstates {
+            palette: self.palette.clone(),
+            data: self.data.clone(),
+            other: self.other.clone(),
+            bits_per_block: RwLock::new(None),
+        }
+    }
+}
+
 impl Blockstates {
+    /// 4096個のブロックを `(y * 16 + z) * 16 + x` の順に並べたものから作成する。
+    /// 1.16以降の形式で書き出す
+    pub fn from_blocks(blocks: &[Block]) -> Result<Self> {
+        if blocks.len() != 4096 {
+            anyhow::bail!("A section must have 4096 blocks, got {}", blocks.len());
+        }
+        let mut palette: Vec<Block> = Vec::new();
+        let indices: Vec<u64> = blocks
+            .iter()
+            .map(|block| match palette.iter().position(|b| b == block) {
+                Some(index) => index as u64,
+                None => {
+                    palette.push(block.clone());
+                    (palette.len() - 1) as u64
+                }
+            })
+            .collect();
+        let mut states = Blockstates {
+            palette,
+            data: None,
+            other: HashMap::new(),
+            bits_per_block: RwLock::new(None),
+        };
+        if states.palette.len() > 1 {
+            let bits = states.calculate_bits_per_block() as usize;
+            let block_per_long = u64::BITS as usize / bits;
+            let data = indices
+                .chunks(block_per_long)
+                .map(|chunk| {
+                    chunk
+                        .iter()
+                        .enumerate()
+                        .fold(0u64, |acc, (i, index)| acc | (index << (i * bits)))
+                        as i64
+                })
+                .collect();
+            states.data = Some(fastnbt::LongArray::new(data));
+        }
+        Ok(states)
+    }
+
+    /// 4096個のブロックを `(y * 16 + z) * 16 + x` の順に返す
+    pub fn blocks(&self) -> Vec<Block> {
+        (0..16)
+            .flat_map(|y| (0..16).flat_map(move |z| (0..16).map(move |x| (x, y, z))))
+            .map(|(x, y, z)| self.get_block(x, y, z).clone())
+            .collect()
+    }
+
     pub fn get_block(&self, x: usize, y: usize, z: usize) -> &Block {
         if x >= 16 || y >= 16 || z >= 16 {
             panic!("X, Y, Z coordinate out of bounds: x={}, z={}", x, z);
         }
         if let Some(data) = &self.data {
-            let block_index = ((y * 16 + z) * 16 + x) as usize;
+            let block_index = (y * 16 + z) * 16 + x;
 
-            let bits_per_block = self.calculate_bits_per_block();
-            let block_per_long = (u64::BITS / bits_per_block) as usize;
-            let data_index = block_index / block_per_long;
-            let block_offset = block_index % block_per_long;
+            let bits_per_block = self.calculate_bits_per_block() as usize;
+            let mask = (1u64 << bits_per_block) - 1;
 
-            let pallet_index = (data[data_index] >> (block_offset * bits_per_block as usize))
-                & ((1 << bits_per_block) - 1);
+            let pallet_index = match BlockStateLayout::detect(data.len(), bits_per_block) {
+                BlockStateLayout::Padded => {
+                    let block_per_long = u64::BITS as usize / bits_per_block;
+                    let data_index = block_index / block_per_long;
+                    let block_offset = block_index % block_per_long;
+                    (data[data_index] as u64 >> (block_offset * bits_per_block)) & mask
+                }
+                BlockStateLayout::Packed => {
+                    let bit_index = block_index * bits_per_block;
+                    let data_index = bit_index / 64;
+                    let bit_offset = bit_index % 64;
+                    let mut value = data[data_index] as u64 >> bit_offset;
+                    if bit_offset + bits_per_block > 64 {
+                        // 次のlongにまたがっている
+                        value |= (data[data_index + 1] as u64) << (64 - bit_offset);
+                    }
+                    value & mask
+                }
+            };
 
             &self.palette[pallet_index as usize]
         } else {
@@ -218,7 +1136,8 @@ impl Blockstates {
             if self.palette.is_empty() {
                 0
             } else {
-                (usize::BITS - (self.palette.len().saturating_sub(1)).leading_zeros()).min(4) as u32
+                // パレットの添字を表すのに必要なビット数（最小4ビット）
+                (usize::BITS - (self.palette.len() - 1).leading_zeros()).max(4)
             }
         };
         if let Ok(mut v) = self.bits_per_block.write() {
@@ -228,10 +1147,1105 @@ impl Blockstates {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+/// ブロックの添字をlong配列に並べる形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockStateLayout {
+    /// 添字がlongの境界をまたいで詰められている（1.16より前）
+    Packed,
+    /// 1つのlongに収まる数だけ添字を詰め、余ったビットは空けておく（1.16以降）
+    Padded,
+}
+
+impl BlockStateLayout {
+    /// long配列の長さから形式を判定する。どちらの形式でも長さが同じ場合は配置も同じになる
+    pub fn detect(data_len: usize, bits_per_block: usize) -> Self {
+        let packed_len = (4096 * bits_per_block).div_ceil(64);
+        let padded_len = 4096usize.div_ceil(64 / bits_per_block);
+        if data_len == packed_len && data_len != padded_len {
+            BlockStateLayout::Packed
+        } else {
+            BlockStateLayout::Padded
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Block {
     #[serde(rename = "Name")]
     name: String,
     #[serde(rename = "Properties")]
     properties: Option<Value>,
 }
+
+impl Block {
+    pub fn new(name: impl Into<String>) -> Self {
+        Block {
+            name: name.into(),
+            properties: None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::Cursor,
+        sync::{Arc, Mutex},
+    };
+
+    // 書き込み・フラッシュ・同期の呼び出し順を記録するリージョンの書き込み先
+    #[derive(Clone, Debug, PartialEq)]
+    enum StreamOp {
+        Write,
+        Flush,
+        Sync,
+    }
+
+    struct RecordingStream {
+        inner: Cursor<Vec<u8>>,
+        ops: Arc<Mutex<Vec<StreamOp>>>,
+    }
+
+    impl Read for RecordingStream {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.inner.read(buf)
+        }
+    }
+
+    impl Write for RecordingStream {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.ops.lock().unwrap().push(StreamOp::Write);
+            self.inner.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.ops.lock().unwrap().push(StreamOp::Flush);
+            self.inner.flush()
+        }
+    }
+
+    impl Seek for RecordingStream {
+        fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    impl RegionStream for Cursor<Vec<u8>> {
+        fn sync(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl RegionStream for RecordingStream {
+        fn sync(&mut self) -> std::io::Result<()> {
+            self.ops.lock().unwrap().push(StreamOp::Sync);
+            Ok(())
+        }
+    }
+
+    fn empty_chunk() -> Chunk {
+        Chunk {
+            sections: vec![],
+            status: "minecraft:full".to_string(),
+            other: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_chunk_pos_origin_block() {
+        assert_eq!(ChunkPos::new(0, 0).origin_block(), (0, 0));
+        assert_eq!(ChunkPos::new(1, 1).origin_block(), (16, 16));
+        assert_eq!(ChunkPos::new(-1, -1).origin_block(), (-16, -16));
+    }
+
+    #[test]
+    fn test_chunk_pos_center_block() {
+        assert_eq!(ChunkPos::new(0, 0).center_block(), (8, 8));
+        assert_eq!(ChunkPos::new(1, 1).center_block(), (24, 24));
+        assert_eq!(ChunkPos::new(-1, -1).center_block(), (-8, -8));
+    }
+
+    #[test]
+    fn test_chunk_pos_region() {
+        assert_eq!(ChunkPos::new(0, 0).region(), RegionPos::new(0, 0));
+        assert_eq!(ChunkPos::new(31, 31).region(), RegionPos::new(0, 0));
+        assert_eq!(ChunkPos::new(32, 32).region(), RegionPos::new(1, 1));
+        assert_eq!(ChunkPos::new(-1, -1).region(), RegionPos::new(-1, -1));
+        assert_eq!(ChunkPos::new(-32, -32).region(), RegionPos::new(-1, -1));
+        assert_eq!(ChunkPos::new(-33, -33).region(), RegionPos::new(-2, -2));
+    }
+
+    #[test]
+    fn test_chunk_pos_region_offset() {
+        assert_eq!(ChunkPos::new(5, 20).region_offset(), (5, 20));
+        assert_eq!(ChunkPos::new(-1, -1).region_offset(), (31, 31));
+        assert_eq!(ChunkPos::new(-33, 40).region_offset(), (31, 8));
+    }
+
+    #[test]
+    fn test_region_pos_try_parse_file_name() {
+        assert_eq!(
+            RegionPos::try_parse_file_name("r.0.0.mca"),
+            Ok(RegionPos::new(0, 0))
+        );
+        assert_eq!(
+            RegionPos::try_parse_file_name("r.-1.2.mca"),
+            Ok(RegionPos::new(-1, 2))
+        );
+        let pos = RegionPos::new(-3, 7);
+        assert_eq!(RegionPos::try_parse_file_name(&pos.to_file_name()), Ok(pos));
+    }
+
+    #[test]
+    fn test_region_pos_try_parse_file_name_malformed() {
+        assert!(RegionPos::try_parse_file_name("r.0_0.mca").is_err());
+        assert!(RegionPos::try_parse_file_name("r.0.0.mcr").is_err());
+        assert!(RegionPos::try_parse_file_name("c.0.0.mca").is_err());
+        assert!(RegionPos::try_parse_file_name("r.a.0.mca").is_err());
+        assert!(RegionPos::try_parse_file_name("r.0.mca").is_err());
+        assert!(RegionPos::try_parse_file_name("").is_err());
+    }
+
+    #[test]
+    fn test_group_chunks_by_region() {
+        let chunks = [
+            ChunkPos::new(0, 0),
+            ChunkPos::new(31, 5),
+            ChunkPos::new(32, 0),
+            ChunkPos::new(-1, -1),
+            ChunkPos::new(-32, 0),
+            ChunkPos::new(-33, 0),
+        ];
+
+        let groups = group_chunks_by_region(&chunks);
+
+        assert_eq!(groups.len(), 5);
+        assert_eq!(
+            groups[&RegionPos::new(0, 0)],
+            vec![ChunkPos::new(0, 0), ChunkPos::new(31, 5)]
+        );
+        assert_eq!(groups[&RegionPos::new(1, 0)], vec![ChunkPos::new(32, 0)]);
+        assert_eq!(groups[&RegionPos::new(-1, -1)], vec![ChunkPos::new(-1, -1)]);
+        assert_eq!(groups[&RegionPos::new(-1, 0)], vec![ChunkPos::new(-32, 0)]);
+        assert_eq!(groups[&RegionPos::new(-2, 0)], vec![ChunkPos::new(-33, 0)]);
+    }
+
+    fn block(name: &str) -> Block {
+        Block {
+            name: name.to_string(),
+            properties: None,
+        }
+    }
+
+    // 1つのlongに収まる数だけ詰め、余ったビットは空けておく形式でパレットの添字を並べる
+    fn pack_padded(indices: &[u64], bits: usize) -> Vec<i64> {
+        let per_long = 64 / bits;
+        indices
+            .chunks(per_long)
+            .map(|chunk| {
+                chunk
+                    .iter()
+                    .enumerate()
+                    .fold(0u64, |acc, (i, idx)| acc | (idx << (i * bits))) as i64
+            })
+            .collect()
+    }
+
+    fn blockstates(palette: Vec<Block>, data: Option<Vec<i64>>) -> Blockstates {
+        Blockstates {
+            palette,
+            data: data.map(fastnbt::LongArray::new),
+            other: HashMap::new(),
+            bits_per_block: RwLock::new(None),
+        }
+    }
+
+    #[test]
+    fn test_blockstates_bits_per_block() {
+        let palette = |n: usize| (0..n).map(|i| block(&format!("b{}", i))).collect();
+        assert_eq!(blockstates(palette(1), None).calculate_bits_per_block(), 4);
+        assert_eq!(blockstates(palette(2), None).calculate_bits_per_block(), 4);
+        assert_eq!(blockstates(palette(16), None).calculate_bits_per_block(), 4);
+        assert_eq!(blockstates(palette(17), None).calculate_bits_per_block(), 5);
+        assert_eq!(blockstates(palette(32), None).calculate_bits_per_block(), 5);
+        assert_eq!(blockstates(palette(33), None).calculate_bits_per_block(), 6);
+        assert_eq!(blockstates(palette(40), None).calculate_bits_per_block(), 6);
+    }
+
+    #[test]
+    fn test_blockstates_from_blocks_roundtrip() {
+        let blocks: Vec<Block> = (0..4096)
+            .map(|i| Block::new(format!("minecraft:block_{}", i % 20)))
+            .collect();
+        let states = Blockstates::from_blocks(&blocks).unwrap();
+        assert_eq!(states.palette.len(), 20);
+        assert_eq!(states.blocks(), blocks);
+
+        let air = vec![Block::new("minecraft:air"); 4096];
+        let states = Blockstates::from_blocks(&air).unwrap();
+        assert!(states.data.is_none());
+        assert_eq!(states.blocks(), air);
+
+        assert!(Blockstates::from_blocks(&air[..10]).is_err());
+    }
+
+    #[test]
+    fn test_blockstates_get_block_large_palette() {
+        let palette: Vec<Block> = (0..40)
+            .map(|i| block(&format!("minecraft:block_{}", i)))
+            .collect();
+        let indices: Vec<u64> = (0..4096).map(|i| (i * 7 % 40) as u64).collect();
+        let states = blockstates(palette, Some(pack_padded(&indices, 6)));
+
+        for (x, y, z) in [(0, 0, 0), (1, 0, 0), (9, 0, 0), (10, 0, 0), (15, 15, 15), (3, 7, 11)] {
+            let i = (y * 16 + z) * 16 + x;
+            assert_eq!(
+                states.get_block(x, y, z).name,
+                format!("minecraft:block_{}", i * 7 % 40)
+            );
+        }
+    }
+
+    #[test]
+    fn test_region_close_flushes_and_syncs_after_write() {
+        let ops = Arc::new(Mutex::new(Vec::new()));
+        let stream = RecordingStream {
+            inner: Cursor::new(Vec::new()),
+            ops: ops.clone(),
+        };
+        let mut region = Region::create(RegionPos::new(0, 0), stream).unwrap();
+
+        region
+            .save_chunk(ChunkPos::new(1, 2), &empty_chunk())
+            .unwrap();
+        let ops_before_close = ops.lock().unwrap().len();
+
+        region.close().unwrap();
+
+        let ops = ops.lock().unwrap();
+        assert_eq!(ops.len(), ops_before_close + 2);
+        assert_eq!(ops[ops.len() - 3..], [StreamOp::Write, StreamOp::Flush, StreamOp::Sync]);
+    }
+
+    // ヘッダーへの書き込みで異常終了する書き込み先。書き込んだ内容は共有のバッファに残る
+    struct CrashingStream {
+        inner: Arc<Mutex<Cursor<Vec<u8>>>>,
+        ops: Arc<Mutex<Vec<StreamOp>>>,
+        crash_on_header: bool,
+    }
+
+    impl Read for CrashingStream {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.inner.lock().unwrap().read(buf)
+        }
+    }
+
+    impl Write for CrashingStream {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            let mut inner = self.inner.lock().unwrap();
+            if self.crash_on_header && inner.position() < REGION_HEADER_SIZE {
+                return Err(std::io::Error::other("crashed"));
+            }
+            self.ops.lock().unwrap().push(StreamOp::Write);
+            inner.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.ops.lock().unwrap().push(StreamOp::Flush);
+            Ok(())
+        }
+    }
+
+    impl Seek for CrashingStream {
+        fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+            self.inner.lock().unwrap().seek(pos)
+        }
+    }
+
+    impl RegionStream for CrashingStream {
+        fn sync(&mut self) -> std::io::Result<()> {
+            self.ops.lock().unwrap().push(StreamOp::Sync);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_region_crash_before_header_update_leaves_chunk_absent() {
+        let buffer = Arc::new(Mutex::new(Cursor::new(Vec::new())));
+        let ops = Arc::new(Mutex::new(Vec::new()));
+        let mut region = Region::create(
+            RegionPos::new(0, 0),
+            CrashingStream {
+                inner: buffer.clone(),
+                ops: ops.clone(),
+                crash_on_header: false,
+            },
+        )
+        .unwrap();
+        region
+            .save_chunk(ChunkPos::new(0, 0), &empty_chunk())
+            .unwrap();
+        region.close().unwrap();
+
+        // 2つ目のチャンクのデータを書き込んだ後、ヘッダーの更新前に異常終了させる
+        let mut region = Region::from_stream(
+            RegionPos::new(0, 0),
+            CrashingStream {
+                inner: buffer.clone(),
+                ops: ops.clone(),
+                crash_on_header: true,
+            },
+        )
+        .unwrap();
+        ops.lock().unwrap().clear();
+        assert!(region
+            .save_chunk(ChunkPos::new(3, 4), &empty_chunk())
+            .is_err());
+        assert_eq!(
+            ops.lock().unwrap().last(),
+            Some(&StreamOp::Sync),
+            "data must be synced before the header is updated"
+        );
+
+        let data = buffer.lock().unwrap().get_ref().clone();
+        let mut region = Region::from_stream(RegionPos::new(0, 0), Cursor::new(data)).unwrap();
+        assert!(region.load_chunk((3, 4)).unwrap().is_none());
+        assert!(region.load_chunk((0, 0)).unwrap().is_some());
+    }
+
+    // 添字をlongの境界をまたいで詰める
+    fn pack_packed(indices: &[u64], bits: usize) -> Vec<i64> {
+        let mut data = vec![0u64; (indices.len() * bits).div_ceil(64)];
+        for (i, idx) in indices.iter().enumerate() {
+            let bit_index = i * bits;
+            data[bit_index / 64] |= idx << (bit_index % 64);
+            if bit_index % 64 + bits > 64 {
+                data[bit_index / 64 + 1] |= idx >> (64 - bit_index % 64);
+            }
+        }
+        data.into_iter().map(|v| v as i64).collect()
+    }
+
+    #[test]
+    fn test_block_state_layout_detect() {
+        assert_eq!(BlockStateLayout::detect(256, 4), BlockStateLayout::Padded);
+        assert_eq!(BlockStateLayout::detect(342, 5), BlockStateLayout::Padded);
+        assert_eq!(BlockStateLayout::detect(320, 5), BlockStateLayout::Packed);
+        assert_eq!(BlockStateLayout::detect(410, 6), BlockStateLayout::Padded);
+        assert_eq!(BlockStateLayout::detect(384, 6), BlockStateLayout::Packed);
+    }
+
+    #[test]
+    fn test_blockstates_get_block_packed_layout() {
+        let palette: Vec<Block> = (0..20)
+            .map(|i| block(&format!("minecraft:block_{}", i)))
+            .collect();
+        let indices: Vec<u64> = (0..4096).map(|i| (i * 3 % 20) as u64).collect();
+        let states = blockstates(palette, Some(pack_packed(&indices, 5)));
+
+        // 5ビットの場合、12番目の添字は1つ目と2つ目のlongにまたがる
+        for (x, y, z) in [(0, 0, 0), (12, 0, 0), (13, 0, 0), (15, 15, 15), (3, 7, 11)] {
+            let i = (y * 16 + z) * 16 + x;
+            assert_eq!(
+                states.get_block(x, y, z).name,
+                format!("minecraft:block_{}", i * 3 % 20)
+            );
+        }
+    }
+
+    #[test]
+    fn test_chunk_get_block_matches_real_region() {
+        use fastanvil::Chunk as _;
+
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("examples/resource/regions");
+        let mut region = Dimension::new(path).load_region((0, 0)).unwrap();
+        let chunk = region.load_chunk((0, 0)).unwrap().unwrap();
+
+        let bytes = region.raw.read_chunk(0, 0).unwrap().unwrap();
+        let expected: fastanvil::CurrentJavaChunk = fastnbt::from_bytes(&bytes).unwrap();
+        let y_min = expected.y_range().start;
+
+        for y in (0..384).step_by(7) {
+            for (x, z) in [(0, 0), (15, 15), (4, 9), (11, 2)] {
+                assert_eq!(
+                    chunk.get_block(x, y, z).unwrap().name,
+                    expected.block(x, y + y_min, z).unwrap().name(),
+                    "block at ({}, {}, {})",
+                    x,
+                    y,
+                    z
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_dimension_load_region_creates_missing_region() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dim = Dimension::new(temp_dir.path().join("region"));
+
+        let mut region = dim.load_region((-1, 2)).unwrap();
+        assert!(region.load_chunk((-1, 64)).unwrap().is_none());
+        region.save_chunk(ChunkPos::new(-1, 64), &empty_chunk()).unwrap();
+        region.close().unwrap();
+        assert!(temp_dir.path().join("region/r.-1.2.mca").is_file());
+
+        let mut region = dim.load_region((-1, 2)).unwrap();
+        let chunk = region.load_chunk((-1, 64)).unwrap().unwrap();
+        assert_eq!(chunk.status, "minecraft:full");
+    }
+
+    #[test]
+    fn test_mc_dimension_from_id() {
+        assert_eq!(
+            McDimension::from_id("minecraft:overworld"),
+            McDimension::Overworld
+        );
+        assert_eq!(McDimension::from_id("the_nether"), McDimension::Nether);
+        assert_eq!(
+            McDimension::from_id("minecraft:the_end"),
+            McDimension::TheEnd
+        );
+        let custom = McDimension::from_id("mymod:mining");
+        assert_eq!(custom, McDimension::Custom("mymod:mining".to_string()));
+        assert_eq!(custom.namespaced_id(), ("mymod", "mining"));
+        assert_eq!(
+            McDimension::Custom("mining".to_string()).namespaced_id(),
+            ("minecraft", "mining")
+        );
+    }
+
+    #[test]
+    fn test_server_layout_vanilla_region_dir() {
+        let layout = ServerLayout::new(PathBuf::from("/srv"), "world", ServerDirStructure::Vanilla);
+
+        assert_eq!(
+            layout.region_dir(&McDimension::Overworld),
+            PathBuf::from("/srv/world/region")
+        );
+        assert_eq!(
+            layout.region_dir(&McDimension::Nether),
+            PathBuf::from("/srv/world/DIM-1/region")
+        );
+        assert_eq!(
+            layout.region_dir(&McDimension::TheEnd),
+            PathBuf::from("/srv/world/DIM1/region")
+        );
+        assert_eq!(
+            layout.region_dir(&McDimension::from_id("mymod:mining")),
+            PathBuf::from("/srv/world/dimensions/mymod/mining/region")
+        );
+    }
+
+    #[test]
+    fn test_server_layout_plugin_region_dir() {
+        let layout = ServerLayout::new(PathBuf::from("/srv"), "world", ServerDirStructure::Plugin);
+
+        assert_eq!(
+            layout.region_dir(&McDimension::Overworld),
+            PathBuf::from("/srv/world/region")
+        );
+        assert_eq!(
+            layout.region_dir(&McDimension::Nether),
+            PathBuf::from("/srv/world_nether/DIM-1/region")
+        );
+        assert_eq!(
+            layout.region_dir(&McDimension::TheEnd),
+            PathBuf::from("/srv/world_the_end/DIM1/region")
+        );
+        assert_eq!(
+            layout.region_dir(&McDimension::from_id("mymod:mining")),
+            PathBuf::from("/srv/world_mining/dimensions/mymod/mining/region")
+        );
+    }
+
+    #[test]
+    fn test_dimension_retain_existing_chunks() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dim = Dimension::new(temp_dir.path().join("region"));
+        for pos in [
+            ChunkPos::new(0, 0),
+            ChunkPos::new(3, 5),
+            ChunkPos::new(-1, 40),
+        ] {
+            let mut region = dim.load_region(pos.region()).unwrap();
+            region.save_chunk(pos, &empty_chunk()).unwrap();
+            region.close().unwrap();
+        }
+        std::fs::write(temp_dir.path().join("region/notes.txt"), "not a region").unwrap();
+
+        let requested: Vec<ChunkPos> = (-2..6)
+            .flat_map(|x| (-2..6).map(move |z| ChunkPos::new(x, z)))
+            .chain([ChunkPos::new(-1, 40), ChunkPos::new(100, 100)])
+            .collect();
+        let retained = dim.retain_existing_chunks(&requested).unwrap();
+
+        assert_eq!(
+            retained,
+            vec![
+                ChunkPos::new(0, 0),
+                ChunkPos::new(3, 5),
+                ChunkPos::new(-1, 40)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dimension_unreadable_chunks() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dim = Dimension::new(temp_dir.path().join("region"));
+        let mut region = dim.load_region(RegionPos::new(0, 0)).unwrap();
+        region
+            .save_chunk(ChunkPos::new(0, 0), &empty_chunk())
+            .unwrap();
+        region.close().unwrap();
+        // 読み込めないチャンク
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(temp_dir.path().join("region/r.0.0.mca"))
+            .unwrap();
+        let mut raw = fastanvil::Region::from_stream(file).unwrap();
+        raw.write_chunk(1, 0, b"not nbt").unwrap();
+        drop(raw);
+
+        let mut unreadable = dim
+            .unreadable_chunks(&[
+                ChunkPos::new(0, 0),
+                ChunkPos::new(1, 0),
+                ChunkPos::new(2, 0),
+                ChunkPos::new(40, 0),
+            ])
+            .unwrap();
+        unreadable.sort_by_key(|pos| (pos.x, pos.z));
+        assert_eq!(
+            unreadable,
+            vec![
+                ChunkPos::new(1, 0),
+                ChunkPos::new(2, 0),
+                ChunkPos::new(40, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dimension_missing_chunks() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dim = Dimension::new(temp_dir.path().join("region"));
+        for pos in [ChunkPos::new(0, 0), ChunkPos::new(-1, 1)] {
+            let mut region = dim.load_region(pos.region()).unwrap();
+            region.save_chunk(pos, &empty_chunk()).unwrap();
+            region.close().unwrap();
+        }
+
+        let missing = dim
+            .missing_chunks(&chunks_in_area(ChunkPos::new(-1, 0), ChunkPos::new(0, 1)))
+            .unwrap();
+        assert_eq!(missing, vec![ChunkPos::new(-1, 0), ChunkPos::new(0, 1)]);
+    }
+
+    #[test]
+    fn test_dimension_missing_chunks_includes_proto_chunks() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dim = Dimension::new(temp_dir.path().join("region"));
+        let mut region = dim.load_region((0, 0)).unwrap();
+        region
+            .save_chunk(ChunkPos::new(0, 0), &empty_chunk())
+            .unwrap();
+        // 前回の生成範囲の縁に残った生成途中のチャンク
+        region
+            .save_chunk(
+                ChunkPos::new(1, 0),
+                &Chunk::new(vec![], "minecraft:features"),
+            )
+            .unwrap();
+        // 1.18より前の形式で生成を終えたチャンク
+        region
+            .raw
+            .write_chunk(
+                2,
+                0,
+                &fastnbt::to_bytes(&fastnbt::nbt!({ "Level": { "Status": "full" } })).unwrap(),
+            )
+            .unwrap();
+        region.close().unwrap();
+
+        let missing = dim
+            .missing_chunks(&chunks_in_area(ChunkPos::new(0, 0), ChunkPos::new(3, 0)))
+            .unwrap();
+        assert_eq!(missing, vec![ChunkPos::new(1, 0), ChunkPos::new(3, 0)]);
+    }
+
+    #[test]
+    fn test_chunks_in_area() {
+        let expected = vec![
+            ChunkPos::new(-1, 2),
+            ChunkPos::new(-1, 3),
+            ChunkPos::new(0, 2),
+            ChunkPos::new(0, 3),
+        ];
+        assert_eq!(
+            chunks_in_area(ChunkPos::new(-1, 2), ChunkPos::new(0, 3)),
+            expected
+        );
+        // 角の指定順によらない
+        assert_eq!(
+            chunks_in_area(ChunkPos::new(0, 3), ChunkPos::new(-1, 2)),
+            expected
+        );
+        assert_eq!(
+            chunks_in_area(ChunkPos::new(5, 5), ChunkPos::new(5, 5)),
+            vec![ChunkPos::new(5, 5)]
+        );
+    }
+
+    #[test]
+    fn test_dimension_list_chunks_missing_directory() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dim = Dimension::new(temp_dir.path().join("region"));
+        assert!(dim.list_chunks().unwrap().is_empty());
+    }
+
+    fn write_level_dat(layout: &ServerLayout, data: HashMap<String, Value>) {
+        let path = layout.level_dat_path();
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let mut root = HashMap::new();
+        root.insert("Data".to_string(), Value::Compound(data));
+        let file = File::create(path).unwrap();
+        crate::infra::nbt::write_nbt_gzip(file, &Value::Compound(root)).unwrap();
+    }
+
+    #[test]
+    fn test_server_layout_get_data_version() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let layout = ServerLayout::new(
+            temp_dir.path().to_path_buf(),
+            "world",
+            ServerDirStructure::Vanilla,
+        );
+        let mut data = HashMap::new();
+        data.insert("DataVersion".to_string(), Value::Int(3465));
+        data.insert("LevelName".to_string(), Value::String("world".to_string()));
+        write_level_dat(&layout, data);
+
+        assert_eq!(layout.get_data_version().unwrap(), 3465);
+    }
+
+    #[test]
+    fn test_server_layout_get_data_version_missing_tag() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let layout = ServerLayout::new(
+            temp_dir.path().to_path_buf(),
+            "world",
+            ServerDirStructure::Vanilla,
+        );
+        let mut data = HashMap::new();
+        data.insert("version".to_string(), Value::Int(19133));
+        write_level_dat(&layout, data);
+
+        let err = layout.get_data_version().unwrap_err().to_string();
+        assert!(err.contains("DataVersion"));
+    }
+
+    #[test]
+    fn test_server_layout_region_dir_override() {
+        let layout = ServerLayout::new(PathBuf::from("/srv"), "world", ServerDirStructure::Plugin)
+            .with_region_dir_override(
+                McDimension::Nether,
+                PathBuf::from("worlds/hell/DIM-1/region"),
+            );
+
+        assert_eq!(
+            layout.region_dir(&McDimension::Nether),
+            PathBuf::from("/srv/worlds/hell/DIM-1/region")
+        );
+        // 上書きしていないディメンションは既定の配置のまま
+        assert_eq!(
+            layout.region_dir(&McDimension::TheEnd),
+            PathBuf::from("/srv/world_the_end/DIM1/region")
+        );
+    }
+
+    // 座標だけを持つチャンクのデータをzlib圧縮して、長さと圧縮形式を付けたもの
+    fn raw_chunk(x: i32, z: i32) -> Vec<u8> {
+        let mut nbt = HashMap::new();
+        nbt.insert("xPos".to_string(), Value::Int(x));
+        nbt.insert("zPos".to_string(), Value::Int(z));
+        let mut encoder =
+            flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder
+            .write_all(&fastnbt::to_bytes(&Value::Compound(nbt)).unwrap())
+            .unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let mut raw = ((compressed.len() + 1) as u32).to_be_bytes().to_vec();
+        raw.push(2);
+        raw.extend(compressed);
+        raw
+    }
+
+    fn set_location(region: &mut [u8], x: usize, z: usize, offset: u32, sectors: u8) {
+        let index = (x + z * 32) * 4;
+        region[index..index + 3].copy_from_slice(&offset.to_be_bytes()[1..]);
+        region[index + 3] = sectors;
+    }
+
+    fn put_sector(region: &mut Vec<u8>, sector: usize, data: &[u8]) {
+        let start = sector * SECTOR_SIZE as usize;
+        let end = start + SECTOR_SIZE as usize;
+        if region.len() < end {
+            region.resize(end, 0);
+        }
+        region[start..start + data.len()].copy_from_slice(data);
+    }
+
+    #[test]
+    fn test_validate_region_valid() {
+        let mut region = vec![0u8; REGION_HEADER_SIZE as usize];
+        set_location(&mut region, 0, 0, 2, 1);
+        put_sector(&mut region, 2, &raw_chunk(32, -32));
+        set_location(&mut region, 5, 7, 3, 1);
+        put_sector(&mut region, 3, &raw_chunk(37, -25));
+
+        let issues = validate_region(RegionPos::new(1, -1), &mut Cursor::new(region)).unwrap();
+        assert!(issues.is_empty(), "{:?}", issues);
+    }
+
+    #[test]
+    fn test_validate_region_detects_corruption() {
+        let pos = RegionPos::new(0, 0);
+        let mut region = vec![0u8; REGION_HEADER_SIZE as usize];
+        // (0, 0) と (1, 0) が同じセクターを指す
+        set_location(&mut region, 0, 0, 2, 1);
+        set_location(&mut region, 1, 0, 2, 1);
+        put_sector(&mut region, 2, &raw_chunk(0, 0));
+        // (2, 0) にはほかのチャンクの座標が記録されている
+        set_location(&mut region, 2, 0, 3, 1);
+        put_sector(&mut region, 3, &raw_chunk(9, 9));
+        // (3, 0) のデータの長さが0
+        set_location(&mut region, 3, 0, 4, 1);
+        put_sector(&mut region, 4, &[0, 0, 0, 0, 2]);
+        // (4, 0) はファイルの終端より後を指す
+        set_location(&mut region, 4, 0, 100, 1);
+        // (5, 0) のデータが壊れている
+        set_location(&mut region, 5, 0, 5, 1);
+        put_sector(&mut region, 5, &[0, 0, 0, 4, 2, 1, 2, 3]);
+        // (6, 0) はヘッダーの中を指す
+        set_location(&mut region, 6, 0, 1, 1);
+
+        let issues = validate_region(pos, &mut Cursor::new(region)).unwrap();
+
+        assert!(issues.contains(&RegionIssue::OverlappingSectors {
+            first: ChunkPos::new(0, 0),
+            second: ChunkPos::new(1, 0)
+        }));
+        // 重なったセクターには (0, 0) のデータがあるので、(1, 0) の座標も一致しない
+        assert!(issues.contains(&RegionIssue::CoordinateMismatch {
+            chunk: ChunkPos::new(1, 0),
+            stored: ChunkPos::new(0, 0)
+        }));
+        assert!(issues.contains(&RegionIssue::CoordinateMismatch {
+            chunk: ChunkPos::new(2, 0),
+            stored: ChunkPos::new(9, 9)
+        }));
+        assert!(issues.contains(&RegionIssue::EmptyChunk {
+            chunk: ChunkPos::new(3, 0)
+        }));
+        assert!(issues.contains(&RegionIssue::OffsetPastEnd {
+            chunk: ChunkPos::new(4, 0)
+        }));
+        assert!(issues.iter().any(|issue| matches!(
+            issue,
+            RegionIssue::UnreadableChunk { chunk, .. } if *chunk == ChunkPos::new(5, 0)
+        )));
+        assert!(issues.contains(&RegionIssue::OffsetInHeader {
+            chunk: ChunkPos::new(6, 0)
+        }));
+        assert_eq!(issues.len(), 7, "{:?}", issues);
+    }
+
+    #[test]
+    fn test_list_region_chunks_reads_header_only() {
+        let pos = RegionPos::new(-1, 2);
+        let mut region = vec![0u8; REGION_HEADER_SIZE as usize];
+        set_location(&mut region, 1, 2, 2, 1);
+        put_sector(&mut region, 2, &raw_chunk(-31, 66));
+        // データが壊れていてもヘッダーにあれば列挙する
+        set_location(&mut region, 31, 31, 3, 1);
+        put_sector(&mut region, 3, b"garbage");
+        // セクター数が0のエントリは存在しないとみなす
+        set_location(&mut region, 4, 4, 4, 0);
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dim = Dimension::new(temp_dir.path().join("region"));
+        std::fs::create_dir_all(temp_dir.path().join("region")).unwrap();
+        std::fs::write(
+            temp_dir.path().join("region").join(pos.to_file_name()),
+            &region,
+        )
+        .unwrap();
+
+        let expected = vec![ChunkPos::new(-31, 66), ChunkPos::new(-1, 95)];
+        assert_eq!(
+            list_region_chunks(pos, &mut Cursor::new(region)).unwrap(),
+            expected
+        );
+        assert_eq!(dim.list_chunks().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_server_layout_dimension_carries_kind() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let layout = ServerLayout::new(
+            temp_dir.path().to_path_buf(),
+            "world",
+            ServerDirStructure::Vanilla,
+        );
+        let nether = layout.dimension(&McDimension::Nether);
+        let mut region = nether.load_region((0, -1)).unwrap();
+        region
+            .save_chunk(ChunkPos::new(3, -4), &empty_chunk())
+            .unwrap();
+        region.close().unwrap();
+
+        assert_eq!(nether.kind(), &McDimension::Nether);
+        assert_eq!(nether.list_chunks().unwrap(), vec![ChunkPos::new(3, -4)]);
+        assert!(
+            temp_dir
+                .path()
+                .join("world/DIM-1/region/r.0.-1.mca")
+                .exists()
+        );
+        // オーバーワールドのリージョンには書き込まれない
+        assert!(
+            layout
+                .dimension(&McDimension::Overworld)
+                .list_chunks()
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(
+            Dimension::new(temp_dir.path().join("region")).kind(),
+            &McDimension::Overworld
+        );
+    }
+
+    #[test]
+    fn test_dimension_compact_region() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dim = Dimension::new(temp_dir.path().join("region"));
+        // 圧縮しても小さくならないデータで、書き換えるたびにチャンクを大きくする
+        let mut seed = 1u32;
+        let mut chunk_with_noise = |len: usize| {
+            let mut chunk = empty_chunk();
+            let noise = (0..len)
+                .map(|_| {
+                    seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                    (seed >> 16) as i8
+                })
+                .collect();
+            chunk.set_tag("noise", Value::ByteArray(fastnbt::ByteArray::new(noise)));
+            chunk
+        };
+        let mut region = dim.load_region((0, 0)).unwrap();
+        region
+            .save_chunk(ChunkPos::new(0, 0), &chunk_with_noise(100))
+            .unwrap();
+        for len in [5_000, 10_000, 20_000, 40_000] {
+            region
+                .save_chunk(ChunkPos::new(1, 1), &chunk_with_noise(len))
+                .unwrap();
+        }
+        let last = chunk_with_noise(6_000);
+        region.save_chunk(ChunkPos::new(1, 1), &last).unwrap();
+        region.close().unwrap();
+
+        let path = temp_dir.path().join("region/r.0.0.mca");
+        let size_before = std::fs::metadata(&path).unwrap().len();
+        dim.compact_region((0, 0)).unwrap();
+        let size_after = std::fs::metadata(&path).unwrap().len();
+
+        assert!(
+            size_after < size_before,
+            "{} >= {}",
+            size_after,
+            size_before
+        );
+        assert_eq!(size_after % SECTOR_SIZE, 0);
+        assert!(!temp_dir.path().join("region/r.0.0.mca.tmp").exists());
+        assert!(dim.validate_region((0, 0)).unwrap().iter().all(|issue| {
+            // empty_chunkは座標を持たない
+            matches!(issue, RegionIssue::UnreadableChunk { .. })
+        }));
+        let mut region = dim.load_region((0, 0)).unwrap();
+        let loaded = region.load_chunk(ChunkPos::new(1, 1)).unwrap().unwrap();
+        assert_eq!(loaded.get_tag("noise"), last.get_tag("noise"));
+        assert!(region.load_chunk(ChunkPos::new(0, 0)).unwrap().is_some());
+        assert_eq!(
+            dim.list_chunks().unwrap(),
+            vec![ChunkPos::new(0, 0), ChunkPos::new(1, 1)]
+        );
+    }
+
+    #[test]
+    fn test_region_rejects_locations_beyond_file() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dim = Dimension::new(temp_dir.path().join("region"));
+        let mut region = dim.load_region((0, 0)).unwrap();
+        for pos in [ChunkPos::new(0, 0), ChunkPos::new(2, 0)] {
+            region.save_chunk(pos, &empty_chunk()).unwrap();
+        }
+        region.close().unwrap();
+        let mut region = std::fs::read(temp_dir.path().join("region/r.0.0.mca")).unwrap();
+        // ファイルの終端より後ろを指す
+        set_location(&mut region, 1, 0, 50, 1);
+        // (2, 0) のデータの途中でファイルが切れている
+        region.truncate(3 * SECTOR_SIZE as usize + 8);
+
+        let mut region = Region::from_stream(RegionPos::new(0, 0), Cursor::new(region)).unwrap();
+        assert!(region.load_chunk((0, 0)).unwrap().is_some());
+        let err = region.load_chunk((1, 0)).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "region r.0.0.mca: chunk (1, 0) offset beyond file length"
+        );
+        let err = region.load_chunk((2, 0)).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "region r.0.0.mca: chunk (2, 0) data beyond file length"
+        );
+
+        // 書き直したチャンクは読み込める
+        region
+            .save_chunk(ChunkPos::new(1, 0), &empty_chunk())
+            .unwrap();
+        assert!(region.load_chunk((1, 0)).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_region_rejects_truncated_header() {
+        let result = Region::from_stream(RegionPos::new(-1, 2), Cursor::new(vec![0u8; 100]));
+        let err = result.err().unwrap();
+        assert!(
+            format!("{:#}", err).starts_with("region r.-1.2.mca: file is truncated"),
+            "{:#}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_region_load_chunk_activity() {
+        let activity_tags = |last_update, inhabited_time| {
+            HashMap::from([
+                ("LastUpdate".to_string(), Value::Long(last_update)),
+                ("InhabitedTime".to_string(), Value::Long(inhabited_time)),
+                ("xPos".to_string(), Value::Int(0)),
+            ])
+        };
+        let mut region = Region::create(RegionPos::new(0, 0), Cursor::new(Vec::new())).unwrap();
+        // 1.18以降の形式
+        let mut chunk = empty_chunk();
+        for (name, value) in activity_tags(1200, 300) {
+            chunk.set_tag(name, value);
+        }
+        region.save_chunk(ChunkPos::new(0, 0), &chunk).unwrap();
+        // 1.18より前の `Level` 以下に記録された形式
+        let old = Value::Compound(HashMap::from([(
+            "Level".to_string(),
+            Value::Compound(activity_tags(50, 0)),
+        )]));
+        region
+            .raw
+            .write_chunk(1, 0, &fastnbt::to_bytes(&old).unwrap())
+            .unwrap();
+        // 記録されていないチャンク
+        region
+            .save_chunk(ChunkPos::new(2, 0), &empty_chunk())
+            .unwrap();
+
+        let expected = ChunkActivity {
+            last_update: Some(1200),
+            inhabited_time: Some(300),
+        };
+        assert_eq!(region.load_chunk_activity((0, 0)).unwrap(), Some(expected));
+        assert_eq!(
+            region.load_chunk((0, 0)).unwrap().unwrap().activity(),
+            expected
+        );
+        assert_eq!(
+            region.load_chunk_activity((1, 0)).unwrap(),
+            Some(ChunkActivity {
+                last_update: Some(50),
+                inhabited_time: Some(0),
+            })
+        );
+        assert_eq!(
+            region.load_chunk_activity((2, 0)).unwrap(),
+            Some(ChunkActivity::default())
+        );
+        assert_eq!(region.load_chunk_activity((3, 0)).unwrap(), None);
+    }
+
+    #[test]
+    fn test_dimension_inhabited_chunks() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dim = Dimension::new(temp_dir.path().join("region"));
+        for (pos, inhabited_time) in [
+            (ChunkPos::new(0, 0), Some(0)),
+            (ChunkPos::new(1, 0), Some(40)),
+            (ChunkPos::new(2, 0), Some(2000)),
+            (ChunkPos::new(3, 0), None),
+            (ChunkPos::new(-1, 0), Some(5)),
+        ] {
+            let mut chunk = empty_chunk();
+            if let Some(time) = inhabited_time {
+                chunk.set_tag("InhabitedTime", Value::Long(time));
+            }
+            let mut region = dim.load_region(pos.region()).unwrap();
+            region.save_chunk(pos, &chunk).unwrap();
+            region.close().unwrap();
+        }
+
+        let requested: Vec<ChunkPos> = (-1..5).map(|x| ChunkPos::new(x, 0)).collect();
+        assert_eq!(
+            dim.inhabited_chunks(&requested, 0).unwrap(),
+            vec![
+                ChunkPos::new(-1, 0),
+                ChunkPos::new(1, 0),
+                ChunkPos::new(2, 0)
+            ]
+        );
+        assert_eq!(
+            dim.inhabited_chunks(&requested, 100).unwrap(),
+            vec![ChunkPos::new(2, 0)]
+        );
+        assert!(
+            dim.inhabited_chunks(&[ChunkPos::new(100, 100)], 0)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_dimension_validate_region_written_by_region() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dim = Dimension::new(temp_dir.path().join("region"));
+        let mut region = dim.load_region((0, 0)).unwrap();
+        region
+            .save_chunk(ChunkPos::new(1, 2), &empty_chunk())
+            .unwrap();
+        region.close().unwrap();
+
+        // empty_chunkは座標を持たない
+        let issues = dim.validate_region((0, 0)).unwrap();
+        assert!(matches!(
+            issues.as_slice(),
+            [RegionIssue::UnreadableChunk { chunk, .. }] if *chunk == ChunkPos::new(1, 2)
+        ));
+    }
+}
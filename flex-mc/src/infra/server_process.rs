@@ -0,0 +1,276 @@
+use std::{
+    path::Path,
+    pin::Pin,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    task::{Context, Poll},
+};
+
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use tokio::{
+    io::{AsyncBufRead, AsyncWrite, BufReader},
+    process::{Child, Command},
+    sync::mpsc,
+};
+
+/// サーバーの標準入力
+pub type ServerStdin = Box<dyn AsyncWrite + Unpin + Send>;
+/// サーバーの標準出力
+pub type ServerStdout = Box<dyn AsyncBufRead + Unpin + Send>;
+
+/// マインクラフトサーバーを起動する
+#[async_trait]
+pub trait ServerLauncher: Send + Sync {
+    async fn start(
+        &self,
+        command: std::process::Command,
+        current_dir: &Path,
+    ) -> Result<Box<dyn ServerProcess>>;
+}
+
+/// 起動中のマインクラフトサーバーのプロセス
+#[async_trait]
+pub trait ServerProcess: Send {
+    /// 標準入力を取り出す。2回目以降は `None` を返す
+    fn take_stdin(&mut self) -> Option<ServerStdin>;
+    /// 標準出力を取り出す。2回目以降は `None` を返す
+    fn take_stdout(&mut self) -> Option<ServerStdout>;
+    /// プロセスの終了を待つ
+    async fn wait(&mut self) -> Result<()>;
+    /// プロセスを強制終了する
+    async fn kill(&mut self) -> Result<()>;
+}
+
+/// tokioのプロセスとしてサーバーを起動する
+pub struct TokioServerLauncher;
+
+#[async_trait]
+impl ServerLauncher for TokioServerLauncher {
+    async fn start(
+        &self,
+        command: std::process::Command,
+        current_dir: &Path,
+    ) -> Result<Box<dyn ServerProcess>> {
+        // 呼び出し元のfutureが破棄されてもサーバーが残らないよう、破棄時に強制終了する
+        let child = Command::from(command)
+            .kill_on_drop(true)
+            .current_dir(current_dir)
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .spawn()?;
+        Ok(Box::new(TokioServerProcess { child }))
+    }
+}
+
+pub struct TokioServerProcess {
+    child: Child,
+}
+
+#[async_trait]
+impl ServerProcess for TokioServerProcess {
+    fn take_stdin(&mut self) -> Option<ServerStdin> {
+        self.child
+            .stdin
+            .take()
+            .map(|stdin| Box::new(stdin) as ServerStdin)
+    }
+
+    fn take_stdout(&mut self) -> Option<ServerStdout> {
+        self.child
+            .stdout
+            .take()
+            .map(|stdout| Box::new(BufReader::new(stdout)) as ServerStdout)
+    }
+
+    async fn wait(&mut self) -> Result<()> {
+        let status = self.child.wait().await?;
+        if !status.success() {
+            return Err(anyhow!("Server exited with {}", status));
+        }
+        Ok(())
+    }
+
+    async fn kill(&mut self) -> Result<()> {
+        self.child.kill().await?;
+        Ok(())
+    }
+}
+
+/// 決められた内容を標準出力に出力し、標準入力に書き込まれたコマンドを記録するモックサーバー
+pub struct MockServerLauncher {
+    stdout: Vec<u8>,
+    commands: Arc<Mutex<Vec<String>>>,
+    command_tx: mpsc::UnboundedSender<String>,
+    hang_until_killed: bool,
+    killed: Arc<AtomicBool>,
+}
+
+impl MockServerLauncher {
+    /// 受信したコマンドを1行ずつ受け取るチャンネルとともに作成する
+    pub fn new(stdout_lines: &[&str]) -> (Self, mpsc::UnboundedReceiver<String>) {
+        let (command_tx, command_rx) = mpsc::unbounded_channel();
+        let stdout = stdout_lines
+            .iter()
+            .flat_map(|line| format!("{}\n", line).into_bytes())
+            .collect();
+        let launcher = MockServerLauncher {
+            stdout,
+            commands: Arc::new(Mutex::new(Vec::new())),
+            command_tx,
+            hang_until_killed: false,
+            killed: Arc::new(AtomicBool::new(false)),
+        };
+        (launcher, command_rx)
+    }
+
+    /// `stop` を無視して強制終了されるまで終了しないサーバーにする
+    pub fn with_hang_until_killed(mut self) -> Self {
+        self.hang_until_killed = true;
+        self
+    }
+
+    /// 起動したサーバーが強制終了されたかどうか
+    pub fn was_killed(&self) -> bool {
+        self.killed.load(Ordering::SeqCst)
+    }
+
+    /// これまでに受信したコマンド
+    pub fn commands(&self) -> Vec<String> {
+        self.commands.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl ServerLauncher for MockServerLauncher {
+    async fn start(
+        &self,
+        _command: std::process::Command,
+        _current_dir: &Path,
+    ) -> Result<Box<dyn ServerProcess>> {
+        Ok(Box::new(MockServerProcess {
+            stdin: Some(MockStdin {
+                buf: Vec::new(),
+                commands: self.commands.clone(),
+                command_tx: self.command_tx.clone(),
+            }),
+            stdout: Some(self.stdout.clone()),
+            hang_until_killed: self.hang_until_killed,
+            exited: false,
+            killed: self.killed.clone(),
+        }))
+    }
+}
+
+pub struct MockServerProcess {
+    stdin: Option<MockStdin>,
+    stdout: Option<Vec<u8>>,
+    hang_until_killed: bool,
+    exited: bool,
+    killed: Arc<AtomicBool>,
+}
+
+#[async_trait]
+impl ServerProcess for MockServerProcess {
+    fn take_stdin(&mut self) -> Option<ServerStdin> {
+        self.stdin
+            .take()
+            .map(|stdin| Box::new(stdin) as ServerStdin)
+    }
+
+    fn take_stdout(&mut self) -> Option<ServerStdout> {
+        self.stdout
+            .take()
+            .map(|stdout| Box::new(std::io::Cursor::new(stdout)) as ServerStdout)
+    }
+
+    async fn wait(&mut self) -> Result<()> {
+        if self.hang_until_killed && !self.killed.load(Ordering::SeqCst) {
+            std::future::pending::<()>().await;
+        }
+        self.exited = true;
+        Ok(())
+    }
+
+    async fn kill(&mut self) -> Result<()> {
+        self.killed.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+// `TokioServerLauncher` と同様に、終了前に破棄されたら強制終了する
+impl Drop for MockServerProcess {
+    fn drop(&mut self) {
+        if !self.exited {
+            self.killed.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+// 書き込まれた内容を行ごとに記録する標準入力
+struct MockStdin {
+    buf: Vec<u8>,
+    commands: Arc<Mutex<Vec<String>>>,
+    command_tx: mpsc::UnboundedSender<String>,
+}
+
+impl AsyncWrite for MockStdin {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        self.buf.extend_from_slice(buf);
+        while let Some(pos) = self.buf.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.buf.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&line[..pos]).to_string();
+            self.commands.lock().unwrap().push(line.clone());
+            // 受信側が閉じていても記録は続ける
+            let _ = self.command_tx.send(line);
+        }
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_mock_server_scripts_stdout_and_records_stdin() {
+        let (launcher, mut command_rx) = MockServerLauncher::new(&["Starting", "Done!"]);
+        let mut process = launcher
+            .start(std::process::Command::new("java"), Path::new("/server"))
+            .await
+            .unwrap();
+
+        let mut lines = process.take_stdout().unwrap().lines();
+        assert_eq!(
+            lines.next_line().await.unwrap().as_deref(),
+            Some("Starting")
+        );
+        assert_eq!(lines.next_line().await.unwrap().as_deref(), Some("Done!"));
+        assert_eq!(lines.next_line().await.unwrap(), None);
+
+        let mut stdin = process.take_stdin().unwrap();
+        stdin.write_all(b"say hel").await.unwrap();
+        stdin.write_all(b"lo\nstop\n").await.unwrap();
+        assert!(process.take_stdin().is_none());
+
+        assert_eq!(command_rx.recv().await.as_deref(), Some("say hello"));
+        assert_eq!(command_rx.recv().await.as_deref(), Some("stop"));
+        assert_eq!(launcher.commands(), vec!["say hello", "stop"]);
+        process.wait().await.unwrap();
+    }
+}
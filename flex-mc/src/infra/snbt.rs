@@ -0,0 +1,637 @@
+use std::collections::HashMap;
+
+use anyhow::{Result, bail};
+use fastnbt::Value;
+
+/// NBTを人が読めるSNBT（`{name:"Test",x:10,arr:[I;1,2,3]}` の形式）に変換する。
+/// コンパウンドのキーは出力が一定になるように辞書順に並べる
+pub fn to_snbt(value: &Value) -> String {
+    let mut out = String::new();
+    write_value(&mut out, value);
+    out
+}
+
+fn write_value(out: &mut String, value: &Value) {
+    match value {
+        Value::Byte(v) => out.push_str(&format!("{}b", v)),
+        Value::Short(v) => out.push_str(&format!("{}s", v)),
+        Value::Int(v) => out.push_str(&v.to_string()),
+        Value::Long(v) => out.push_str(&format!("{}L", v)),
+        Value::Float(v) => write_decimal(out, format!("{:?}", v), 'f'),
+        Value::Double(v) => write_decimal(out, format!("{:?}", v), 'd'),
+        Value::String(v) => write_quoted(out, v),
+        Value::ByteArray(values) => write_array(out, "B", values.iter().map(|v| format!("{}b", v))),
+        Value::IntArray(values) => write_array(out, "I", values.iter().map(|v| v.to_string())),
+        Value::LongArray(values) => write_array(out, "L", values.iter().map(|v| format!("{}L", v))),
+        Value::List(values) => {
+            out.push('[');
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_value(out, value);
+            }
+            out.push(']');
+        }
+        Value::Compound(fields) => {
+            let mut keys: Vec<&String> = fields.keys().collect();
+            keys.sort();
+            out.push('{');
+            for (i, key) in keys.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                if is_unquoted_key(key) {
+                    out.push_str(key);
+                } else {
+                    write_quoted(out, key);
+                }
+                out.push(':');
+                write_value(out, &fields[key]);
+            }
+            out.push('}');
+        }
+    }
+}
+
+// Debug表記は `1.0` のように常に小数点か指数を含む。
+// NaN・無限大はJavaの `Float.toString` と同じ表記にし、from_snbt で読み戻せるようにする
+fn write_decimal(out: &mut String, debug: String, suffix: char) {
+    let body = match debug.as_str() {
+        "inf" => "Infinity",
+        "-inf" => "-Infinity",
+        other => other,
+    };
+    out.push_str(body);
+    out.push(suffix);
+}
+
+fn write_array(out: &mut String, prefix: &str, values: impl Iterator<Item = String>) {
+    out.push('[');
+    out.push_str(prefix);
+    out.push(';');
+    out.push_str(&values.collect::<Vec<_>>().join(","));
+    out.push(']');
+}
+
+fn write_quoted(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        if c == '"' || c == '\\' {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out.push('"');
+}
+
+// 引用符なしで書けるキーかどうか
+fn is_unquoted_key(key: &str) -> bool {
+    !key.is_empty() && key.chars().all(is_unquoted_char)
+}
+
+/// SNBTをNBTに変換する。型の接尾辞（`10b`、`10s`、`10L`、`1.5f`、`1.5d`）、
+/// 配列（`[B;…]`、`[I;…]`、`[L;…]`）、引用符あり・なしの文字列、`true`/`false` を解釈する。
+/// 接尾辞のない整数はInt、小数はDoubleになる
+pub fn from_snbt(input: &str) -> Result<Value> {
+    let mut parser = Parser {
+        chars: input.char_indices().collect(),
+        pos: 0,
+        len: input.len(),
+        depth: 0,
+    };
+    let value = parser.parse_value()?;
+    parser.skip_whitespace();
+    if parser.pos < parser.chars.len() {
+        bail!("Unexpected trailing input at position {}", parser.offset());
+    }
+    Ok(value)
+}
+
+// コンパウンド・リストの入れ子の深さの上限（マインクラフト本体と同じ）
+const MAX_DEPTH: usize = 512;
+
+struct Parser {
+    chars: Vec<(usize, char)>,
+    pos: usize,
+    len: usize,
+    // 読み込み中のコンパウンド・リストの入れ子の深さ
+    depth: usize,
+}
+
+impl Parser {
+    // エラーメッセージに使う、入力文字列中のバイト位置
+    fn offset(&self) -> usize {
+        self.chars
+            .get(self.pos)
+            .map_or(self.len, |(offset, _)| *offset)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).map(|(_, c)| *c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<()> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(c) => bail!(
+                "Expected '{}' but found '{}' at position {}",
+                expected,
+                c,
+                self.offset()
+            ),
+            None => bail!(
+                "Expected '{}' but reached the end of input at position {}",
+                expected,
+                self.offset()
+            ),
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Value> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(c @ ('{' | '[')) => {
+                // 深い入れ子でスタックを使い切らないよう、再帰の深さを制限する
+                if self.depth >= MAX_DEPTH {
+                    bail!(
+                        "Nesting exceeds depth {} at position {}",
+                        MAX_DEPTH,
+                        self.offset()
+                    );
+                }
+                self.depth += 1;
+                let value = if c == '{' {
+                    self.parse_compound()
+                } else {
+                    self.parse_list_or_array()
+                };
+                self.depth -= 1;
+                value
+            }
+            Some('"' | '\'') => Ok(Value::String(self.parse_quoted()?)),
+            Some(_) => {
+                let start = self.offset();
+                let token = self.parse_unquoted()?;
+                parse_scalar(&token, start)
+            }
+            None => bail!("Expected a value at position {}", self.offset()),
+        }
+    }
+
+    fn parse_compound(&mut self) -> Result<Value> {
+        self.expect('{')?;
+        let mut fields = HashMap::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.pos += 1;
+            return Ok(Value::Compound(fields));
+        }
+        loop {
+            self.skip_whitespace();
+            let key_offset = self.offset();
+            let key = match self.peek() {
+                Some('"' | '\'') => self.parse_quoted()?,
+                _ => self.parse_unquoted()?,
+            };
+            self.expect(':')?;
+            let value = self.parse_value()?;
+            if fields.insert(key.clone(), value).is_some() {
+                bail!("Duplicate key '{}' at position {}", key, key_offset);
+            }
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some('}') => {
+                    self.pos += 1;
+                    return Ok(Value::Compound(fields));
+                }
+                _ => bail!("Expected ',' or '}}' at position {}", self.offset()),
+            }
+        }
+    }
+
+    fn parse_list_or_array(&mut self) -> Result<Value> {
+        self.expect('[')?;
+        let array_type = match (self.peek(), self.chars.get(self.pos + 1)) {
+            (Some(c @ ('B' | 'I' | 'L')), Some((_, ';'))) => Some(c),
+            _ => None,
+        };
+        if let Some(array_type) = array_type {
+            self.pos += 2;
+            return self.parse_array(array_type);
+        }
+
+        let mut values: Vec<Value> = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.pos += 1;
+            return Ok(Value::List(values));
+        }
+        loop {
+            self.skip_whitespace();
+            let offset = self.offset();
+            let value = self.parse_value()?;
+            // NBTのリストの要素はすべて同じ型でなければならない
+            if let Some(first) = values.first()
+                && std::mem::discriminant(first) != std::mem::discriminant(&value)
+            {
+                bail!(
+                    "List elements must have the same type, but the element at position {} differs",
+                    offset
+                );
+            }
+            values.push(value);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some(']') => {
+                    self.pos += 1;
+                    return Ok(Value::List(values));
+                }
+                _ => bail!("Expected ',' or ']' at position {}", self.offset()),
+            }
+        }
+    }
+
+    fn parse_array(&mut self, array_type: char) -> Result<Value> {
+        let mut values = Vec::new();
+        self.skip_whitespace();
+        if self.peek() != Some(']') {
+            loop {
+                self.skip_whitespace();
+                let offset = self.offset();
+                let token = self.parse_unquoted()?;
+                values.push((parse_scalar(&token, offset)?, offset));
+                self.skip_whitespace();
+                match self.peek() {
+                    Some(',') => self.pos += 1,
+                    Some(']') => break,
+                    _ => bail!("Expected ',' or ']' at position {}", self.offset()),
+                }
+            }
+        }
+        self.pos += 1;
+
+        let invalid = |offset: usize| {
+            anyhow::anyhow!(
+                "Invalid element of [{};] array at position {}",
+                array_type,
+                offset
+            )
+        };
+        Ok(match array_type {
+            'B' => Value::ByteArray(fastnbt::ByteArray::new(
+                values
+                    .into_iter()
+                    .map(|(value, offset)| match value {
+                        Value::Byte(v) => Ok(v),
+                        _ => Err(invalid(offset)),
+                    })
+                    .collect::<Result<_>>()?,
+            )),
+            'I' => Value::IntArray(fastnbt::IntArray::new(
+                values
+                    .into_iter()
+                    .map(|(value, offset)| match value {
+                        Value::Int(v) => Ok(v),
+                        _ => Err(invalid(offset)),
+                    })
+                    .collect::<Result<_>>()?,
+            )),
+            _ => Value::LongArray(fastnbt::LongArray::new(
+                values
+                    .into_iter()
+                    .map(|(value, offset)| match value {
+                        Value::Long(v) => Ok(v),
+                        Value::Int(v) => Ok(v as i64),
+                        _ => Err(invalid(offset)),
+                    })
+                    .collect::<Result<_>>()?,
+            )),
+        })
+    }
+
+    fn parse_quoted(&mut self) -> Result<String> {
+        let start = self.offset();
+        let Some(quote) = self.peek() else {
+            bail!("Expected a string at position {}", start);
+        };
+        self.pos += 1;
+        let mut s = String::new();
+        loop {
+            match self.peek() {
+                Some('\\') => {
+                    self.pos += 1;
+                    match self.peek() {
+                        Some(c @ ('\\' | '"' | '\'')) => s.push(c),
+                        _ => bail!("Invalid escape sequence at position {}", self.offset()),
+                    }
+                }
+                Some(c) if c == quote => {
+                    self.pos += 1;
+                    return Ok(s);
+                }
+                Some(c) => s.push(c),
+                None => bail!("Unterminated string starting at position {}", start),
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn parse_unquoted(&mut self) -> Result<String> {
+        let start = self.pos;
+        while self.peek().is_some_and(is_unquoted_char) {
+            self.pos += 1;
+        }
+        if self.pos == start {
+            match self.peek() {
+                Some(c) => bail!("Unexpected '{}' at position {}", c, self.offset()),
+                None => bail!("Unexpected end of input at position {}", self.offset()),
+            }
+        }
+        Ok(self.chars[start..self.pos].iter().map(|(_, c)| c).collect())
+    }
+}
+
+// 引用符なしの値を、数値・真偽値・文字列のいずれかとして解釈する
+fn parse_scalar(token: &str, offset: usize) -> Result<Value> {
+    let out_of_range = || anyhow::anyhow!("Number out of range at position {}: {}", offset, token);
+    let (body, suffix) = token.split_at(token.len() - 1);
+    let is_integer = |s: &str| {
+        let digits = s.strip_prefix(['-', '+']).unwrap_or(s);
+        !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit())
+    };
+    let is_decimal = |s: &str| {
+        s.chars().any(|c| c.is_ascii_digit())
+            && s.chars()
+                .all(|c| c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E' | '-' | '+'))
+    };
+    // to_snbt が書き出すNaN・無限大は、接尾辞が付いている場合だけ数値として読む
+    let is_suffixed_decimal =
+        |s: &str| is_decimal(s) || matches!(s, "NaN" | "Infinity" | "-Infinity" | "+Infinity");
+
+    if is_integer(token) {
+        return token.parse().map(Value::Int).map_err(|_| out_of_range());
+    }
+    match suffix {
+        "b" | "B" if is_integer(body) => {
+            return body.parse().map(Value::Byte).map_err(|_| out_of_range());
+        }
+        "s" | "S" if is_integer(body) => {
+            return body.parse().map(Value::Short).map_err(|_| out_of_range());
+        }
+        "l" | "L" if is_integer(body) => {
+            return body.parse().map(Value::Long).map_err(|_| out_of_range());
+        }
+        "f" | "F" if is_suffixed_decimal(body) => {
+            if let Ok(v) = body.parse() {
+                return Ok(Value::Float(v));
+            }
+        }
+        "d" | "D" if is_suffixed_decimal(body) => {
+            if let Ok(v) = body.parse() {
+                return Ok(Value::Double(v));
+            }
+        }
+        _ => {}
+    }
+    if is_decimal(token)
+        && let Ok(v) = token.parse()
+    {
+        return Ok(Value::Double(v));
+    }
+    Ok(match token {
+        "true" => Value::Byte(1),
+        "false" => Value::Byte(0),
+        _ => Value::String(token.to_string()),
+    })
+}
+
+fn is_unquoted_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '+')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compound(fields: Vec<(&str, Value)>) -> Value {
+        Value::Compound(
+            fields
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect::<HashMap<_, _>>(),
+        )
+    }
+
+    #[test]
+    fn test_to_snbt_scalars() {
+        assert_eq!(to_snbt(&Value::Byte(-1)), "-1b");
+        assert_eq!(to_snbt(&Value::Short(300)), "300s");
+        assert_eq!(to_snbt(&Value::Int(10)), "10");
+        assert_eq!(to_snbt(&Value::Long(1 << 40)), "1099511627776L");
+        assert_eq!(to_snbt(&Value::Float(1.5)), "1.5f");
+        assert_eq!(to_snbt(&Value::Float(2.0)), "2.0f");
+        assert_eq!(to_snbt(&Value::Double(-0.25)), "-0.25d");
+        assert_eq!(
+            to_snbt(&Value::String(r#"say "hi" \o/"#.to_string())),
+            r#""say \"hi\" \\o/""#
+        );
+    }
+
+    #[test]
+    fn test_snbt_non_finite_round_trip() {
+        assert_eq!(to_snbt(&Value::Float(f32::NAN)), "NaNf");
+        assert_eq!(to_snbt(&Value::Float(f32::INFINITY)), "Infinityf");
+        assert_eq!(to_snbt(&Value::Double(f64::NEG_INFINITY)), "-Infinityd");
+
+        for value in [
+            Value::Float(f32::INFINITY),
+            Value::Float(f32::NEG_INFINITY),
+            Value::Double(f64::INFINITY),
+            Value::Double(f64::NEG_INFINITY),
+        ] {
+            assert_eq!(from_snbt(&to_snbt(&value)).unwrap(), value);
+        }
+        // NaNは自身と等しくならないため個別に確かめる
+        assert!(matches!(
+            from_snbt(&to_snbt(&Value::Float(f32::NAN))).unwrap(),
+            Value::Float(v) if v.is_nan()
+        ));
+        assert!(matches!(
+            from_snbt(&to_snbt(&Value::Double(f64::NAN))).unwrap(),
+            Value::Double(v) if v.is_nan()
+        ));
+        // 接尾辞がなければ文字列のまま
+        assert_eq!(
+            from_snbt("Infinity").unwrap(),
+            Value::String("Infinity".to_string())
+        );
+    }
+
+    #[test]
+    fn test_to_snbt_compound() {
+        let value = compound(vec![
+            ("name", Value::String("Test".to_string())),
+            ("x", Value::Int(10)),
+            (
+                "arr",
+                Value::IntArray(fastnbt::IntArray::new(vec![1, 2, 3])),
+            ),
+        ]);
+        assert_eq!(to_snbt(&value), r#"{arr:[I;1,2,3],name:"Test",x:10}"#);
+    }
+
+    #[test]
+    fn test_to_snbt_chunk_like() {
+        let section = compound(vec![
+            ("Y", Value::Byte(-4)),
+            (
+                "block_states",
+                compound(vec![
+                    (
+                        "palette",
+                        Value::List(vec![compound(vec![(
+                            "Name",
+                            Value::String("minecraft:stone".to_string()),
+                        )])]),
+                    ),
+                    (
+                        "data",
+                        Value::LongArray(fastnbt::LongArray::new(vec![0, -1])),
+                    ),
+                ]),
+            ),
+        ]);
+        let value = compound(vec![
+            ("DataVersion", Value::Int(3465)),
+            ("sections", Value::List(vec![section])),
+            (
+                "Heightmaps",
+                Value::ByteArray(fastnbt::ByteArray::new(vec![1, -2])),
+            ),
+            ("minecraft:custom key", Value::List(vec![])),
+        ]);
+        assert_eq!(
+            to_snbt(&value),
+            concat!(
+                r#"{DataVersion:3465,Heightmaps:[B;1b,-2b],"minecraft:custom key":[],"#,
+                r#"sections:[{Y:-4b,block_states:{data:[L;0L,-1L],"#,
+                r#"palette:[{Name:"minecraft:stone"}]}}]}"#
+            )
+        );
+    }
+
+    #[test]
+    fn test_from_snbt_round_trip() {
+        let section = compound(vec![
+            ("Y", Value::Byte(-4)),
+            (
+                "BlockLight",
+                Value::ByteArray(fastnbt::ByteArray::new(vec![0, 15, -1])),
+            ),
+            (
+                "data",
+                Value::LongArray(fastnbt::LongArray::new(vec![0, i64::MIN])),
+            ),
+        ]);
+        let value = compound(vec![
+            ("DataVersion", Value::Int(3465)),
+            ("InhabitedTime", Value::Long(1200)),
+            ("Status", Value::String("minecraft:full".to_string())),
+            ("weird \"key\"", Value::Short(-7)),
+            (
+                "Motion",
+                Value::List(vec![Value::Double(0.5), Value::Double(-1e-7)]),
+            ),
+            (
+                "Rotation",
+                Value::List(vec![Value::Float(90.0), Value::Float(1.5)]),
+            ),
+            (
+                "UUID",
+                Value::IntArray(fastnbt::IntArray::new(vec![1, -2, 3, 4])),
+            ),
+            ("sections", Value::List(vec![section])),
+            ("empty", Value::List(vec![])),
+            ("nested", compound(vec![("inner", compound(vec![]))])),
+        ]);
+
+        assert_eq!(from_snbt(&to_snbt(&value)).unwrap(), value);
+    }
+
+    #[test]
+    fn test_from_snbt_literals() {
+        assert_eq!(
+            from_snbt(r#" { name : 'It\'s "quoted"' , id: stone_bricks, flag: true } "#).unwrap(),
+            compound(vec![
+                ("name", Value::String(r#"It's "quoted""#.to_string())),
+                ("id", Value::String("stone_bricks".to_string())),
+                ("flag", Value::Byte(1)),
+            ])
+        );
+        assert_eq!(from_snbt("10b").unwrap(), Value::Byte(10));
+        assert_eq!(from_snbt("10s").unwrap(), Value::Short(10));
+        assert_eq!(from_snbt("10L").unwrap(), Value::Long(10));
+        assert_eq!(from_snbt("1.5f").unwrap(), Value::Float(1.5));
+        assert_eq!(from_snbt("1.5").unwrap(), Value::Double(1.5));
+        assert_eq!(from_snbt("-3").unwrap(), Value::Int(-3));
+        assert_eq!(
+            from_snbt("[L;1,2L]").unwrap(),
+            Value::LongArray(fastnbt::LongArray::new(vec![1, 2]))
+        );
+        assert_eq!(
+            from_snbt("[I;]").unwrap(),
+            Value::IntArray(fastnbt::IntArray::new(vec![]))
+        );
+    }
+
+    #[test]
+    fn test_from_snbt_depth_limit() {
+        let nested = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(from_snbt(&nested(MAX_DEPTH)).is_ok());
+        let err = from_snbt(&nested(MAX_DEPTH + 1)).unwrap_err();
+        assert!(
+            err.to_string().contains("Nesting exceeds depth 512"),
+            "{}",
+            err
+        );
+
+        // スタックを使い切るほど深い入力もエラーになる
+        let deep = format!("{}1{}", "{a:".repeat(100_000), "}".repeat(100_000));
+        assert!(from_snbt(&deep).is_err());
+        assert!(from_snbt(&"[".repeat(1_000_000)).is_err());
+    }
+
+    #[test]
+    fn test_from_snbt_errors() {
+        let error = |input: &str| from_snbt(input).unwrap_err().to_string();
+
+        assert_eq!(error("{a:1"), "Expected ',' or '}' at position 4");
+        assert_eq!(error("{a 1}"), "Expected ':' but found '1' at position 3");
+        assert_eq!(
+            error("[1,2b]"),
+            "List elements must have the same type, but the element at position 3 differs"
+        );
+        assert_eq!(
+            error("[B;1b,300]"),
+            "Invalid element of [B;] array at position 6"
+        );
+        assert_eq!(error("300b"), "Number out of range at position 0: 300b");
+        assert_eq!(error("\"abc"), "Unterminated string starting at position 0");
+        assert_eq!(error("{a:1} x"), "Unexpected trailing input at position 6");
+        assert_eq!(error("{a:1,a:2}"), "Duplicate key 'a' at position 5");
+        assert_eq!(error(""), "Expected a value at position 0");
+    }
+}
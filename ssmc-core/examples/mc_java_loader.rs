@@ -1,5 +1,4 @@
-use ssmc_core::domain::McVanillaVersionId;
-use ssmc_core::infra::mc_java::{DefaultMcJavaLoader, McJavaLoader};
+use ssmc_core::infra::mc_java::{DefaultMcJavaLoader, JavaComponent, McJavaLoader};
 use ssmc_core::infra::trie_loader::DefaultTrieLoader;
 use ssmc_core::infra::url_fetcher::DefaultUrlFetcher;
 use std::error::Error;
@@ -45,7 +44,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let start_time = Instant::now();
 
     let java_path = loader
-        .ready_runtime(&McVanillaVersionId::new("java-runtime-alpha".to_string()))
+        .ready_runtime(&JavaComponent::new("java-runtime-alpha"))
         .await?;
 
     let elapsed = start_time.elapsed();
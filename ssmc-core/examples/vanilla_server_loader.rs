@@ -90,6 +90,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let options_basic = ServerRunOptions {
         max_memory: None,
         initial_memory: None,
+        ..Default::default()
     };
     let cmd_basic = command_factory(options_basic);
     println!(
@@ -101,6 +102,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let options_with_memory = ServerRunOptions {
         max_memory: Some(2048),
         initial_memory: Some(1024),
+        ..Default::default()
     };
     let cmd_with_memory = command_factory(options_with_memory);
     println!(
@@ -19,6 +19,10 @@ pub trait McVersion {
 pub struct ServerRunOptions {
     pub max_memory: Option<u32>,     // MB
     pub initial_memory: Option<u32>, // MB
+    /// Extra JVM arguments placed before `-jar`, e.g. `-XX:+UseG1GC`
+    pub jvm_args: Vec<String>,
+    /// Extra server arguments placed after `nogui`, e.g. `--universe worlds`
+    pub server_args: Vec<String>,
 }
 
 #[async_trait::async_trait]
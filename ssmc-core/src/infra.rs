@@ -1,5 +1,7 @@
+pub mod fabric;
 pub mod fs_handler;
 pub mod mc_java;
+pub mod paper;
 pub mod trie_loader;
 pub mod url_fetcher;
 pub mod vanilla;
@@ -0,0 +1,344 @@
+use crate::{
+    domain::{McServerLoader, McVanillaVersionId, McVersion},
+    infra::{mc_java::McJavaLoader, url_fetcher::UrlFetcher, vanilla::fetch_server_download},
+    util::file_trie::{Dir, File, Path, Permission},
+};
+use serde::Deserialize;
+use std::sync::Arc;
+use url::Url;
+
+const FABRIC_META_URL: &str = "https://meta.fabricmc.net/v2";
+
+pub struct McFabricVersion {
+    pub version: McVanillaVersionId,
+    /// Fabric loader version. The latest stable loader is used if not specified
+    pub loader_version: Option<String>,
+}
+
+impl McVersion for McFabricVersion {
+    fn vanilla_id(&self) -> McVanillaVersionId {
+        self.version.clone()
+    }
+}
+
+pub struct FabricVersionLoader {
+    url_fetcher: Arc<dyn UrlFetcher + Send + Sync>,
+    java_loader: Arc<dyn McJavaLoader + Send + Sync>,
+}
+
+impl FabricVersionLoader {
+    pub fn new(
+        url_fetcher: Arc<dyn UrlFetcher + Send + Sync>,
+        java_loader: Arc<dyn McJavaLoader + Send + Sync>,
+    ) -> Self {
+        Self {
+            url_fetcher,
+            java_loader,
+        }
+    }
+
+    async fn fetch_json<T: for<'de> Deserialize<'de>>(&self, url: &str) -> Result<T, String> {
+        let url = Url::parse(url).map_err(|e| format!("Invalid Fabric meta URL: {}", e))?;
+        let data = self.url_fetcher.fetch_binary(&url).await?;
+        serde_json::from_slice(&data)
+            .map_err(|e| format!("Failed to parse Fabric meta response from {}: {}", url, e))
+    }
+
+    async fn resolve_loader_version(&self, version: &McFabricVersion) -> Result<String, String> {
+        let loaders: Vec<LoaderInfo> = self
+            .fetch_json(&format!(
+                "{}/versions/loader/{}",
+                FABRIC_META_URL,
+                version.version.id()
+            ))
+            .await?;
+
+        match &version.loader_version {
+            Some(requested) => loaders
+                .into_iter()
+                .find(|info| &info.loader.version == requested)
+                .map(|info| info.loader.version)
+                .ok_or_else(|| {
+                    format!(
+                        "Fabric loader '{}' not available for version '{}'",
+                        requested,
+                        version.version.id()
+                    )
+                }),
+            None => loaders
+                .into_iter()
+                .find(|info| info.loader.stable)
+                .map(|info| info.loader.version)
+                .ok_or_else(|| {
+                    format!(
+                        "No stable Fabric loader available for version '{}'",
+                        version.version.id()
+                    )
+                }),
+        }
+    }
+
+    async fn resolve_installer_version(&self) -> Result<String, String> {
+        let installers: Vec<ComponentVersion> = self
+            .fetch_json(&format!("{}/versions/installer", FABRIC_META_URL))
+            .await?;
+
+        installers
+            .into_iter()
+            .find(|installer| installer.stable)
+            .map(|installer| installer.version)
+            .ok_or_else(|| "No stable Fabric installer available".to_string())
+    }
+}
+
+#[async_trait::async_trait]
+impl McServerLoader for FabricVersionLoader {
+    type Version = McFabricVersion;
+    async fn ready_server(
+        &self,
+        mut world_data: Dir,
+        version: &Self::Version,
+    ) -> Result<
+        (
+            Dir,
+            Box<dyn Fn(crate::domain::ServerRunOptions) -> std::process::Command>,
+        ),
+        String,
+    > {
+        // Step 1: Resolve loader and installer versions
+        let loader_version = self.resolve_loader_version(version).await?;
+        let installer_version = self.resolve_installer_version().await?;
+
+        // Step 2: Add the Fabric server launcher
+        let launcher_url = Url::parse(&format!(
+            "{}/versions/loader/{}/{}/{}/server/jar",
+            FABRIC_META_URL,
+            version.version.id(),
+            loader_version,
+            installer_version
+        ))
+        .map_err(|e| format!("Invalid Fabric launcher URL: {}", e))?;
+        world_data
+            .put_file(
+                Path::from_str("fabric-server-launch.jar"),
+                File::url(launcher_url, Permission::read_write()),
+            )
+            .map_err(|_| "Failed to add fabric-server-launch.jar to world data".to_string())?;
+
+        // Step 3: Add the vanilla server jar so the launcher does not download it on startup
+        let download = fetch_server_download(self.url_fetcher.as_ref(), &version.version).await?;
+        let mut server_file = File::url(download.server_url, Permission::read_write());
+        if let Some(sha1) = download.server_sha1 {
+            server_file = server_file.with_sha1(sha1);
+        }
+        world_data
+            .put_file(Path::from_str("server.jar"), server_file)
+            .map_err(|_| "Failed to add server.jar to world data".to_string())?;
+
+        // Step 4: Get Java runtime path
+        let java_path = self
+            .java_loader
+            .ready_runtime(&download.java_component)
+            .await?;
+
+        // Step 5: Create command factory invoking the launcher
+        let command_factory = Box::new(move |options: crate::domain::ServerRunOptions| {
+            let mut cmd = std::process::Command::new(&java_path);
+
+            if let Some(xmx) = options.max_memory {
+                cmd.arg(format!("-Xmx{}M", xmx));
+            }
+            if let Some(xms) = options.initial_memory {
+                cmd.arg(format!("-Xms{}M", xms));
+            }
+            cmd.args(&options.jvm_args);
+
+            cmd.arg("-jar").arg("fabric-server-launch.jar").arg("nogui");
+            cmd.args(&options.server_args);
+
+            cmd
+        });
+
+        Ok((world_data, command_factory))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct LoaderInfo {
+    loader: ComponentVersion,
+}
+
+#[derive(Debug, Deserialize)]
+struct ComponentVersion {
+    version: String,
+    stable: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infra::{
+        mc_java::{JavaComponent, McJavaLoader},
+        url_fetcher::DummyUrlFetcher,
+    };
+    use std::path::PathBuf;
+
+    struct DummyJavaLoader;
+
+    #[async_trait::async_trait]
+    impl McJavaLoader for DummyJavaLoader {
+        async fn list_runtimes(&self) -> Result<Vec<crate::infra::mc_java::McJava>, String> {
+            Ok(vec![])
+        }
+
+        async fn ready_runtime(&self, component: &JavaComponent) -> Result<PathBuf, String> {
+            assert_eq!(component.name(), "java-runtime-gamma");
+            Ok(PathBuf::from("/usr/bin/java"))
+        }
+    }
+
+    fn create_url_fetcher() -> DummyUrlFetcher {
+        let mut url_fetcher = DummyUrlFetcher::new();
+
+        url_fetcher.add_data(
+            Url::parse("https://meta.fabricmc.net/v2/versions/loader/1.20.1").unwrap(),
+            r#"[
+                {"loader": {"separator": ".", "build": 23, "maven": "net.fabricmc:fabric-loader:0.16.0-beta.1", "version": "0.16.0-beta.1", "stable": false}},
+                {"loader": {"separator": ".", "build": 22, "maven": "net.fabricmc:fabric-loader:0.15.11", "version": "0.15.11", "stable": true}},
+                {"loader": {"separator": ".", "build": 21, "maven": "net.fabricmc:fabric-loader:0.15.10", "version": "0.15.10", "stable": true}}
+            ]"#,
+        );
+        url_fetcher.add_data(
+            Url::parse("https://meta.fabricmc.net/v2/versions/installer").unwrap(),
+            r#"[
+                {"url": "https://maven.fabricmc.net/installer-1.1.0.jar", "maven": "net.fabricmc:fabric-installer:1.1.0", "version": "1.1.0", "stable": false},
+                {"url": "https://maven.fabricmc.net/installer-1.0.1.jar", "maven": "net.fabricmc:fabric-installer:1.0.1", "version": "1.0.1", "stable": true}
+            ]"#,
+        );
+        url_fetcher.add_data(
+            Url::parse("https://piston-meta.mojang.com/mc/game/version_manifest_v2.json").unwrap(),
+            r#"{
+                "versions": [
+                    {
+                        "id": "1.20.1",
+                        "type": "release",
+                        "url": "https://example.com/1.20.1.json"
+                    }
+                ]
+            }"#,
+        );
+        url_fetcher.add_data(
+            Url::parse("https://example.com/1.20.1.json").unwrap(),
+            r#"{
+                "downloads": {
+                    "server": {"url": "https://example.com/server.jar"}
+                },
+                "javaVersion": {"component": "java-runtime-gamma", "majorVersion": 17}
+            }"#,
+        );
+        url_fetcher
+    }
+
+    fn create_test_loader(url_fetcher: DummyUrlFetcher) -> FabricVersionLoader {
+        FabricVersionLoader::new(Arc::new(url_fetcher), Arc::new(DummyJavaLoader))
+    }
+
+    fn file_url(dir: &Dir, path: &str) -> String {
+        match &dir.get_file(Path::from_str(path)).unwrap().content {
+            crate::util::file_trie::FileContent::Url(url) => url.to_string(),
+            _ => panic!("{} should be a URL file", path),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ready_server_latest_stable_loader() {
+        let loader = create_test_loader(create_url_fetcher());
+        let (world_data, command_factory) = loader
+            .ready_server(
+                Dir::new(),
+                &McFabricVersion {
+                    version: McVanillaVersionId::new("1.20.1".to_string()),
+                    loader_version: None,
+                },
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            file_url(&world_data, "fabric-server-launch.jar"),
+            "https://meta.fabricmc.net/v2/versions/loader/1.20.1/0.15.11/1.0.1/server/jar"
+        );
+        assert_eq!(
+            file_url(&world_data, "server.jar"),
+            "https://example.com/server.jar"
+        );
+
+        let command = command_factory(crate::domain::ServerRunOptions {
+            max_memory: Some(2048),
+            initial_memory: None,
+            ..Default::default()
+        });
+        assert_eq!(command.get_program(), "/usr/bin/java");
+        let args: Vec<&str> = command
+            .get_args()
+            .map(|arg| arg.to_str().unwrap())
+            .collect();
+        assert_eq!(
+            args,
+            vec!["-Xmx2048M", "-jar", "fabric-server-launch.jar", "nogui"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ready_server_specific_loader() {
+        let loader = create_test_loader(create_url_fetcher());
+        let (world_data, _) = loader
+            .ready_server(
+                Dir::new(),
+                &McFabricVersion {
+                    version: McVanillaVersionId::new("1.20.1".to_string()),
+                    loader_version: Some("0.15.10".to_string()),
+                },
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            file_url(&world_data, "fabric-server-launch.jar"),
+            "https://meta.fabricmc.net/v2/versions/loader/1.20.1/0.15.10/1.0.1/server/jar"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ready_server_unknown_loader() {
+        let loader = create_test_loader(create_url_fetcher());
+        let result = loader
+            .ready_server(
+                Dir::new(),
+                &McFabricVersion {
+                    version: McVanillaVersionId::new("1.20.1".to_string()),
+                    loader_version: Some("0.1.0".to_string()),
+                },
+            )
+            .await;
+
+        let error_msg = result.err().unwrap();
+        assert!(error_msg.contains("Fabric loader '0.1.0' not available"));
+    }
+
+    #[tokio::test]
+    async fn test_ready_server_unsupported_game_version() {
+        let loader = create_test_loader(create_url_fetcher());
+        let result = loader
+            .ready_server(
+                Dir::new(),
+                &McFabricVersion {
+                    version: McVanillaVersionId::new("1.13".to_string()),
+                    loader_version: None,
+                },
+            )
+            .await;
+
+        assert!(result.is_err());
+    }
+}
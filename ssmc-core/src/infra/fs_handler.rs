@@ -1,8 +1,31 @@
 use std::collections::HashMap;
 use std::fs;
+use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, RwLock};
 
+/// Prefix of error messages for failures that may succeed if retried,
+/// e.g. a file still held open by a server process that is shutting down
+pub const BUSY_ERROR_PREFIX: &str = "Resource busy: ";
+
+/// Maximum number of symlinks followed while resolving a path, as on Linux
+const MAX_SYMLINK_DEPTH: usize = 40;
+
+pub fn is_busy_error(error: &str) -> bool {
+    error.starts_with(BUSY_ERROR_PREFIX)
+}
+
+fn describe_io_error(action: String, e: std::io::Error) -> String {
+    match e.kind() {
+        std::io::ErrorKind::PermissionDenied
+        | std::io::ErrorKind::WouldBlock
+        | std::io::ErrorKind::ResourceBusy => {
+            format!("{}{}: {}", BUSY_ERROR_PREFIX, action, e)
+        }
+        _ => format!("{}: {}", action, e),
+    }
+}
+
 #[async_trait::async_trait]
 pub trait FsHandler: Send + Sync {
     fn list_entries(&self, path: &Path) -> Result<Vec<PathBuf>, String>;
@@ -10,9 +33,18 @@ pub trait FsHandler: Send + Sync {
     fn create_symlink(&self, path: &Path, target: &Path) -> Result<(), String>;
     fn read(&self, path: &Path) -> Result<Vec<u8>, String>;
     fn write(&self, path: &Path, data: &[u8], executable: bool) -> Result<(), String>;
+    /// Appends to an existing file, so large content can be written piece by piece
+    fn append(&self, path: &Path, data: &[u8]) -> Result<(), String>;
+    /// Copies a file, overwriting `to` and keeping the executable permission of `from`
+    fn copy(&self, from: &Path, to: &Path) -> Result<(), String>;
+    /// Moves a file or directory, replacing `to` if it is an existing file
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), String>;
     fn delete(&self, path: &Path) -> Result<(), String>;
     fn is_file(&self, path: &Path) -> bool;
     fn is_dir(&self, path: &Path) -> bool;
+    fn is_executable(&self, path: &Path) -> bool;
+    /// Returns the size of the file at `path`, following links, or `None` if it is not a file
+    fn file_size(&self, path: &Path) -> Option<u64>;
 }
 
 #[derive(Debug, Clone)]
@@ -101,8 +133,9 @@ impl FsHandler for DefaultFsHandler {
             })?;
         }
 
-        fs::write(path, data)
-            .map_err(|e| format!("Failed to write file {}: {}", path.display(), e))?;
+        fs::write(path, data).map_err(|e| {
+            describe_io_error(format!("Failed to write file {}", path.display()), e)
+        })?;
 
         if executable {
             self.set_executable(path)?;
@@ -111,6 +144,72 @@ impl FsHandler for DefaultFsHandler {
         Ok(())
     }
 
+    fn append(&self, path: &Path, data: &[u8]) -> Result<(), String> {
+        fs::OpenOptions::new()
+            .append(true)
+            .open(path)
+            .and_then(|mut file| file.write_all(data))
+            .map_err(|e| {
+                describe_io_error(format!("Failed to append to file {}", path.display()), e)
+            })
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> Result<(), String> {
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent).map_err(|e| {
+                format!(
+                    "Failed to create parent directory {}: {}",
+                    parent.display(),
+                    e
+                )
+            })?;
+        }
+
+        // On copy-on-write filesystems a reflink shares the data blocks instead of copying them.
+        // It fails if `to` already exists or the filesystem lacks support, so fall back to a plain copy
+        if reflink_copy::reflink(from, to).is_ok() {
+            let permissions = fs::metadata(from)
+                .map_err(|e| format!("Failed to get metadata for {}: {}", from.display(), e))?
+                .permissions();
+            return fs::set_permissions(to, permissions)
+                .map_err(|e| format!("Failed to set permissions for {}: {}", to.display(), e));
+        }
+
+        fs::copy(from, to).map_err(|e| {
+            describe_io_error(
+                format!("Failed to copy file {} to {}", from.display(), to.display()),
+                e,
+            )
+        })?;
+
+        Ok(())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), String> {
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent).map_err(|e| {
+                format!(
+                    "Failed to create parent directory {}: {}",
+                    parent.display(),
+                    e
+                )
+            })?;
+        }
+
+        match fs::rename(from, to) {
+            Ok(()) => Ok(()),
+            // Renaming is not possible across filesystems, so copy everything and delete the source
+            Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
+                self.copy_recursive(from, to)?;
+                self.delete(from)
+            }
+            Err(e) => Err(describe_io_error(
+                format!("Failed to rename {} to {}", from.display(), to.display()),
+                e,
+            )),
+        }
+    }
+
     fn delete(&self, path: &Path) -> Result<(), String> {
         if !path.exists() {
             return Ok(());
@@ -132,9 +231,52 @@ impl FsHandler for DefaultFsHandler {
     fn is_dir(&self, path: &Path) -> bool {
         path.is_dir()
     }
+
+    #[cfg(unix)]
+    fn is_executable(&self, path: &Path) -> bool {
+        use std::os::unix::fs::PermissionsExt;
+
+        fs::metadata(path)
+            .map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+            .unwrap_or(false)
+    }
+
+    #[cfg(windows)]
+    fn is_executable(&self, _path: &Path) -> bool {
+        // On Windows, executable permission is determined by file extension
+        false
+    }
+
+    fn file_size(&self, path: &Path) -> Option<u64> {
+        fs::metadata(path)
+            .ok()
+            .filter(|metadata| metadata.is_file())
+            .map(|metadata| metadata.len())
+    }
 }
 
 impl DefaultFsHandler {
+    fn copy_recursive(&self, from: &Path, to: &Path) -> Result<(), String> {
+        let metadata = fs::symlink_metadata(from)
+            .map_err(|e| format!("Failed to get metadata for {}: {}", from.display(), e))?;
+
+        if metadata.is_symlink() {
+            let target = fs::read_link(from)
+                .map_err(|e| format!("Failed to read symlink {}: {}", from.display(), e))?;
+            self.create_symlink(to, &target)
+        } else if metadata.is_dir() {
+            self.mkdir(to)?;
+            for entry in self.list_entries(from)? {
+                if let Some(name) = entry.file_name() {
+                    self.copy_recursive(&entry, &to.join(name))?;
+                }
+            }
+            Ok(())
+        } else {
+            self.copy(from, to)
+        }
+    }
+
     #[cfg(unix)]
     fn set_executable(&self, path: &Path) -> Result<(), String> {
         use std::os::unix::fs::PermissionsExt;
@@ -186,17 +328,25 @@ impl FsHandler for OnMemoryFsHandler {
     fn list_entries(&self, path: &Path) -> Result<Vec<PathBuf>, String> {
         let mut entries = Vec::new();
 
-        // Find all files and directories that are direct children of the given path
+        // Collect the direct children of the given path. A deeper key implies a directory
+        // at its first component below `path`, even if that directory was never registered
+        let mut add_child = |key: &Path| {
+            if let Ok(relative) = key.strip_prefix(path)
+                && let Some(child) = relative.components().next()
+            {
+                let child = path.join(child);
+                if !entries.contains(&child) {
+                    entries.push(child);
+                }
+            }
+        };
+
         let files = self
             .files
             .read()
             .map_err(|e| format!("Lock error: {}", e))?;
-        for file_path in files.keys() {
-            if let Some(parent) = file_path.parent() {
-                if parent == path {
-                    entries.push(file_path.clone());
-                }
-            }
+        for key in files.keys() {
+            add_child(key);
         }
         drop(files);
 
@@ -204,15 +354,20 @@ impl FsHandler for OnMemoryFsHandler {
             .directories
             .read()
             .map_err(|e| format!("Lock error: {}", e))?;
-        for dir_path in directories.keys() {
-            if let Some(parent) = dir_path.parent() {
-                if parent == path {
-                    entries.push(dir_path.clone());
-                }
-            }
+        for key in directories.keys() {
+            add_child(key);
         }
         drop(directories);
 
+        let links = self
+            .links
+            .read()
+            .map_err(|e| format!("Lock error: {}", e))?;
+        for key in links.keys() {
+            add_child(key);
+        }
+        drop(links);
+
         Ok(entries)
     }
 
@@ -251,12 +406,13 @@ impl FsHandler for OnMemoryFsHandler {
     }
 
     fn read(&self, path: &Path) -> Result<Vec<u8>, String> {
+        let resolved = self.resolve_links(path)?;
         let files = self
             .files
             .read()
             .map_err(|e| format!("Lock error: {}", e))?;
         files
-            .get(path)
+            .get(&resolved)
             .cloned()
             .ok_or_else(|| format!("File not found: {}", path.display()))
     }
@@ -279,6 +435,48 @@ impl FsHandler for OnMemoryFsHandler {
         Ok(())
     }
 
+    fn append(&self, path: &Path, data: &[u8]) -> Result<(), String> {
+        let resolved = self.resolve_links(path)?;
+        self.files
+            .write()
+            .map_err(|e| format!("Lock error: {}", e))?
+            .get_mut(&resolved)
+            .ok_or_else(|| format!("File not found: {}", path.display()))?
+            .extend_from_slice(data);
+        Ok(())
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> Result<(), String> {
+        let data = self.read(from)?;
+        let executable = self
+            .executable_files
+            .read()
+            .map_err(|e| format!("Lock error: {}", e))?
+            .get(&self.resolve_links(from)?)
+            .copied()
+            .unwrap_or(false);
+        self.write(to, &data, executable)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), String> {
+        if !self.is_file(from) && !self.is_dir(from) && !self.is_link(from)? {
+            return Err(format!("File not found: {}", from.display()));
+        }
+        if self.is_file(to) {
+            self.delete(to)?;
+        }
+        if let Some(parent) = to.parent() {
+            self.mkdir(parent)?;
+        }
+
+        remap_paths(&self.files, from, to)?;
+        remap_paths(&self.executable_files, from, to)?;
+        remap_paths(&self.directories, from, to)?;
+        remap_paths(&self.links, from, to)?;
+
+        Ok(())
+    }
+
     fn delete(&self, path: &Path) -> Result<(), String> {
         // Remove file if it exists
         if self
@@ -303,20 +501,20 @@ impl FsHandler for OnMemoryFsHandler {
             .remove(path)
             .is_some()
         {
-            // Also remove all files and subdirectories within this directory
-            let path_str = path.to_string_lossy().to_string();
+            // Also remove all files and subdirectories within this directory,
+            // comparing whole components so that `/foobar` is not inside `/foo`
             self.files
                 .write()
                 .map_err(|e| format!("Lock error: {}", e))?
-                .retain(|k, _| !k.to_string_lossy().starts_with(&path_str));
+                .retain(|k, _| !k.starts_with(path));
             self.directories
                 .write()
                 .map_err(|e| format!("Lock error: {}", e))?
-                .retain(|k, _| !k.to_string_lossy().starts_with(&path_str));
+                .retain(|k, _| !k.starts_with(path));
             self.executable_files
                 .write()
                 .map_err(|e| format!("Lock error: {}", e))?
-                .retain(|k, _| !k.to_string_lossy().starts_with(&path_str));
+                .retain(|k, _| !k.starts_with(path));
             return Ok(());
         }
 
@@ -324,18 +522,122 @@ impl FsHandler for OnMemoryFsHandler {
     }
 
     fn is_file(&self, path: &Path) -> bool {
+        let Ok(resolved) = self.resolve_links(path) else {
+            return false;
+        };
         self.files
             .read()
-            .map(|files| files.contains_key(path))
+            .map(|files| files.contains_key(&resolved))
             .unwrap_or(false)
     }
 
     fn is_dir(&self, path: &Path) -> bool {
+        let Ok(resolved) = self.resolve_links(path) else {
+            return false;
+        };
         self.directories
             .read()
-            .map(|directories| directories.contains_key(path))
+            .map(|directories| directories.contains_key(&resolved))
             .unwrap_or(false)
     }
+
+    fn is_executable(&self, path: &Path) -> bool {
+        let Ok(resolved) = self.resolve_links(path) else {
+            return false;
+        };
+        self.executable_files
+            .read()
+            .map(|executable_files| executable_files.get(&resolved).copied().unwrap_or(false))
+            .unwrap_or(false)
+    }
+
+    fn file_size(&self, path: &Path) -> Option<u64> {
+        let resolved = self.resolve_links(path).ok()?;
+        self.files
+            .read()
+            .ok()?
+            .get(&resolved)
+            .map(|data| data.len() as u64)
+    }
+}
+
+impl OnMemoryFsHandler {
+    /// Follows links at `path` or any of its ancestors until the path contains no links
+    fn resolve_links(&self, path: &Path) -> Result<PathBuf, String> {
+        let links = self
+            .links
+            .read()
+            .map_err(|e| format!("Lock error: {}", e))?;
+        let mut current = path.to_path_buf();
+        let mut visited = Vec::new();
+        for _ in 0..MAX_SYMLINK_DEPTH {
+            let Some((link, target)) = current
+                .ancestors()
+                .find_map(|ancestor| links.get(ancestor).map(|target| (ancestor, target)))
+            else {
+                return Ok(current);
+            };
+            if visited.contains(&current) {
+                return Err(format!("Symlink cycle detected: {}", path.display()));
+            }
+            // Relative targets are relative to the directory containing the link
+            let base = match link.parent() {
+                Some(parent) if target.is_relative() => parent.join(target),
+                _ => target.clone(),
+            };
+            let next = normalize_path(&base.join(current.strip_prefix(link).unwrap_or(link)));
+            visited.push(current);
+            current = next;
+        }
+        Err(format!(
+            "Too many levels of symbolic links: {}",
+            path.display()
+        ))
+    }
+
+    fn is_link(&self, path: &Path) -> Result<bool, String> {
+        Ok(self
+            .links
+            .read()
+            .map_err(|e| format!("Lock error: {}", e))?
+            .contains_key(path))
+    }
+}
+
+// Removes `.` and resolves `..` without touching the file system
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+// Moves `from` and everything below it to the same relative position under `to`
+fn remap_paths<V>(map: &RwLock<HashMap<PathBuf, V>>, from: &Path, to: &Path) -> Result<(), String> {
+    let mut map = map.write().map_err(|e| format!("Lock error: {}", e))?;
+    let keys: Vec<PathBuf> = map
+        .keys()
+        .filter(|k| k.starts_with(from))
+        .cloned()
+        .collect();
+    for key in keys {
+        if let (Some(value), Ok(relative)) = (map.remove(&key), key.strip_prefix(from)) {
+            let new_key = if relative.as_os_str().is_empty() {
+                to.to_path_buf()
+            } else {
+                to.join(relative)
+            };
+            map.insert(new_key, value);
+        }
+    }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -384,6 +686,71 @@ mod tests {
         assert!(!file_path.exists());
     }
 
+    #[tokio::test]
+    async fn test_default_fs_handler_copy() {
+        let temp_dir = TempDir::new().unwrap();
+        let fs_handler = DefaultFsHandler::new();
+
+        let from = temp_dir.path().join("java");
+        let to = temp_dir.path().join("runtime/bin/java");
+        fs_handler.write(&from, b"binary", true).unwrap();
+        // Existing files are overwritten
+        fs_handler.write(&to, b"old", false).unwrap();
+
+        fs_handler.copy(&from, &to).unwrap();
+
+        assert_eq!(fs_handler.read(&to).unwrap(), b"binary");
+        assert_eq!(fs_handler.read(&from).unwrap(), b"binary");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&to).unwrap().permissions().mode();
+            assert_ne!(mode & 0o111, 0);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_default_fs_handler_rename_dir() {
+        let temp_dir = TempDir::new().unwrap();
+        let fs_handler = DefaultFsHandler::new();
+
+        let from = temp_dir.path().join("generated/world");
+        let to = temp_dir.path().join("servers/main/world");
+        fs_handler
+            .write(&from.join("region/r.0.0.mca"), b"region", false)
+            .unwrap();
+        fs_handler
+            .write(&from.join("level.dat"), b"level", false)
+            .unwrap();
+
+        fs_handler.rename(&from, &to).unwrap();
+
+        assert!(!from.exists());
+        assert_eq!(
+            fs_handler.read(&to.join("region/r.0.0.mca")).unwrap(),
+            b"region"
+        );
+        assert_eq!(fs_handler.read(&to.join("level.dat")).unwrap(), b"level");
+    }
+
+    #[tokio::test]
+    async fn test_default_fs_handler_copy_recursive() {
+        // The cross-device fallback of rename
+        let temp_dir = TempDir::new().unwrap();
+        let fs_handler = DefaultFsHandler::new();
+
+        let from = temp_dir.path().join("from");
+        let to = temp_dir.path().join("to");
+        fs_handler.write(&from.join("a/b.txt"), b"b", true).unwrap();
+        fs_handler.mkdir(&from.join("empty")).unwrap();
+
+        fs_handler.copy_recursive(&from, &to).unwrap();
+
+        assert_eq!(fs_handler.read(&to.join("a/b.txt")).unwrap(), b"b");
+        assert!(fs_handler.is_dir(&to.join("empty")));
+        assert!(fs_handler.is_file(&from.join("a/b.txt")));
+    }
+
     #[tokio::test]
     async fn test_on_memory_fs_handler_write_read() {
         let fs_handler = OnMemoryFsHandler::new();
@@ -408,6 +775,36 @@ mod tests {
         assert!(entries.contains(&dir_path));
     }
 
+    #[tokio::test]
+    async fn test_on_memory_fs_handler_list_nested() {
+        let fs_handler = OnMemoryFsHandler::new();
+
+        fs_handler
+            .write(&PathBuf::from("/a/b/c.txt"), b"c", false)
+            .unwrap();
+        fs_handler
+            .write(&PathBuf::from("/a/d.txt"), b"d", false)
+            .unwrap();
+        // A file whose parent directories were never registered
+        fs_handler
+            .files
+            .write()
+            .unwrap()
+            .insert(PathBuf::from("/a/e/f/g.txt"), b"g".to_vec());
+
+        let mut entries = fs_handler.list_entries(&PathBuf::from("/a")).unwrap();
+        entries.sort();
+
+        assert_eq!(
+            entries,
+            vec![
+                PathBuf::from("/a/b"),
+                PathBuf::from("/a/d.txt"),
+                PathBuf::from("/a/e"),
+            ]
+        );
+    }
+
     #[tokio::test]
     async fn test_on_memory_fs_handler_delete() {
         let fs_handler = OnMemoryFsHandler::new();
@@ -421,4 +818,221 @@ mod tests {
 
         assert!(fs_handler.read(&file_path).is_err());
     }
+
+    #[tokio::test]
+    async fn test_on_memory_fs_handler_delete_keeps_prefixed_sibling() {
+        let fs_handler = OnMemoryFsHandler::new();
+
+        fs_handler
+            .write(&PathBuf::from("/foo/a.txt"), b"a", false)
+            .unwrap();
+        fs_handler
+            .write(&PathBuf::from("/foobar/b.txt"), b"b", false)
+            .unwrap();
+
+        fs_handler.delete(&PathBuf::from("/foo")).unwrap();
+
+        assert!(!fs_handler.is_file(&PathBuf::from("/foo/a.txt")));
+        assert!(!fs_handler.is_dir(&PathBuf::from("/foo")));
+        assert_eq!(
+            fs_handler.read(&PathBuf::from("/foobar/b.txt")).unwrap(),
+            b"b"
+        );
+        assert!(fs_handler.is_dir(&PathBuf::from("/foobar")));
+    }
+
+    #[tokio::test]
+    async fn test_on_memory_fs_handler_read_through_symlink() {
+        let fs_handler = OnMemoryFsHandler::new();
+
+        fs_handler
+            .write(&PathBuf::from("/cache/server-1.21.jar"), b"jar", false)
+            .unwrap();
+        fs_handler
+            .write(&PathBuf::from("/cache/libraries/lib.jar"), b"lib", false)
+            .unwrap();
+        fs_handler
+            .create_symlink(
+                &PathBuf::from("/server/server.jar"),
+                &PathBuf::from("/cache/server-1.21.jar"),
+            )
+            .unwrap();
+        // Relative link to a directory, followed through an intermediate component
+        fs_handler
+            .create_symlink(
+                &PathBuf::from("/server/libraries"),
+                &PathBuf::from("../cache/libraries"),
+            )
+            .unwrap();
+
+        assert_eq!(
+            fs_handler
+                .read(&PathBuf::from("/server/server.jar"))
+                .unwrap(),
+            b"jar"
+        );
+        assert!(fs_handler.is_file(&PathBuf::from("/server/server.jar")));
+        assert!(fs_handler.is_dir(&PathBuf::from("/server/libraries")));
+        assert_eq!(
+            fs_handler
+                .read(&PathBuf::from("/server/libraries/lib.jar"))
+                .unwrap(),
+            b"lib"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_on_memory_fs_handler_symlink_cycle() {
+        let fs_handler = OnMemoryFsHandler::new();
+
+        fs_handler
+            .create_symlink(&PathBuf::from("/a"), &PathBuf::from("/b"))
+            .unwrap();
+        fs_handler
+            .create_symlink(&PathBuf::from("/b"), &PathBuf::from("/a"))
+            .unwrap();
+        // A link into its own subtree grows the path instead of repeating it
+        fs_handler
+            .create_symlink(&PathBuf::from("/c"), &PathBuf::from("/c/d"))
+            .unwrap();
+
+        let err = fs_handler.read(&PathBuf::from("/a/file")).unwrap_err();
+        assert!(err.contains("Symlink cycle detected"), "{}", err);
+        let err = fs_handler.read(&PathBuf::from("/c")).unwrap_err();
+        assert!(err.contains("Too many levels of symbolic links"), "{}", err);
+        assert!(!fs_handler.is_file(&PathBuf::from("/a")));
+        assert!(!fs_handler.is_dir(&PathBuf::from("/a")));
+    }
+
+    #[tokio::test]
+    async fn test_on_memory_fs_handler_copy() {
+        let fs_handler = OnMemoryFsHandler::new();
+
+        let from = PathBuf::from("/java");
+        let to = PathBuf::from("/runtime/bin/java");
+        fs_handler.write(&from, b"binary", true).unwrap();
+
+        fs_handler.copy(&from, &to).unwrap();
+
+        assert_eq!(fs_handler.read(&to).unwrap(), b"binary");
+        assert!(fs_handler.is_dir(&PathBuf::from("/runtime/bin")));
+        assert_eq!(
+            fs_handler.executable_files.read().unwrap().get(&to),
+            Some(&true)
+        );
+        assert!(
+            fs_handler
+                .copy(&PathBuf::from("/missing"), &PathBuf::from("/copy"))
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_file_size() {
+        let temp_dir = TempDir::new().unwrap();
+        let default_handler = DefaultFsHandler::new();
+        let file_path = temp_dir.path().join("server.jar");
+        default_handler.write(&file_path, b"12345", false).unwrap();
+        assert_eq!(default_handler.file_size(&file_path), Some(5));
+        assert_eq!(default_handler.file_size(temp_dir.path()), None);
+        assert_eq!(
+            default_handler.file_size(&temp_dir.path().join("missing")),
+            None
+        );
+
+        let memory_handler = OnMemoryFsHandler::new();
+        memory_handler
+            .write(&PathBuf::from("/data/a"), b"abc", false)
+            .unwrap();
+        memory_handler
+            .create_symlink(&PathBuf::from("/link"), &PathBuf::from("/data"))
+            .unwrap();
+        assert_eq!(memory_handler.file_size(&PathBuf::from("/link/a")), Some(3));
+        assert_eq!(memory_handler.file_size(&PathBuf::from("/data")), None);
+    }
+
+    #[tokio::test]
+    async fn test_append() {
+        let temp_dir = TempDir::new().unwrap();
+        let default_handler = DefaultFsHandler::new();
+        let file_path = temp_dir.path().join("server.jar");
+        default_handler.write(&file_path, b"abc", true).unwrap();
+        default_handler.append(&file_path, b"def").unwrap();
+        assert_eq!(default_handler.read(&file_path).unwrap(), b"abcdef");
+        assert!(default_handler.is_executable(&file_path));
+        assert!(
+            default_handler
+                .append(&temp_dir.path().join("missing"), b"x")
+                .is_err()
+        );
+
+        let memory_handler = OnMemoryFsHandler::new();
+        let file_path = PathBuf::from("/server.jar");
+        memory_handler.write(&file_path, b"abc", false).unwrap();
+        memory_handler.append(&file_path, b"def").unwrap();
+        assert_eq!(memory_handler.read(&file_path).unwrap(), b"abcdef");
+        assert!(
+            memory_handler
+                .append(&PathBuf::from("/missing"), b"x")
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_on_memory_fs_handler_rename_dir() {
+        let fs_handler = OnMemoryFsHandler::new();
+
+        fs_handler
+            .write(
+                &PathBuf::from("/tmp/world/region/r.0.0.mca"),
+                b"region",
+                false,
+            )
+            .unwrap();
+        fs_handler
+            .write(&PathBuf::from("/tmp/world/level.dat"), b"level", false)
+            .unwrap();
+        fs_handler
+            .write(
+                &PathBuf::from("/tmp/world_nether/level.dat"),
+                b"nether",
+                false,
+            )
+            .unwrap();
+
+        fs_handler
+            .rename(
+                &PathBuf::from("/tmp/world"),
+                &PathBuf::from("/server/world"),
+            )
+            .unwrap();
+
+        assert!(!fs_handler.is_dir(&PathBuf::from("/tmp/world")));
+        assert!(!fs_handler.is_file(&PathBuf::from("/tmp/world/level.dat")));
+        assert!(fs_handler.is_dir(&PathBuf::from("/server/world/region")));
+        assert_eq!(
+            fs_handler
+                .read(&PathBuf::from("/server/world/region/r.0.0.mca"))
+                .unwrap(),
+            b"region"
+        );
+        assert_eq!(
+            fs_handler
+                .read(&PathBuf::from("/server/world/level.dat"))
+                .unwrap(),
+            b"level"
+        );
+        // Siblings sharing the name as a prefix stay in place
+        assert_eq!(
+            fs_handler
+                .read(&PathBuf::from("/tmp/world_nether/level.dat"))
+                .unwrap(),
+            b"nether"
+        );
+        assert!(
+            fs_handler
+                .rename(&PathBuf::from("/missing"), &PathBuf::from("/moved"))
+                .is_err()
+        );
+    }
 }
@@ -1,4 +1,3 @@
-use crate::domain::McVanillaVersionId;
 use crate::infra::trie_loader::TrieLoader;
 use crate::infra::url_fetcher::UrlFetcher;
 use crate::util::file_trie::{Dir, File, Path, Permission};
@@ -15,7 +14,26 @@ pub trait McJavaLoader {
     /** Javaランタイムのリストを取得 */
     async fn list_runtimes(&self) -> Result<Vec<McJava>, String>;
     /** Javaをインストールして実行パスを返す */
-    async fn ready_runtime(&self, version_id: &McVanillaVersionId) -> Result<PathBuf, String>;
+    async fn ready_runtime(&self, component: &JavaComponent) -> Result<PathBuf, String>;
+}
+
+/// Name of a Java runtime component in Mojang's runtime list, e.g. `java-runtime-gamma`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct JavaComponent(String);
+
+impl JavaComponent {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self(name.into())
+    }
+
+    /// The runtime for versions whose details have no `javaVersion` (1.16.5 and older), which run on Java 8
+    pub fn legacy() -> Self {
+        Self::new("jre-legacy")
+    }
+
+    pub fn name(&self) -> &str {
+        &self.0
+    }
 }
 
 pub struct DefaultMcJavaLoader {
@@ -90,7 +108,10 @@ impl DefaultMcJavaLoader {
             serde_json::from_slice(&data).map_err(|e| format!("Failed to parse JSON: {}", e))?;
 
         let mut runtimes = Vec::new();
-        let current_platform = self.get_current_platform();
+        // 対応するプラットフォームがなければ利用できるランタイムもない
+        let Ok(current_platform) = self.get_current_platform() else {
+            return Ok(runtimes);
+        };
 
         for (platform_name, runtime_types) in response.platforms {
             if platform_name != current_platform {
@@ -111,8 +132,8 @@ impl DefaultMcJavaLoader {
         Ok(runtimes)
     }
 
-    async fn ready_runtime(&self, version_id: &McVanillaVersionId) -> Result<PathBuf, String> {
-        let runtime_path = self.cache_path.join(&version_id.id());
+    async fn ready_runtime(&self, component: &JavaComponent) -> Result<PathBuf, String> {
+        let runtime_path = self.cache_path.join(component.name());
         let java_executable = runtime_path.join(self.get_java_executable_path());
 
         if !java_executable.exists() {
@@ -123,10 +144,17 @@ impl DefaultMcJavaLoader {
             let response: JavaRuntimesResponse = serde_json::from_slice(&data)
                 .map_err(|e| format!("Failed to parse JSON: {}", e))?;
 
-            let current_platform = self.get_current_platform();
+            let current_platform = self.get_current_platform()?;
+            if !response.platforms.contains_key(current_platform) {
+                return Err(format!(
+                    "Runtime not found: {} (Mojang provides no Java runtimes for platform {}; install Java manually)",
+                    component.name(),
+                    current_platform
+                ));
+            }
             let manifest_url = self
-                .find_manifest_url(&response, version_id, &current_platform)
-                .ok_or_else(|| format!("Runtime not found: {}", version_id.id()))?;
+                .find_manifest_url(&response, component, current_platform)
+                .ok_or_else(|| format!("Runtime not found: {}", component.name()))?;
 
             let manifest_data = self.url_fetcher.fetch_binary(&manifest_url).await?;
             let manifest: ManifestResponse = serde_json::from_slice(&manifest_data)
@@ -184,39 +212,10 @@ impl DefaultMcJavaLoader {
             .unwrap_or(8)
     }
 
-    fn get_current_platform(&self) -> String {
-        #[cfg(all(target_os = "linux", target_arch = "x86"))]
-        {
-            "linux-i386".to_string()
-        }
-        #[cfg(all(target_os = "linux", not(target_arch = "x86")))]
-        {
-            "linux".to_string()
-        }
-        #[cfg(all(target_os = "macos", target_arch = "aarch64"))]
-        {
-            "mac-os-arm64".to_string()
-        }
-        #[cfg(all(target_os = "macos", not(target_arch = "aarch64")))]
-        {
-            "mac-os".to_string()
-        }
-        #[cfg(all(target_os = "windows", target_arch = "x86"))]
-        {
-            "windows-x86".to_string()
-        }
-        #[cfg(all(target_os = "windows", target_arch = "x86_64"))]
-        {
-            "windows-x64".to_string()
-        }
-        #[cfg(all(target_os = "windows", target_arch = "aarch64"))]
-        {
-            "windows-arm64".to_string()
-        }
-        #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
-        {
-            "linux".to_string() // fallback
-        }
+    fn get_current_platform(&self) -> Result<&'static str, String> {
+        let (os, arch) = (std::env::consts::OS, std::env::consts::ARCH);
+        platform_key(os, arch)
+            .ok_or_else(|| format!("No Java runtime platform is known for {}-{}", os, arch))
     }
 
     fn get_java_executable_path(&self) -> &'static str {
@@ -233,7 +232,7 @@ impl DefaultMcJavaLoader {
     fn find_manifest_url(
         &self,
         response: &JavaRuntimesResponse,
-        version_id: &McVanillaVersionId,
+        component: &JavaComponent,
         platform: &str,
     ) -> Option<Url> {
         for (platform_name, runtime_types) in &response.platforms {
@@ -243,7 +242,7 @@ impl DefaultMcJavaLoader {
 
             for (runtime_type, runtime_list) in runtime_types {
                 for runtime in runtime_list {
-                    if runtime_type == version_id.id() {
+                    if runtime_type == component.name() {
                         return Url::parse(&runtime.manifest.url).ok();
                     }
                 }
@@ -253,14 +252,30 @@ impl DefaultMcJavaLoader {
     }
 }
 
+/// Maps `std::env::consts::{OS, ARCH}` to the platform key of Mojang's Java runtime list
+fn platform_key(os: &str, arch: &str) -> Option<&'static str> {
+    match (os, arch) {
+        ("linux", "x86_64") => Some("linux"),
+        ("linux", "x86") => Some("linux-i386"),
+        ("linux", "aarch64") => Some("linux-arm64"),
+        ("linux", "arm") => Some("linux-arm"),
+        ("macos", "x86_64") => Some("mac-os"),
+        ("macos", "aarch64") => Some("mac-os-arm64"),
+        ("windows", "x86_64") => Some("windows-x64"),
+        ("windows", "x86") => Some("windows-x86"),
+        ("windows", "aarch64") => Some("windows-arm64"),
+        _ => None,
+    }
+}
+
 #[async_trait::async_trait]
 impl McJavaLoader for DefaultMcJavaLoader {
     async fn list_runtimes(&self) -> Result<Vec<McJava>, String> {
         self.list_runtimes().await
     }
 
-    async fn ready_runtime(&self, version_id: &McVanillaVersionId) -> Result<PathBuf, String> {
-        self.ready_runtime(version_id).await
+    async fn ready_runtime(&self, component: &JavaComponent) -> Result<PathBuf, String> {
+        self.ready_runtime(component).await
     }
 }
 
@@ -336,7 +351,7 @@ mod tests {
             Url::parse("https://launchermeta.mojang.com/v1/products/java-runtime/2ec0cc96c44e5a76b9c8b7c39df7210883d12871/all.json").unwrap(),
              r#"{"linux": {"java-runtime-alpha": [{"manifest": {"url": "https://example.com/manifest.json"}, "version": {"name": "17.0.1"}}]}}"#);
 
-        let manifest_response = r#"{"files": {"bin/java": {"downloads": {"raw": {"url": "https://example.com/java" } },"executable": true },"lib/": {}}}"#;
+        let manifest_response = r#"{"files": {"bin/java": {"type": "file", "downloads": {"raw": {"url": "https://example.com/java" } },"executable": true },"lib": {"type": "directory"}}}"#;
 
         url_fetcher.add_data(manifest_url, manifest_response.as_bytes());
 
@@ -356,7 +371,7 @@ mod tests {
             PathBuf::from("/tmp/test_cache"),
         );
         let java_path = loader
-            .ready_runtime(&McVanillaVersionId::new("java-runtime-alpha".to_string()))
+            .ready_runtime(&JavaComponent::new("java-runtime-alpha"))
             .await
             .unwrap();
 
@@ -390,13 +405,51 @@ mod tests {
             PathBuf::from("/tmp/test_cache"),
         );
         let result = loader
-            .ready_runtime(&McVanillaVersionId::new("nonexistent:1.0.0".to_string()))
+            .ready_runtime(&JavaComponent::new("nonexistent:1.0.0"))
             .await;
 
         assert!(result.is_err());
         assert!(result.unwrap_err().contains("Runtime not found"));
     }
 
+    #[test]
+    fn test_platform_key() {
+        assert_eq!(platform_key("linux", "x86_64"), Some("linux"));
+        assert_eq!(platform_key("linux", "x86"), Some("linux-i386"));
+        assert_eq!(platform_key("linux", "aarch64"), Some("linux-arm64"));
+        assert_eq!(platform_key("linux", "arm"), Some("linux-arm"));
+        assert_eq!(platform_key("macos", "x86_64"), Some("mac-os"));
+        assert_eq!(platform_key("macos", "aarch64"), Some("mac-os-arm64"));
+        assert_eq!(platform_key("windows", "x86_64"), Some("windows-x64"));
+        assert_eq!(platform_key("windows", "x86"), Some("windows-x86"));
+        assert_eq!(platform_key("windows", "aarch64"), Some("windows-arm64"));
+        assert_eq!(platform_key("freebsd", "x86_64"), None);
+        assert_eq!(platform_key("linux", "riscv64"), None);
+    }
+
+    #[tokio::test]
+    async fn test_ready_runtime_platform_not_provided() {
+        let mut url_fetcher = DummyUrlFetcher::new();
+        let url = Url::parse("https://launchermeta.mojang.com/v1/products/java-runtime/2ec0cc96c44e5a76b9c8b7c39df7210883d12871/all.json").unwrap();
+        url_fetcher.add_data(url, r#"{"gamecore": {}}"#.as_bytes());
+
+        let loader = DefaultMcJavaLoader::new(
+            Arc::new(url_fetcher),
+            Arc::new(DefaultTrieLoader::new(
+                Arc::new(DefaultFsHandler::new()),
+                Arc::new(DummyUrlFetcher::new()),
+            )),
+            PathBuf::from("/tmp/test_cache"),
+        );
+        let err = loader
+            .ready_runtime(&JavaComponent::new("java-runtime-gamma"))
+            .await
+            .unwrap_err();
+
+        assert!(err.contains("Runtime not found"), "{}", err);
+        assert!(err.contains("install Java manually"), "{}", err);
+    }
+
     #[test]
     fn test_extract_major_version() {
         let loader = DefaultMcJavaLoader::new(
@@ -0,0 +1,331 @@
+use crate::{
+    domain::{McServerLoader, McVanillaVersionId, McVersion},
+    infra::{mc_java::McJavaLoader, url_fetcher::UrlFetcher, vanilla::fetch_server_download},
+    util::file_trie::{Dir, File, Path, Permission},
+};
+use serde::Deserialize;
+use std::sync::Arc;
+use url::Url;
+
+const PAPER_API_URL: &str = "https://api.papermc.io/v2/projects/paper";
+
+pub struct McPaperVersion {
+    pub version: McVanillaVersionId,
+    /// Paper build number. The latest stable build is used if not specified
+    pub build: Option<u32>,
+}
+
+impl McVersion for McPaperVersion {
+    fn vanilla_id(&self) -> McVanillaVersionId {
+        self.version.clone()
+    }
+}
+
+pub struct PaperVersionLoader {
+    url_fetcher: Arc<dyn UrlFetcher + Send + Sync>,
+    java_loader: Arc<dyn McJavaLoader + Send + Sync>,
+}
+
+impl PaperVersionLoader {
+    pub fn new(
+        url_fetcher: Arc<dyn UrlFetcher + Send + Sync>,
+        java_loader: Arc<dyn McJavaLoader + Send + Sync>,
+    ) -> Self {
+        Self {
+            url_fetcher,
+            java_loader,
+        }
+    }
+
+    /// Resolve the build to download and return its jar with the download URL and checksum
+    async fn resolve_download(&self, version: &McPaperVersion) -> Result<File, String> {
+        let builds_url = Url::parse(&format!(
+            "{}/versions/{}/builds",
+            PAPER_API_URL,
+            version.version.id()
+        ))
+        .map_err(|e| format!("Invalid Paper builds URL: {}", e))?;
+
+        let builds_data = self.url_fetcher.fetch_binary(&builds_url).await?;
+        let builds: BuildsResponse = serde_json::from_slice(&builds_data)
+            .map_err(|e| format!("Failed to parse Paper builds: {}", e))?;
+
+        let build = match version.build {
+            Some(requested) => builds
+                .builds
+                .into_iter()
+                .find(|build| build.build == requested)
+                .ok_or_else(|| {
+                    format!(
+                        "Paper build {} not found for version '{}'",
+                        requested,
+                        version.version.id()
+                    )
+                })?,
+            None => builds
+                .builds
+                .into_iter()
+                .filter(|build| build.channel == "default")
+                .max_by_key(|build| build.build)
+                .ok_or_else(|| {
+                    format!(
+                        "No stable Paper build available for version '{}'",
+                        version.version.id()
+                    )
+                })?,
+        };
+
+        let application = build.downloads.application;
+        let download_url = Url::parse(&format!(
+            "{}/versions/{}/builds/{}/downloads/{}",
+            PAPER_API_URL,
+            version.version.id(),
+            build.build,
+            application.name
+        ))
+        .map_err(|e| format!("Invalid Paper download URL: {}", e))?;
+
+        let mut jar = File::url(download_url, Permission::read_write());
+        if let Some(sha256) = application.sha256 {
+            jar = jar.with_sha256(sha256);
+        }
+        Ok(jar)
+    }
+}
+
+#[async_trait::async_trait]
+impl McServerLoader for PaperVersionLoader {
+    type Version = McPaperVersion;
+    async fn ready_server(
+        &self,
+        mut world_data: Dir,
+        version: &Self::Version,
+    ) -> Result<
+        (
+            Dir,
+            Box<dyn Fn(crate::domain::ServerRunOptions) -> std::process::Command>,
+        ),
+        String,
+    > {
+        // Step 1: Resolve the Paper build and add its jar as paper.jar
+        let jar = self.resolve_download(version).await?;
+        world_data
+            .put_file(Path::from_str("paper.jar"), jar)
+            .map_err(|_| "Failed to add paper.jar to world data".to_string())?;
+
+        // Step 2: Get Java runtime path required by the vanilla version
+        let vanilla = fetch_server_download(self.url_fetcher.as_ref(), &version.version).await?;
+        let java_path = self
+            .java_loader
+            .ready_runtime(&vanilla.java_component)
+            .await?;
+
+        // Step 3: Create command factory with full Java path
+        let command_factory = Box::new(move |options: crate::domain::ServerRunOptions| {
+            let mut cmd = std::process::Command::new(&java_path);
+
+            if let Some(xmx) = options.max_memory {
+                cmd.arg(format!("-Xmx{}M", xmx));
+            }
+            if let Some(xms) = options.initial_memory {
+                cmd.arg(format!("-Xms{}M", xms));
+            }
+            cmd.args(&options.jvm_args);
+
+            cmd.arg("-jar").arg("paper.jar").arg("nogui");
+            cmd.args(&options.server_args);
+
+            cmd
+        });
+
+        Ok((world_data, command_factory))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct BuildsResponse {
+    builds: Vec<BuildInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BuildInfo {
+    build: u32,
+    channel: String,
+    downloads: BuildDownloads,
+}
+
+#[derive(Debug, Deserialize)]
+struct BuildDownloads {
+    application: BuildDownload,
+}
+
+#[derive(Debug, Deserialize)]
+struct BuildDownload {
+    name: String,
+    sha256: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infra::{
+        mc_java::{JavaComponent, McJavaLoader},
+        url_fetcher::DummyUrlFetcher,
+    };
+    use std::path::PathBuf;
+
+    struct DummyJavaLoader;
+
+    #[async_trait::async_trait]
+    impl McJavaLoader for DummyJavaLoader {
+        async fn list_runtimes(&self) -> Result<Vec<crate::infra::mc_java::McJava>, String> {
+            Ok(vec![])
+        }
+
+        async fn ready_runtime(&self, _component: &JavaComponent) -> Result<PathBuf, String> {
+            Ok(PathBuf::from("/usr/bin/java"))
+        }
+    }
+
+    fn create_url_fetcher() -> DummyUrlFetcher {
+        let mut url_fetcher = DummyUrlFetcher::new();
+
+        url_fetcher.add_data(
+            Url::parse("https://api.papermc.io/v2/projects/paper/versions/1.20.1/builds").unwrap(),
+            r#"{
+                "project_id": "paper",
+                "version": "1.20.1",
+                "builds": [
+                    {
+                        "build": 195,
+                        "channel": "default",
+                        "downloads": {"application": {"name": "paper-1.20.1-195.jar", "sha256": "aaa"}}
+                    },
+                    {
+                        "build": 196,
+                        "channel": "default",
+                        "downloads": {"application": {"name": "paper-1.20.1-196.jar", "sha256": "bbb"}}
+                    },
+                    {
+                        "build": 197,
+                        "channel": "experimental",
+                        "downloads": {"application": {"name": "paper-1.20.1-197.jar", "sha256": "ccc"}}
+                    }
+                ]
+            }"#,
+        );
+        url_fetcher.add_data(
+            Url::parse("https://piston-meta.mojang.com/mc/game/version_manifest_v2.json").unwrap(),
+            r#"{
+                "versions": [
+                    {
+                        "id": "1.20.1",
+                        "type": "release",
+                        "url": "https://example.com/1.20.1.json"
+                    }
+                ]
+            }"#,
+        );
+        url_fetcher.add_data(
+            Url::parse("https://example.com/1.20.1.json").unwrap(),
+            r#"{
+                "downloads": {
+                    "server": {"url": "https://example.com/server.jar"}
+                },
+                "javaVersion": {"component": "java-runtime-gamma", "majorVersion": 17}
+            }"#,
+        );
+        url_fetcher
+    }
+
+    fn create_test_loader(url_fetcher: DummyUrlFetcher) -> PaperVersionLoader {
+        PaperVersionLoader::new(Arc::new(url_fetcher), Arc::new(DummyJavaLoader))
+    }
+
+    fn paper_jar_url(dir: &Dir) -> String {
+        match &dir.get_file(Path::from_str("paper.jar")).unwrap().content {
+            crate::util::file_trie::FileContent::Url(url) => url.to_string(),
+            _ => panic!("paper.jar should be a URL file"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ready_server_latest_build() {
+        let loader = create_test_loader(create_url_fetcher());
+        let (world_data, command_factory) = loader
+            .ready_server(
+                Dir::new(),
+                &McPaperVersion {
+                    version: McVanillaVersionId::new("1.20.1".to_string()),
+                    build: None,
+                },
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            paper_jar_url(&world_data),
+            "https://api.papermc.io/v2/projects/paper/versions/1.20.1/builds/196/downloads/paper-1.20.1-196.jar"
+        );
+        // The jar is verified against the checksum from the builds API
+        assert_eq!(
+            world_data
+                .get_file(Path::from_str("paper.jar"))
+                .unwrap()
+                .sha256
+                .as_deref(),
+            Some("bbb")
+        );
+
+        let command = command_factory(crate::domain::ServerRunOptions {
+            max_memory: Some(2048),
+            initial_memory: Some(1024),
+            ..Default::default()
+        });
+        let args: Vec<&str> = command
+            .get_args()
+            .map(|arg| arg.to_str().unwrap())
+            .collect();
+        assert_eq!(
+            args,
+            vec!["-Xmx2048M", "-Xms1024M", "-jar", "paper.jar", "nogui"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ready_server_specific_build() {
+        let loader = create_test_loader(create_url_fetcher());
+        let (world_data, _) = loader
+            .ready_server(
+                Dir::new(),
+                &McPaperVersion {
+                    version: McVanillaVersionId::new("1.20.1".to_string()),
+                    build: Some(195),
+                },
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            paper_jar_url(&world_data),
+            "https://api.papermc.io/v2/projects/paper/versions/1.20.1/builds/195/downloads/paper-1.20.1-195.jar"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ready_server_build_not_found() {
+        let loader = create_test_loader(create_url_fetcher());
+        let result = loader
+            .ready_server(
+                Dir::new(),
+                &McPaperVersion {
+                    version: McVanillaVersionId::new("1.20.1".to_string()),
+                    build: Some(1),
+                },
+            )
+            .await;
+
+        let error_msg = result.err().unwrap();
+        assert!(error_msg.contains("Paper build 1 not found"));
+    }
+}
@@ -9,7 +9,18 @@ use crate::util::fs_converter::TrieToFsConverter;
 #[async_trait::async_trait]
 pub trait TrieLoader {
     /// Writes the contents of the file trie to the specified base path.
-    async fn mount_contents(&self, trie: &Dir, base_path: &Path) -> Result<()>;
+    async fn mount_contents(&self, trie: &Dir, base_path: &Path) -> Result<()> {
+        self.mount_contents_with_progress(trie, base_path, &|_, _| {})
+            .await
+    }
+
+    /// Same as `mount_contents`, calling `on_progress(done, total)` each time a file is written.
+    async fn mount_contents_with_progress(
+        &self,
+        trie: &Dir,
+        base_path: &Path,
+        on_progress: &(dyn Fn(usize, usize) + Send + Sync),
+    ) -> Result<()>;
 
     /// Loads the content of a file from the trie.
     async fn load_content(&self, trie: &File) -> Result<Vec<u8>>;
@@ -36,8 +47,15 @@ impl DefaultTrieLoader {
 
 #[async_trait::async_trait]
 impl TrieLoader for DefaultTrieLoader {
-    async fn mount_contents(&self, trie: &Dir, base_path: &Path) -> Result<()> {
-        self.converter.write_directory(trie, base_path).await
+    async fn mount_contents_with_progress(
+        &self,
+        trie: &Dir,
+        base_path: &Path,
+        on_progress: &(dyn Fn(usize, usize) + Send + Sync),
+    ) -> Result<()> {
+        self.converter
+            .write_directory_with_progress(trie, base_path, on_progress)
+            .await
     }
 
     async fn load_content(&self, file: &File) -> Result<Vec<u8>> {
@@ -46,11 +64,16 @@ impl TrieLoader for DefaultTrieLoader {
             FileContent::Path(path) => self.fs_handler.read(path).map_err(|e| {
                 anyhow::anyhow!("Failed to read source file {}: {}", path.display(), e)
             }),
-            FileContent::Url(url) => self
-                .url_fetcher
-                .fetch_binary(url)
-                .await
-                .map_err(|e| anyhow::anyhow!("Failed to fetch URL {}: {}", url, e)),
+            FileContent::Url(url) => {
+                let data = self
+                    .url_fetcher
+                    .fetch_binary(url)
+                    .await
+                    .map_err(|e| anyhow::anyhow!("Failed to fetch URL {}: {}", url, e))?;
+                file.verify_content(&data)
+                    .map_err(|e| anyhow::anyhow!("Corrupted download from {}: {}", url, e))?;
+                Ok(data)
+            }
         }
     }
 }
@@ -100,4 +123,75 @@ mod tests {
             .unwrap();
         assert_eq!(nested_content, b"nested content");
     }
+
+    #[tokio::test]
+    async fn test_load_content_verifies_sha1() {
+        let url = url::Url::parse("https://example.com/server.jar").unwrap();
+        let mut url_fetcher = DummyUrlFetcher::new();
+        url_fetcher.add_data(url.clone(), b"hello world".to_vec());
+        let loader =
+            DefaultTrieLoader::new(Arc::new(OnMemoryFsHandler::new()), Arc::new(url_fetcher));
+
+        let file = File::url(url.clone(), Permission::read_write())
+            .with_sha1("2AAE6C35C94FCFB415DBE95F408B9CE91EE846ED");
+        assert_eq!(loader.load_content(&file).await.unwrap(), b"hello world");
+
+        // Hash of an empty file, as if the download was truncated
+        let file = File::url(url, Permission::read_write())
+            .with_sha1("da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        let err = loader.load_content(&file).await.unwrap_err().to_string();
+        assert!(err.contains("SHA1 mismatch"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_mount_contents_rejects_sha1_mismatch() {
+        let url = url::Url::parse("https://example.com/server.jar").unwrap();
+        let mut url_fetcher = DummyUrlFetcher::new();
+        url_fetcher.add_data(url.clone(), b"truncated".to_vec());
+        let fs_handler = Arc::new(OnMemoryFsHandler::new());
+        let loader = DefaultTrieLoader::new(fs_handler.clone(), Arc::new(url_fetcher));
+
+        let mut trie = Dir::new();
+        trie.put_file(
+            VirtualPath::from_str("server.jar"),
+            File::url(url, Permission::read_write())
+                .with_sha1("2aae6c35c94fcfb415dbe95f408b9ce91ee846ed"),
+        )
+        .unwrap();
+
+        let result = loader
+            .mount_contents(&trie, &PathBuf::from("/output"))
+            .await;
+        assert!(result.is_err());
+        assert!(
+            fs_handler
+                .read(&PathBuf::from("/output/server.jar"))
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mount_contents_reports_progress_per_file() {
+        let fs_handler = Arc::new(OnMemoryFsHandler::new());
+        let loader = DefaultTrieLoader::new(fs_handler.clone(), Arc::new(DummyUrlFetcher::new()));
+
+        let mut trie = Dir::new();
+        for name in ["a.txt", "b.txt", "subdir/c.txt"] {
+            trie.put_file(
+                VirtualPath::from_str(name),
+                File::inline(name.as_bytes().to_vec(), Permission::read_write()),
+            )
+            .unwrap();
+        }
+
+        let reports = std::sync::Mutex::new(Vec::new());
+        loader
+            .mount_contents_with_progress(&trie, &PathBuf::from("/output"), &|done, total| {
+                reports.lock().unwrap().push((done, total))
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(reports.into_inner().unwrap(), vec![(1, 3), (2, 3), (3, 3)]);
+    }
 }
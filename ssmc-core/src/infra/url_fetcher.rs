@@ -1,35 +1,437 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
+use futures::StreamExt;
+use reqwest::{
+    StatusCode,
+    header::{CONTENT_RANGE, RANGE, RETRY_AFTER},
+};
+use sha1::{Digest, Sha1};
+use tokio::io::AsyncWriteExt;
 use url::Url;
 
+use crate::infra::fs_handler::FsHandler;
+
 #[async_trait::async_trait]
 pub trait UrlFetcher: Send + Sync {
     async fn fetch_binary(&self, url: &Url) -> Result<Vec<u8>, String>;
+
+    /// Passes the body to `on_chunk` piece by piece, stopping at the first error it returns.
+    /// The default implementation fetches the whole body and passes it at once
+    async fn fetch_binary_chunks(
+        &self,
+        url: &Url,
+        on_chunk: &mut (dyn for<'a> FnMut(&'a [u8]) -> Result<(), String> + Send),
+    ) -> Result<(), String> {
+        let data = self.fetch_binary(url).await?;
+        on_chunk(&data)
+    }
+
+    /// Downloads `url` into `path`. The default implementation buffers the whole body in memory
+    async fn fetch_binary_to_file(&self, url: &Url, path: &Path) -> Result<(), String> {
+        let data = self.fetch_binary(url).await?;
+        tokio::fs::write(path, data)
+            .await
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct DefaultUrlFetcher;
 
+/// How many times a transient failure is retried before giving up
+const DEFAULT_MAX_RETRIES: u32 = 3;
+/// Wait before the first retry. The wait doubles on each following retry
+const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
 #[async_trait::async_trait]
 impl UrlFetcher for DefaultUrlFetcher {
     async fn fetch_binary(&self, url: &Url) -> Result<Vec<u8>, String> {
+        self.fetch_once(url).await.map_err(FetchError::into_message)
+    }
+
+    async fn fetch_binary_chunks(
+        &self,
+        url: &Url,
+        on_chunk: &mut (dyn for<'a> FnMut(&'a [u8]) -> Result<(), String> + Send),
+    ) -> Result<(), String> {
         let response = reqwest::get(url.as_str())
             .await
             .map_err(|e| format!("Failed to fetch URL {}: {}", url, e))?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(format!("Failed to fetch URL {}: HTTP {}", url, status));
+        }
+
+        let mut stream = response.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|e| format!("Failed to read response body: {}", e))?;
+            on_chunk(&chunk)?;
+        }
+        Ok(())
+    }
 
+    /// Streams the body into `<path>.part` and renames it once complete.
+    /// Transient failures are retried like [`RetryingUrlFetcher`] does,
+    /// and a leftover `.part` file, including one from an earlier run, is resumed with a `Range` request
+    async fn fetch_binary_to_file(&self, url: &Url, path: &Path) -> Result<(), String> {
+        let mut part_path = path.as_os_str().to_owned();
+        part_path.push(".part");
+        let part_path = PathBuf::from(part_path);
+
+        retry_transient(DEFAULT_MAX_RETRIES, DEFAULT_INITIAL_BACKOFF, || {
+            self.download_to_part(url, &part_path)
+        })
+        .await?;
+        tokio::fs::rename(&part_path, path)
+            .await
+            .map_err(|e| format!("Failed to move download to {}: {}", path.display(), e))
+    }
+}
+
+/// Whether a response continues a partial download or starts it over
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ResumeMode {
+    Append,
+    Restart,
+}
+
+/// Decides how to use a response to a request for the bytes after `written`.
+/// Anything but a 206 whose `Content-Range` starts exactly at `written` restarts the download
+fn resume_mode(written: u64, status: StatusCode, content_range: Option<&str>) -> ResumeMode {
+    if written == 0 || status != StatusCode::PARTIAL_CONTENT {
+        return ResumeMode::Restart;
+    }
+    let start = content_range
+        .and_then(|range| range.strip_prefix("bytes "))
+        .and_then(|range| range.split('-').next())
+        .and_then(|start| start.trim().parse::<u64>().ok());
+    if start == Some(written) {
+        ResumeMode::Append
+    } else {
+        ResumeMode::Restart
+    }
+}
+
+impl DefaultUrlFetcher {
+    async fn download_to_part(&self, url: &Url, part_path: &Path) -> Result<(), FetchError> {
+        let written = tokio::fs::metadata(part_path)
+            .await
+            .map(|metadata| metadata.len())
+            .unwrap_or(0);
+
+        let mut request = reqwest::Client::new().get(url.as_str());
+        if written > 0 {
+            request = request.header(RANGE, format!("bytes={}-", written));
+        }
+        let response = request.send().await.map_err(|e| request_error(url, e))?;
+
+        let status = response.status();
+        if status == StatusCode::RANGE_NOT_SATISFIABLE {
+            // The partial file no longer matches the resource, so start over on the next attempt
+            let _ = tokio::fs::remove_file(part_path).await;
+            return Err(FetchError::Transient(format!(
+                "Failed to resume URL {}: HTTP {}",
+                url, status
+            )));
+        }
+        if !status.is_success() {
+            return Err(response_error(url, &response));
+        }
+
+        let content_range = response
+            .headers()
+            .get(CONTENT_RANGE)
+            .and_then(|value| value.to_str().ok());
+        let mut options = tokio::fs::OpenOptions::new();
+        match resume_mode(written, status, content_range) {
+            ResumeMode::Append => options.append(true),
+            ResumeMode::Restart => options.write(true).create(true).truncate(true),
+        };
+        let write_error = |e: std::io::Error| {
+            FetchError::Permanent(format!("Failed to write {}: {}", part_path.display(), e))
+        };
+        let mut file = options.open(part_path).await.map_err(|e| {
+            FetchError::Permanent(format!("Failed to open {}: {}", part_path.display(), e))
+        })?;
+
+        let mut stream = response.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|e| {
+                FetchError::Transient(format!("Failed to read response body: {}", e))
+            })?;
+            file.write_all(&chunk).await.map_err(write_error)?;
+        }
+        file.flush().await.map_err(write_error)
+    }
+}
+
+/// Failure of a single fetch attempt
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FetchError {
+    /// Connection errors and 408, 429 and 5xx responses, which may succeed if retried
+    Transient(String),
+    /// A transient failure whose response asked to wait this long before retrying (`Retry-After`)
+    RetryAfter(String, Duration),
+    /// Other 4xx responses and errors that will not succeed if retried
+    Permanent(String),
+}
+
+impl FetchError {
+    pub fn into_message(self) -> String {
+        match self {
+            FetchError::Transient(message)
+            | FetchError::RetryAfter(message, _)
+            | FetchError::Permanent(message) => message,
+        }
+    }
+}
+
+/// The longest `Retry-After` that is honoured, so a misbehaving server cannot stall a run
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// Classifies a failure to send a request. Connection errors may succeed if retried
+fn request_error(url: &Url, e: reqwest::Error) -> FetchError {
+    let message = format!("Failed to fetch URL {}: {}", url, e);
+    if e.is_builder() || e.is_redirect() {
+        FetchError::Permanent(message)
+    } else {
+        FetchError::Transient(message)
+    }
+}
+
+fn response_error(url: &Url, response: &reqwest::Response) -> FetchError {
+    let retry_after = response
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok());
+    status_error(url, response.status(), retry_after)
+}
+
+/// Classifies a non-success response.
+/// Timeouts (408), rate limiting (429) and server errors may succeed if retried.
+/// `Retry-After` is honoured in its delta-seconds form only
+fn status_error(url: &Url, status: StatusCode, retry_after: Option<&str>) -> FetchError {
+    let message = format!("Failed to fetch URL {}: HTTP {}", url, status);
+    let transient = status.is_server_error()
+        || status == StatusCode::REQUEST_TIMEOUT
+        || status == StatusCode::TOO_MANY_REQUESTS;
+    if !transient {
+        return FetchError::Permanent(message);
+    }
+    match retry_after.and_then(|value| value.trim().parse::<u64>().ok()) {
+        Some(seconds) => {
+            FetchError::RetryAfter(message, Duration::from_secs(seconds).min(MAX_RETRY_AFTER))
+        }
+        None => FetchError::Transient(message),
+    }
+}
+
+/// A fetcher that tells transient failures apart from permanent ones
+#[async_trait::async_trait]
+pub trait RetryableUrlFetcher: Send + Sync {
+    async fn fetch_once(&self, url: &Url) -> Result<Vec<u8>, FetchError>;
+}
+
+#[async_trait::async_trait]
+impl RetryableUrlFetcher for DefaultUrlFetcher {
+    async fn fetch_once(&self, url: &Url) -> Result<Vec<u8>, FetchError> {
+        let response = reqwest::get(url.as_str())
+            .await
+            .map_err(|e| request_error(url, e))?;
         if !response.status().is_success() {
-            return Err(format!(
-                "Failed to fetch URL {}: HTTP {}",
-                url,
-                response.status()
-            ));
+            return Err(response_error(url, &response));
         }
 
         response
             .bytes()
             .await
             .map(|bytes| bytes.to_vec())
-            .map_err(|e| format!("Failed to read response body: {}", e))
+            .map_err(|e| FetchError::Transient(format!("Failed to read response body: {}", e)))
+    }
+}
+
+/// Retries transient failures with exponential backoff
+pub struct RetryingUrlFetcher<F: RetryableUrlFetcher = DefaultUrlFetcher> {
+    inner: F,
+    max_retries: u32,
+    initial_backoff: Duration,
+}
+
+impl<F: RetryableUrlFetcher> RetryingUrlFetcher<F> {
+    /// Retries up to 3 times, waiting 500ms before the first retry
+    pub fn new(inner: F) -> Self {
+        Self {
+            inner,
+            max_retries: DEFAULT_MAX_RETRIES,
+            initial_backoff: DEFAULT_INITIAL_BACKOFF,
+        }
+    }
+
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Wait before the first retry. The wait doubles on each following retry
+    pub fn with_initial_backoff(mut self, initial_backoff: Duration) -> Self {
+        self.initial_backoff = initial_backoff;
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl<F: RetryableUrlFetcher> UrlFetcher for RetryingUrlFetcher<F> {
+    async fn fetch_binary(&self, url: &Url) -> Result<Vec<u8>, String> {
+        retry_transient(self.max_retries, self.initial_backoff, || {
+            self.inner.fetch_once(url)
+        })
+        .await
+    }
+}
+
+/// Runs `attempt` until it succeeds, retrying transient failures up to `max_retries` times.
+/// Waits for `Retry-After` if the server asked for it, otherwise for a backoff that starts at
+/// `initial_backoff` and doubles on each retry
+async fn retry_transient<T, Fut>(
+    max_retries: u32,
+    initial_backoff: Duration,
+    mut attempt: impl FnMut() -> Fut,
+) -> Result<T, String>
+where
+    Fut: Future<Output = Result<T, FetchError>>,
+{
+    let mut backoff = initial_backoff;
+    let mut retries = 0;
+    loop {
+        let (message, wait) = match attempt().await {
+            Ok(value) => return Ok(value),
+            Err(FetchError::Transient(message)) => (message, backoff),
+            Err(FetchError::RetryAfter(message, wait)) => (message, wait),
+            Err(FetchError::Permanent(message)) => return Err(message),
+        };
+        if retries >= max_retries {
+            return Err(message);
+        }
+        eprintln!(
+            "{} (retrying in {:?}, {}/{})",
+            message,
+            wait,
+            retries + 1,
+            max_retries
+        );
+        tokio::time::sleep(wait).await;
+        backoff *= 2;
+        retries += 1;
+    }
+}
+
+type UrlMatcher = Box<dyn Fn(&Url) -> bool + Send + Sync>;
+
+/// Caches responses of another fetcher under a directory
+pub struct CachingUrlFetcher {
+    inner: Arc<dyn UrlFetcher + Send + Sync>,
+    fs_handler: Arc<dyn FsHandler + Send + Sync>,
+    cache_dir: PathBuf,
+    ttl_rules: Vec<(UrlMatcher, Duration)>,
+}
+
+impl CachingUrlFetcher {
+    /// Responses are cached forever unless a TTL rule matches the URL
+    pub fn new(
+        inner: Arc<dyn UrlFetcher + Send + Sync>,
+        fs_handler: Arc<dyn FsHandler + Send + Sync>,
+        cache_dir: PathBuf,
+    ) -> Self {
+        Self {
+            inner,
+            fs_handler,
+            cache_dir,
+            ttl_rules: Vec::new(),
+        }
+    }
+
+    /// Expires cached responses of matching URLs after `ttl`. The first matching rule is used
+    pub fn with_ttl(
+        mut self,
+        ttl: Duration,
+        matches: impl Fn(&Url) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.ttl_rules.push((Box::new(matches), ttl));
+        self
+    }
+
+    fn cache_paths(&self, url: &Url) -> (PathBuf, PathBuf) {
+        let key = Sha1::digest(url.as_str().as_bytes())
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>();
+        (
+            self.cache_dir.join(&key),
+            self.cache_dir.join(format!("{}.fetched_at", key)),
+        )
+    }
+
+    fn read_cache(&self, url: &Url) -> Option<Vec<u8>> {
+        let (data_path, time_path) = self.cache_paths(url);
+        if !self.fs_handler.is_file(&data_path) {
+            return None;
+        }
+        if let Some((_, ttl)) = self.ttl_rules.iter().find(|(matches, _)| matches(url)) {
+            let fetched_at = self
+                .fs_handler
+                .read(&time_path)
+                .ok()
+                .and_then(|data| String::from_utf8(data).ok())
+                .and_then(|text| text.trim().parse::<u64>().ok())?;
+            let age = SystemTime::now()
+                .duration_since(UNIX_EPOCH + Duration::from_secs(fetched_at))
+                .unwrap_or_default();
+            if age >= *ttl {
+                return None;
+            }
+        }
+        self.fs_handler.read(&data_path).ok()
+    }
+
+    fn write_cache(&self, url: &Url, data: &[u8]) -> Result<(), String> {
+        let (data_path, time_path) = self.cache_paths(url);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        // The timestamp is written only once the body is complete and in place
+        self.write_atomically(&data_path, data)?;
+        self.write_atomically(&time_path, now.to_string().as_bytes())
+    }
+
+    /// Writes to a temporary file and moves it into place,
+    /// so an interrupted write never leaves a truncated file under `path`
+    fn write_atomically(&self, path: &Path, data: &[u8]) -> Result<(), String> {
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_path);
+        self.fs_handler.write(&tmp_path, data, false)?;
+        self.fs_handler.rename(&tmp_path, path)
+    }
+}
+
+#[async_trait::async_trait]
+impl UrlFetcher for CachingUrlFetcher {
+    async fn fetch_binary(&self, url: &Url) -> Result<Vec<u8>, String> {
+        if let Some(data) = self.read_cache(url) {
+            return Ok(data);
+        }
+        let data = self.inner.fetch_binary(url).await?;
+        // A broken cache only costs a re-download, so the response is returned anyway
+        if let Err(e) = self.write_cache(url, &data) {
+            eprintln!("Failed to cache response of {}: {}", url, e);
+        }
+        Ok(data)
     }
 }
 
@@ -106,6 +508,335 @@ mod tests {
         assert_eq!(result2.unwrap(), data2);
     }
 
+    #[tokio::test]
+    async fn test_fetch_binary_to_file_default_writes_body() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("data.bin");
+        let mut fetcher = DummyUrlFetcher::new();
+        let url = Url::parse("https://example.com/data.bin").unwrap();
+        fetcher.add_data(url.clone(), b"payload".to_vec());
+
+        fetcher.fetch_binary_to_file(&url, &path).await.unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"payload");
+    }
+
+    #[test]
+    fn test_resume_mode() {
+        let partial = StatusCode::PARTIAL_CONTENT;
+        // Nothing written yet
+        assert_eq!(resume_mode(0, StatusCode::OK, None), ResumeMode::Restart);
+        // Range matches the bytes already written
+        assert_eq!(
+            resume_mode(100, partial, Some("bytes 100-999/1000")),
+            ResumeMode::Append
+        );
+        assert_eq!(
+            resume_mode(100, partial, Some("bytes 100-999/*")),
+            ResumeMode::Append
+        );
+        // Server ignored the Range header
+        assert_eq!(
+            resume_mode(100, StatusCode::OK, Some("bytes 100-999/1000")),
+            ResumeMode::Restart
+        );
+        // Range starts elsewhere or is missing
+        assert_eq!(
+            resume_mode(100, partial, Some("bytes 0-999/1000")),
+            ResumeMode::Restart
+        );
+        assert_eq!(resume_mode(100, partial, None), ResumeMode::Restart);
+        assert_eq!(
+            resume_mode(100, partial, Some("garbage")),
+            ResumeMode::Restart
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "requires network access"]
+    async fn test_default_fetch_binary_to_file_resumes_partial_download() {
+        // httpbin serves "abcdefghijklmnopqrstuvwxyz" repeated and honours Range requests
+        let expected: Vec<u8> = b"abcdefghijklmnopqrstuvwxyz"
+            .iter()
+            .cycle()
+            .take(1024)
+            .copied()
+            .collect();
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("range.bin");
+        std::fs::write(temp_dir.path().join("range.bin.part"), &expected[..100]).unwrap();
+
+        let url = Url::parse("https://httpbin.org/range/1024").unwrap();
+        DefaultUrlFetcher
+            .fetch_binary_to_file(&url, &path)
+            .await
+            .unwrap();
+
+        assert_eq!(std::fs::read(&path).unwrap(), expected);
+        assert!(!temp_dir.path().join("range.bin.part").exists());
+    }
+
+    // Serves `response` to every request on a local port and counts the requests
+    fn serve_locally(response: &'static str) -> (Url, Arc<std::sync::atomic::AtomicUsize>) {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}/file", listener.local_addr().unwrap())).unwrap();
+        let requests = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = requests.clone();
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                let mut request = Vec::new();
+                let mut buf = [0u8; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    match stream.read(&mut buf) {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => request.extend_from_slice(&buf[..n]),
+                    }
+                }
+                let _ = stream.write_all(response.as_bytes());
+            }
+        });
+        (url, requests)
+    }
+
+    #[tokio::test]
+    async fn test_default_fetch_binary_to_file_does_not_retry_client_errors() {
+        let (url, requests) = serve_locally(
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        );
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let error = DefaultUrlFetcher
+            .fetch_binary_to_file(&url, &temp_dir.path().join("file"))
+            .await
+            .unwrap_err();
+        assert!(error.contains("HTTP 404"), "{}", error);
+        assert_eq!(requests.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    // Fails with the given errors in order, then succeeds
+    struct FlakyUrlFetcher {
+        failures: std::sync::Mutex<Vec<FetchError>>,
+        attempts: std::sync::atomic::AtomicUsize,
+    }
+
+    impl FlakyUrlFetcher {
+        fn new(mut failures: Vec<FetchError>) -> Self {
+            failures.reverse();
+            Self {
+                failures: std::sync::Mutex::new(failures),
+                attempts: std::sync::atomic::AtomicUsize::new(0),
+            }
+        }
+
+        fn attempts(&self) -> usize {
+            self.attempts.load(std::sync::atomic::Ordering::SeqCst)
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl RetryableUrlFetcher for FlakyUrlFetcher {
+        async fn fetch_once(&self, _url: &Url) -> Result<Vec<u8>, FetchError> {
+            self.attempts
+                .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            match self.failures.lock().unwrap().pop() {
+                Some(error) => Err(error),
+                None => Ok(b"data".to_vec()),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retrying_url_fetcher_recovers_from_transient_errors() {
+        let fetcher = RetryingUrlFetcher::new(FlakyUrlFetcher::new(vec![
+            FetchError::Transient("connection reset".to_string()),
+            FetchError::Transient("HTTP 503".to_string()),
+        ]))
+        .with_initial_backoff(Duration::from_millis(1));
+        let url = Url::parse("https://example.com").unwrap();
+
+        assert_eq!(fetcher.fetch_binary(&url).await.unwrap(), b"data");
+        assert_eq!(fetcher.inner.attempts(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_retrying_url_fetcher_gives_up_after_max_retries() {
+        let fetcher = RetryingUrlFetcher::new(FlakyUrlFetcher::new(vec![
+            FetchError::Transient("HTTP 500".to_string()),
+            FetchError::Transient("HTTP 502".to_string()),
+            FetchError::Transient("HTTP 503".to_string()),
+        ]))
+        .with_max_retries(2)
+        .with_initial_backoff(Duration::from_millis(1));
+        let url = Url::parse("https://example.com").unwrap();
+
+        assert_eq!(fetcher.fetch_binary(&url).await.unwrap_err(), "HTTP 503");
+        assert_eq!(fetcher.inner.attempts(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_retrying_url_fetcher_does_not_retry_permanent_errors() {
+        let fetcher = RetryingUrlFetcher::new(FlakyUrlFetcher::new(vec![FetchError::Permanent(
+            "HTTP 404".to_string(),
+        )]))
+        .with_initial_backoff(Duration::from_millis(1));
+        let url = Url::parse("https://example.com").unwrap();
+
+        assert_eq!(fetcher.fetch_binary(&url).await.unwrap_err(), "HTTP 404");
+        assert_eq!(fetcher.inner.attempts(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_retrying_url_fetcher_honours_retry_after() {
+        // The server's wait is used instead of the much longer backoff
+        let fetcher = RetryingUrlFetcher::new(FlakyUrlFetcher::new(vec![FetchError::RetryAfter(
+            "HTTP 429".to_string(),
+            Duration::from_millis(1),
+        )]))
+        .with_initial_backoff(Duration::from_secs(3600));
+        let url = Url::parse("https://example.com").unwrap();
+
+        let data = tokio::time::timeout(Duration::from_secs(10), fetcher.fetch_binary(&url))
+            .await
+            .expect("Retry-After was not honoured");
+        assert_eq!(data.unwrap(), b"data");
+        assert_eq!(fetcher.inner.attempts(), 2);
+    }
+
+    #[test]
+    fn test_status_error() {
+        let url = Url::parse("https://example.com/file").unwrap();
+        let message = |status: u16| {
+            format!(
+                "Failed to fetch URL {}: HTTP {}",
+                url,
+                StatusCode::from_u16(status).unwrap()
+            )
+        };
+        let error = |status: u16, retry_after| {
+            status_error(&url, StatusCode::from_u16(status).unwrap(), retry_after)
+        };
+
+        assert_eq!(error(429, None), FetchError::Transient(message(429)));
+        assert_eq!(error(408, None), FetchError::Transient(message(408)));
+        assert_eq!(error(503, None), FetchError::Transient(message(503)));
+        assert_eq!(error(404, None), FetchError::Permanent(message(404)));
+        assert_eq!(error(403, Some("5")), FetchError::Permanent(message(403)));
+        assert_eq!(
+            error(429, Some("5")),
+            FetchError::RetryAfter(message(429), Duration::from_secs(5))
+        );
+        assert_eq!(
+            error(503, Some("86400")),
+            FetchError::RetryAfter(message(503), MAX_RETRY_AFTER)
+        );
+        // HTTP-date values fall back to the backoff
+        assert_eq!(
+            error(429, Some("Wed, 21 Oct 2015 07:28:00 GMT")),
+            FetchError::Transient(message(429))
+        );
+    }
+
+    // Counts how many times each URL was fetched
+    struct CountingUrlFetcher {
+        inner: DummyUrlFetcher,
+        calls: std::sync::Mutex<Vec<Url>>,
+    }
+
+    #[async_trait::async_trait]
+    impl UrlFetcher for CountingUrlFetcher {
+        async fn fetch_binary(&self, url: &Url) -> Result<Vec<u8>, String> {
+            self.calls.lock().unwrap().push(url.clone());
+            self.inner.fetch_binary(url).await
+        }
+    }
+
+    fn counting_fetcher(urls: &[&Url]) -> Arc<CountingUrlFetcher> {
+        let mut inner = DummyUrlFetcher::new();
+        for url in urls {
+            inner.add_data((*url).clone(), url.path().as_bytes().to_vec());
+        }
+        Arc::new(CountingUrlFetcher {
+            inner,
+            calls: std::sync::Mutex::new(Vec::new()),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_caching_url_fetcher_serves_cached_response() {
+        let url = Url::parse("https://example.com/server.jar").unwrap();
+        let inner = counting_fetcher(&[&url]);
+        let fs_handler = Arc::new(crate::infra::fs_handler::OnMemoryFsHandler::new());
+        let fetcher =
+            CachingUrlFetcher::new(inner.clone(), fs_handler.clone(), PathBuf::from("/cache"));
+
+        assert_eq!(fetcher.fetch_binary(&url).await.unwrap(), b"/server.jar");
+        assert_eq!(fetcher.fetch_binary(&url).await.unwrap(), b"/server.jar");
+        assert_eq!(inner.calls.lock().unwrap().len(), 1);
+
+        // The cache is kept on disk and shared by new fetchers
+        let fetcher = CachingUrlFetcher::new(inner.clone(), fs_handler, PathBuf::from("/cache"));
+        assert_eq!(fetcher.fetch_binary(&url).await.unwrap(), b"/server.jar");
+        assert_eq!(inner.calls.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_caching_url_fetcher_ignores_interrupted_write() {
+        let url = Url::parse("https://example.com/server.jar").unwrap();
+        let inner = counting_fetcher(&[&url]);
+        let fs_handler = Arc::new(crate::infra::fs_handler::OnMemoryFsHandler::new());
+        let fetcher =
+            CachingUrlFetcher::new(inner.clone(), fs_handler.clone(), PathBuf::from("/cache"));
+        // A truncated body left behind by a write that was interrupted before being moved into place
+        let (data_path, _) = fetcher.cache_paths(&url);
+        let tmp_path = PathBuf::from(format!("{}.tmp", data_path.display()));
+        fs_handler.write(&tmp_path, b"/serv", false).unwrap();
+
+        assert_eq!(fetcher.fetch_binary(&url).await.unwrap(), b"/server.jar");
+        assert_eq!(fs_handler.read(&data_path).unwrap(), b"/server.jar");
+        assert!(!fs_handler.is_file(&tmp_path));
+        assert_eq!(fetcher.fetch_binary(&url).await.unwrap(), b"/server.jar");
+        assert_eq!(inner.calls.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_caching_url_fetcher_expires_matching_urls() {
+        let manifest_url = Url::parse("https://example.com/version_manifest.json").unwrap();
+        let jar_url = Url::parse("https://example.com/server.jar").unwrap();
+        let inner = counting_fetcher(&[&manifest_url, &jar_url]);
+        let fetcher = CachingUrlFetcher::new(
+            inner.clone(),
+            Arc::new(crate::infra::fs_handler::OnMemoryFsHandler::new()),
+            PathBuf::from("/cache"),
+        )
+        .with_ttl(Duration::ZERO, |url| url.path().ends_with(".json"));
+
+        for _ in 0..2 {
+            fetcher.fetch_binary(&manifest_url).await.unwrap();
+            fetcher.fetch_binary(&jar_url).await.unwrap();
+        }
+
+        let calls = inner.calls.lock().unwrap();
+        assert_eq!(calls.iter().filter(|url| **url == manifest_url).count(), 2);
+        assert_eq!(calls.iter().filter(|url| **url == jar_url).count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_caching_url_fetcher_does_not_cache_errors() {
+        let url = Url::parse("https://example.com/missing").unwrap();
+        let inner = counting_fetcher(&[]);
+        let fetcher = CachingUrlFetcher::new(
+            inner.clone(),
+            Arc::new(crate::infra::fs_handler::OnMemoryFsHandler::new()),
+            PathBuf::from("/cache"),
+        );
+
+        assert!(fetcher.fetch_binary(&url).await.is_err());
+        assert!(fetcher.fetch_binary(&url).await.is_err());
+        assert_eq!(inner.calls.lock().unwrap().len(), 2);
+    }
+
     #[tokio::test]
     #[ignore = "requires network access"]
     async fn test_default_url_fetcher_success() {
@@ -1,12 +1,25 @@
 use crate::{
     domain::{McServerLoader, McVanillaVersionId, McVersion},
-    infra::{mc_java::McJavaLoader, url_fetcher::UrlFetcher},
+    infra::{
+        mc_java::{JavaComponent, McJavaLoader},
+        url_fetcher::UrlFetcher,
+    },
     util::file_trie::{Dir, File, Path, Permission},
 };
 use serde::Deserialize;
-use std::sync::Arc;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::Mutex;
 use url::Url;
 
+const VERSION_MANIFEST_URL: &str =
+    "https://piston-meta.mojang.com/mc/game/version_manifest_v2.json";
+
+/// How long a fetched version manifest is reused by default
+pub const DEFAULT_MANIFEST_TTL: Duration = Duration::from_secs(10 * 60);
+
 pub enum McVanillaVersionType {
     Release,
     Snapshot,
@@ -15,6 +28,9 @@ pub enum McVanillaVersionType {
 pub struct McVanillaVersion {
     pub version: McVanillaVersionId,
     pub version_type: McVanillaVersionType,
+    /// `releaseTime` from the version manifest, an ISO 8601 time in UTC that sorts
+    /// chronologically as a string. `None` when the version did not come from the manifest
+    pub release_time: Option<String>,
 }
 
 impl McVersion for McVanillaVersion {
@@ -27,11 +43,17 @@ pub enum McVanillaVersionQuery {
     All,
     Release,
     Snapshot,
+    /// Only the newest release, as listed in the manifest's `latest`
+    LatestRelease,
+    /// Only the newest snapshot, which is the newest release when no snapshot is newer
+    LatestSnapshot,
 }
 
 pub struct VanillaVersionLoader {
     url_fetcher: Arc<dyn UrlFetcher + Send + Sync>,
     java_loader: Arc<dyn McJavaLoader + Send + Sync>,
+    manifest_ttl: Duration,
+    manifest_cache: Mutex<Option<(Instant, Arc<VersionManifest>)>>,
 }
 
 impl VanillaVersionLoader {
@@ -42,7 +64,52 @@ impl VanillaVersionLoader {
         Self {
             url_fetcher,
             java_loader,
+            manifest_ttl: DEFAULT_MANIFEST_TTL,
+            manifest_cache: Mutex::new(None),
+        }
+    }
+
+    /// Reuses the fetched version manifest for `ttl` before fetching it again
+    pub fn with_manifest_ttl(mut self, ttl: Duration) -> Self {
+        self.manifest_ttl = ttl;
+        self
+    }
+
+    /// Fetches the version manifest again, replacing the cached copy
+    pub async fn refresh(&self) -> Result<(), String> {
+        let mut cache = self.manifest_cache.lock().await;
+        let manifest = fetch_version_manifest(self.url_fetcher.as_ref()).await?;
+        *cache = Some((Instant::now(), Arc::new(manifest)));
+        Ok(())
+    }
+
+    /// Resolves the newest version of `version_type`
+    pub async fn resolve_latest(
+        &self,
+        version_type: McVanillaVersionType,
+    ) -> Result<McVanillaVersion, String> {
+        let query = match version_type {
+            McVanillaVersionType::Release => McVanillaVersionQuery::LatestRelease,
+            McVanillaVersionType::Snapshot => McVanillaVersionQuery::LatestSnapshot,
+        };
+        let manifest = self.version_manifest().await?;
+        query_manifest(&manifest, &query)
+            .into_iter()
+            .next()
+            .ok_or_else(|| "Latest version not found in version manifest".to_string())
+    }
+
+    async fn version_manifest(&self) -> Result<Arc<VersionManifest>, String> {
+        // Hold the lock while fetching so concurrent callers share one request
+        let mut cache = self.manifest_cache.lock().await;
+        if let Some((fetched_at, manifest)) = cache.as_ref()
+            && fetched_at.elapsed() < self.manifest_ttl
+        {
+            return Ok(manifest.clone());
         }
+        let manifest = Arc::new(fetch_version_manifest(self.url_fetcher.as_ref()).await?);
+        *cache = Some((Instant::now(), manifest.clone()));
+        Ok(manifest)
     }
 }
 
@@ -60,56 +127,30 @@ impl McServerLoader for VanillaVersionLoader {
         ),
         String,
     > {
-        // Step 1: Get version manifest
-        let manifest_url =
-            Url::parse("https://piston-meta.mojang.com/mc/game/version_manifest_v2.json")
-                .map_err(|e| format!("Invalid manifest URL: {}", e))?;
-
-        let manifest_data = self.url_fetcher.fetch_binary(&manifest_url).await?;
-        let manifest: VersionManifest = serde_json::from_slice(&manifest_data)
-            .map_err(|e| format!("Failed to parse version manifest: {}", e))?;
-
-        // Step 2: Find the specific version
-        let version_info = manifest
-            .versions
-            .into_iter()
-            .find(|v| v.id == version.version.id())
-            .ok_or_else(|| format!("Version '{}' not found", version.version.id()))?;
-
-        // Step 3: Get version details
-        let version_url =
-            Url::parse(&version_info.url).map_err(|e| format!("Invalid version URL: {}", e))?;
-
-        let version_data = self.url_fetcher.fetch_binary(&version_url).await?;
-        let version_details: VersionDetails = serde_json::from_slice(&version_data)
-            .map_err(|e| format!("Failed to parse version details: {}", e))?;
-
-        // Step 4: Create file bundle with server jar
-        let server_download = version_details.downloads.server.ok_or_else(|| {
-            format!(
-                "Server download not available for version '{}'",
-                version.version.id()
-            )
-        })?;
-
-        let server_url =
-            Url::parse(&server_download.url).map_err(|e| format!("Invalid server URL: {}", e))?;
-
-        let server_file = File::url(server_url, Permission::read_write());
+        // Step 1: Resolve the server jar and Java runtime for the version
+        let manifest = self.version_manifest().await?;
+        let download = fetch_server_download_from_manifest(
+            self.url_fetcher.as_ref(),
+            &manifest,
+            &version.version,
+        )
+        .await?;
+
+        // Step 2: Create file bundle with server jar
+        let mut server_file = File::url(download.server_url, Permission::read_write());
+        if let Some(sha1) = download.server_sha1 {
+            server_file = server_file.with_sha1(sha1);
+        }
         world_data.put_file(Path::from_str("server.jar"), server_file)
             .map_err(|_| "Failed to add server.jar to world data".to_string())?;
 
-        // Step 5: Get Java runtime path
-        // Determine required Java version from version details
-        let java_version_id = McVanillaVersionId::new(
-            version_details
-                .java_version
-                .map_or("jre-legacy".to_string(), |x| x.component),
-        );
+        // Step 3: Get Java runtime path
+        let java_path = self
+            .java_loader
+            .ready_runtime(&download.java_component)
+            .await?;
 
-        let java_path = self.java_loader.ready_runtime(&java_version_id).await?;
-
-        // Step 6: Create command factory with full Java path
+        // Step 4: Create command factory with full Java path
         let command_factory = Box::new(move |options: crate::domain::ServerRunOptions| {
             let mut cmd = std::process::Command::new(&java_path);
 
@@ -120,9 +161,11 @@ impl McServerLoader for VanillaVersionLoader {
             if let Some(xms) = options.initial_memory {
                 cmd.arg(format!("-Xms{}M", xms));
             }
+            cmd.args(&options.jvm_args);
 
             // Add jar and nogui arguments
             cmd.arg("-jar").arg("server.jar").arg("nogui");
+            cmd.args(&options.server_args);
 
             cmd
         });
@@ -131,72 +174,149 @@ impl McServerLoader for VanillaVersionLoader {
     }
 }
 
+/// Where to download a vanilla server jar and which Java runtime it needs
+pub(crate) struct VanillaServerDownload {
+    pub server_url: Url,
+    pub server_sha1: Option<String>,
+    pub java_component: JavaComponent,
+}
+
+pub(crate) async fn fetch_server_download(
+    url_fetcher: &(dyn UrlFetcher + Send + Sync),
+    version: &McVanillaVersionId,
+) -> Result<VanillaServerDownload, String> {
+    let manifest = fetch_version_manifest(url_fetcher).await?;
+    fetch_server_download_from_manifest(url_fetcher, &manifest, version).await
+}
+
+async fn fetch_version_manifest(
+    url_fetcher: &(dyn UrlFetcher + Send + Sync),
+) -> Result<VersionManifest, String> {
+    let manifest_url =
+        Url::parse(VERSION_MANIFEST_URL).map_err(|e| format!("Invalid manifest URL: {}", e))?;
+
+    let manifest_data = url_fetcher.fetch_binary(&manifest_url).await?;
+    serde_json::from_slice(&manifest_data)
+        .map_err(|e| format!("Failed to parse version manifest: {}", e))
+}
+
+async fn fetch_server_download_from_manifest(
+    url_fetcher: &(dyn UrlFetcher + Send + Sync),
+    manifest: &VersionManifest,
+    version: &McVanillaVersionId,
+) -> Result<VanillaServerDownload, String> {
+    // Find the specific version
+    let version_info = manifest
+        .versions
+        .iter()
+        .find(|v| v.id == version.id())
+        .ok_or_else(|| format!("Version '{}' not found", version.id()))?;
+
+    // Get version details
+    let version_url =
+        Url::parse(&version_info.url).map_err(|e| format!("Invalid version URL: {}", e))?;
+
+    let version_data = url_fetcher.fetch_binary(&version_url).await?;
+    let version_details: VersionDetails = serde_json::from_slice(&version_data)
+        .map_err(|e| format!("Failed to parse version details: {}", e))?;
+
+    let java_component = version_details.java_component();
+    let server_download = version_details.downloads.server.ok_or_else(|| {
+        format!(
+            "Server download not available for version '{}'",
+            version.id()
+        )
+    })?;
+
+    let server_url =
+        Url::parse(&server_download.url).map_err(|e| format!("Invalid server URL: {}", e))?;
+
+    Ok(VanillaServerDownload {
+        server_url,
+        server_sha1: server_download.sha1,
+        java_component,
+    })
+}
+
 #[async_trait::async_trait]
 impl crate::domain::McVersionQuerier for VanillaVersionLoader {
     type Version = McVanillaVersion;
     type VersionQuery = McVanillaVersionQuery;
 
     async fn query_versions(&self, query: &Self::VersionQuery) -> Vec<Self::Version> {
-        let url =
-            match Url::parse("https://piston-meta.mojang.com/mc/game/version_manifest_v2.json") {
-                Ok(url) => url,
-                Err(_) => return vec![],
-            };
-
-        let data = match self.url_fetcher.fetch_binary(&url).await {
-            Ok(data) => data,
-            Err(_) => return vec![],
-        };
-
-        let manifest: VersionManifest = match serde_json::from_slice(&data) {
+        let manifest = match self.version_manifest().await {
             Ok(manifest) => manifest,
             Err(_) => return vec![],
         };
+        query_manifest(&manifest, query)
+    }
+}
 
-        manifest
-            .versions
-            .into_iter()
-            .filter_map(|version_info| {
-                let version_type = match version_info.version_type.as_str() {
-                    "release" => McVanillaVersionType::Release,
-                    "snapshot" => McVanillaVersionType::Snapshot,
-                    _ => return None,
-                };
-
-                let should_include = match query {
-                    McVanillaVersionQuery::All => true,
-                    McVanillaVersionQuery::Release => {
-                        matches!(version_type, McVanillaVersionType::Release)
-                    }
-                    McVanillaVersionQuery::Snapshot => {
-                        matches!(version_type, McVanillaVersionType::Snapshot)
-                    }
-                };
-
-                if should_include {
-                    Some(McVanillaVersion {
-                        version: McVanillaVersionId::new(version_info.id),
-                        version_type,
-                    })
-                } else {
-                    None
+fn query_manifest(
+    manifest: &VersionManifest,
+    query: &McVanillaVersionQuery,
+) -> Vec<McVanillaVersion> {
+    let latest = manifest.latest.as_ref();
+    manifest
+        .versions
+        .iter()
+        .filter_map(|version_info| {
+            let version_type = match version_info.version_type.as_str() {
+                "release" => McVanillaVersionType::Release,
+                "snapshot" => McVanillaVersionType::Snapshot,
+                _ => return None,
+            };
+
+            let should_include = match query {
+                McVanillaVersionQuery::All => true,
+                McVanillaVersionQuery::Release => {
+                    matches!(version_type, McVanillaVersionType::Release)
                 }
-            })
-            .collect()
-    }
+                McVanillaVersionQuery::Snapshot => {
+                    matches!(version_type, McVanillaVersionType::Snapshot)
+                }
+                McVanillaVersionQuery::LatestRelease => {
+                    latest.is_some_and(|latest| latest.release == version_info.id)
+                }
+                McVanillaVersionQuery::LatestSnapshot => {
+                    latest.is_some_and(|latest| latest.snapshot == version_info.id)
+                }
+            };
+
+            if should_include {
+                Some(McVanillaVersion {
+                    version: McVanillaVersionId::new(version_info.id.clone()),
+                    version_type,
+                    release_time: version_info.release_time.clone(),
+                })
+            } else {
+                None
+            }
+        })
+        .collect()
 }
 
 #[derive(Debug, Deserialize)]
 struct VersionManifest {
+    #[serde(default)]
+    latest: Option<LatestVersions>,
     versions: Vec<VersionInfo>,
 }
 
+#[derive(Debug, Deserialize)]
+struct LatestVersions {
+    release: String,
+    snapshot: String,
+}
+
 #[derive(Debug, Deserialize)]
 struct VersionInfo {
     id: String,
     #[serde(rename = "type")]
     version_type: String,
     url: String,
+    #[serde(rename = "releaseTime", default)]
+    release_time: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -206,6 +326,17 @@ struct VersionDetails {
     java_version: Option<JavaVersion>,
 }
 
+impl VersionDetails {
+    /// The Java runtime the version requires.
+    /// Versions older than 1.17 have no `javaVersion` and run on the legacy Java 8 runtime
+    fn java_component(&self) -> JavaComponent {
+        match &self.java_version {
+            Some(java_version) => JavaComponent::new(java_version.component.clone()),
+            None => JavaComponent::legacy(),
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 struct Downloads {
     server: Option<DownloadInfo>,
@@ -214,6 +345,7 @@ struct Downloads {
 #[derive(Debug, Deserialize)]
 struct DownloadInfo {
     url: String,
+    sha1: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -238,7 +370,7 @@ mod tests {
             Ok(vec![])
         }
 
-        async fn ready_runtime(&self, _version_id: &McVanillaVersionId) -> Result<PathBuf, String> {
+        async fn ready_runtime(&self, _component: &JavaComponent) -> Result<PathBuf, String> {
             Ok(PathBuf::from("/usr/bin/java"))
         }
     }
@@ -300,6 +432,10 @@ mod tests {
             versions[2].version_type,
             McVanillaVersionType::Release
         ));
+        assert_eq!(
+            versions[2].release_time.as_deref(),
+            Some("2023-03-14T12:56:18+00:00")
+        );
     }
 
     #[tokio::test]
@@ -380,6 +516,71 @@ mod tests {
         ));
     }
 
+    fn latest_manifest_loader(latest_snapshot: &str) -> VanillaVersionLoader {
+        let mut url_fetcher = DummyUrlFetcher::new();
+        url_fetcher.add_data(
+            Url::parse(VERSION_MANIFEST_URL).unwrap(),
+            format!(
+                r#"{{
+                    "latest": {{"release": "1.20.1", "snapshot": "{}"}},
+                    "versions": [
+                        {{"id": "23w31a", "type": "snapshot", "url": "https://example.com/23w31a.json"}},
+                        {{"id": "1.20.1", "type": "release", "url": "https://example.com/1.20.1.json"}},
+                        {{"id": "1.20", "type": "release", "url": "https://example.com/1.20.json"}}
+                    ]
+                }}"#,
+                latest_snapshot
+            ),
+        );
+        create_test_loader(url_fetcher)
+    }
+
+    #[tokio::test]
+    async fn test_query_versions_latest() {
+        let loader = latest_manifest_loader("23w31a");
+
+        let versions = loader
+            .query_versions(&McVanillaVersionQuery::LatestRelease)
+            .await;
+        assert_eq!(versions.len(), 1);
+        assert_eq!(versions[0].version.id(), "1.20.1");
+
+        let versions = loader
+            .query_versions(&McVanillaVersionQuery::LatestSnapshot)
+            .await;
+        assert_eq!(versions.len(), 1);
+        assert_eq!(versions[0].version.id(), "23w31a");
+    }
+
+    #[tokio::test]
+    async fn test_resolve_latest() {
+        let loader = latest_manifest_loader("23w31a");
+        let release = loader
+            .resolve_latest(McVanillaVersionType::Release)
+            .await
+            .unwrap();
+        assert_eq!(release.version.id(), "1.20.1");
+        assert!(matches!(
+            release.version_type,
+            McVanillaVersionType::Release
+        ));
+
+        // Right after a release, the latest snapshot is the release itself
+        let loader = latest_manifest_loader("1.20.1");
+        let snapshot = loader
+            .resolve_latest(McVanillaVersionType::Snapshot)
+            .await
+            .unwrap();
+        assert_eq!(snapshot.version.id(), "1.20.1");
+    }
+
+    #[tokio::test]
+    async fn test_resolve_latest_without_latest_field() {
+        let loader = create_test_loader(version_fetcher("1.20.1", None));
+        let result = loader.resolve_latest(McVanillaVersionType::Release).await;
+        assert!(result.is_err());
+    }
+
     #[tokio::test]
     async fn test_query_versions_invalid_response() {
         let mut url_fetcher = DummyUrlFetcher::new();
@@ -440,6 +641,7 @@ mod tests {
                 &McVanillaVersion {
                     version: McVanillaVersionId::new("1.20.1".to_string()),
                     version_type: McVanillaVersionType::Release,
+                    release_time: None,
                 },
             )
             .await;
@@ -454,6 +656,7 @@ mod tests {
         let options = crate::domain::ServerRunOptions {
             max_memory: Some(2048),
             initial_memory: Some(1024),
+            ..Default::default()
         };
         let command = command_factory(options);
         let args: Vec<&str> = command
@@ -468,6 +671,55 @@ mod tests {
         assert!(args.contains(&"nogui"));
     }
 
+    fn version_fetcher(id: &str, java_version: Option<&str>) -> DummyUrlFetcher {
+        let mut url_fetcher = DummyUrlFetcher::new();
+        url_fetcher.add_data(
+            Url::parse("https://piston-meta.mojang.com/mc/game/version_manifest_v2.json").unwrap(),
+            format!(
+                r#"{{"versions": [{{"id": "{id}", "type": "release", "url": "https://example.com/{id}.json"}}]}}"#
+            ),
+        );
+        let java_version = java_version
+            .map(|java_version| format!(r#", "javaVersion": {}"#, java_version))
+            .unwrap_or_default();
+        url_fetcher.add_data(
+            Url::parse(&format!("https://example.com/{}.json", id)).unwrap(),
+            format!(
+                r#"{{"downloads": {{"server": {{"url": "https://example.com/server.jar"}}}}{}}}"#,
+                java_version
+            ),
+        );
+        url_fetcher
+    }
+
+    #[tokio::test]
+    async fn test_fetch_server_download_java_component() {
+        let url_fetcher = version_fetcher(
+            "1.20.1",
+            Some(r#"{"component": "java-runtime-gamma", "majorVersion": 17}"#),
+        );
+        let download =
+            fetch_server_download(&url_fetcher, &McVanillaVersionId::new("1.20.1".to_string()))
+                .await
+                .unwrap();
+        assert_eq!(
+            download.java_component,
+            JavaComponent::new("java-runtime-gamma")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fetch_server_download_legacy_java_component() {
+        // 1.8 version details have no javaVersion
+        let url_fetcher = version_fetcher("1.8.9", None);
+        let download =
+            fetch_server_download(&url_fetcher, &McVanillaVersionId::new("1.8.9".to_string()))
+                .await
+                .unwrap();
+        assert_eq!(download.java_component, JavaComponent::legacy());
+        assert_eq!(download.java_component.name(), "jre-legacy");
+    }
+
     #[tokio::test]
     async fn test_ready_version_not_found() {
         let mut url_fetcher = DummyUrlFetcher::new();
@@ -494,6 +746,7 @@ mod tests {
                 &McVanillaVersion {
                     version: McVanillaVersionId::new("1.19.4".to_string()),
                     version_type: McVanillaVersionType::Release,
+                    release_time: None,
                 },
             )
             .await;
@@ -545,6 +798,7 @@ mod tests {
                 &McVanillaVersion {
                     version: McVanillaVersionId::new("1.20.1".to_string()),
                     version_type: McVanillaVersionType::Release,
+                    release_time: None,
                 },
             )
             .await;
@@ -600,6 +854,7 @@ mod tests {
                 &McVanillaVersion {
                     version: McVanillaVersionId::new("1.20.1".to_string()),
                     version_type: McVanillaVersionType::Release,
+                    release_time: None,
                 },
             )
             .await;
@@ -611,6 +866,7 @@ mod tests {
         let options = crate::domain::ServerRunOptions {
             max_memory: None,
             initial_memory: None,
+            ..Default::default()
         };
         let command = command_factory(options);
         let args: Vec<&str> = command
@@ -628,6 +884,57 @@ mod tests {
         assert!(args.contains(&"nogui"));
     }
 
+    #[tokio::test]
+    async fn test_command_factory_custom_args() {
+        let mut url_fetcher = DummyUrlFetcher::new();
+        url_fetcher.add_data(
+            Url::parse("https://piston-meta.mojang.com/mc/game/version_manifest_v2.json").unwrap(),
+            r#"{"versions": [{"id": "1.20.1", "type": "release", "url": "https://example.com/1.20.1.json"}]}"#,
+        );
+        url_fetcher.add_data(
+            Url::parse("https://example.com/1.20.1.json").unwrap(),
+            r#"{"downloads": {"server": {"url": "https://example.com/server.jar"}}}"#,
+        );
+
+        let loader = create_test_loader(url_fetcher);
+        let (_, command_factory) = loader
+            .ready_server(
+                Dir::new(),
+                &McVanillaVersion {
+                    version: McVanillaVersionId::new("1.20.1".to_string()),
+                    version_type: McVanillaVersionType::Release,
+                    release_time: None,
+                },
+            )
+            .await
+            .unwrap();
+
+        let command = command_factory(crate::domain::ServerRunOptions {
+            max_memory: Some(2048),
+            jvm_args: vec!["-XX:+UseG1GC".to_string()],
+            server_args: vec!["--universe".to_string(), "worlds".to_string()],
+            ..Default::default()
+        });
+        let args: Vec<&str> = command
+            .get_args()
+            .map(|arg| arg.to_str().unwrap())
+            .collect();
+
+        // JVM args go before -jar, server args after nogui
+        assert_eq!(
+            args,
+            vec![
+                "-Xmx2048M",
+                "-XX:+UseG1GC",
+                "-jar",
+                "server.jar",
+                "nogui",
+                "--universe",
+                "worlds"
+            ]
+        );
+    }
+
     #[tokio::test]
     async fn test_world_data_includes_server_jar() {
         let mut url_fetcher = DummyUrlFetcher::new();
@@ -671,14 +978,18 @@ mod tests {
 
         // Create initial world data with some files
         let mut initial_world_data = Dir::new();
-        initial_world_data.put_file(
-            Path::from_str("level.dat"),
-            File::inline(b"world data".to_vec(), Permission::read_write())
-        ).unwrap();
-        initial_world_data.put_file(
-            Path::from_str("region/r.0.0.mca"), 
-            File::inline(b"region data".to_vec(), Permission::read_write())
-        ).unwrap();
+        initial_world_data
+            .put_file(
+                Path::from_str("level.dat"),
+                File::inline(b"world data".to_vec(), Permission::read_write()),
+            )
+            .unwrap();
+        initial_world_data
+            .put_file(
+                Path::from_str("region/r.0.0.mca"),
+                File::inline(b"region data".to_vec(), Permission::read_write()),
+            )
+            .unwrap();
 
         let result = loader
             .ready_server(
@@ -686,6 +997,7 @@ mod tests {
                 &McVanillaVersion {
                     version: McVanillaVersionId::new("1.20.1".to_string()),
                     version_type: McVanillaVersionType::Release,
+                    release_time: None,
                 },
             )
             .await;
@@ -694,17 +1006,98 @@ mod tests {
         let (final_world_data, _command_factory) = result.unwrap();
 
         // Check for original world files
-        let level_dat = final_world_data.get_file(Path::from_str("level.dat"))
+        let level_dat = final_world_data
+            .get_file(Path::from_str("level.dat"))
             .expect("level.dat should be present");
-        assert!(matches!(level_dat.content, crate::util::file_trie::FileContent::Inline(_)));
+        assert!(matches!(
+            level_dat.content,
+            crate::util::file_trie::FileContent::Inline(_)
+        ));
 
-        let region_file = final_world_data.get_file(Path::from_str("region/r.0.0.mca"))
+        let region_file = final_world_data
+            .get_file(Path::from_str("region/r.0.0.mca"))
             .expect("region file should be present");
-        assert!(matches!(region_file.content, crate::util::file_trie::FileContent::Inline(_)));
+        assert!(matches!(
+            region_file.content,
+            crate::util::file_trie::FileContent::Inline(_)
+        ));
 
         // Check for added server.jar
-        let server_jar = final_world_data.get_file(Path::from_str("server.jar"))
+        let server_jar = final_world_data
+            .get_file(Path::from_str("server.jar"))
             .expect("server.jar should be added to world data");
-        assert!(matches!(server_jar.content, crate::util::file_trie::FileContent::Url(_)));
+        assert!(matches!(
+            server_jar.content,
+            crate::util::file_trie::FileContent::Url(_)
+        ));
+        assert_eq!(server_jar.sha1.as_deref(), Some("abc123"));
+    }
+
+    // Counts how often each URL is fetched
+    struct CountingUrlFetcher {
+        inner: DummyUrlFetcher,
+        fetches: std::sync::Mutex<Vec<Url>>,
+    }
+
+    impl CountingUrlFetcher {
+        fn fetch_count(&self, url: &str) -> usize {
+            self.fetches
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|fetched| fetched.as_str() == url)
+                .count()
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl UrlFetcher for CountingUrlFetcher {
+        async fn fetch_binary(&self, url: &Url) -> Result<Vec<u8>, String> {
+            self.fetches.lock().unwrap().push(url.clone());
+            self.inner.fetch_binary(url).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_version_manifest_is_cached() {
+        let url_fetcher = Arc::new(CountingUrlFetcher {
+            inner: version_fetcher("1.20.1", None),
+            fetches: std::sync::Mutex::new(Vec::new()),
+        });
+        let loader = VanillaVersionLoader::new(url_fetcher.clone(), Arc::new(DummyJavaLoader));
+
+        assert_eq!(
+            loader
+                .query_versions(&McVanillaVersionQuery::All)
+                .await
+                .len(),
+            1
+        );
+        assert_eq!(
+            loader
+                .query_versions(&McVanillaVersionQuery::Release)
+                .await
+                .len(),
+            1
+        );
+        assert_eq!(url_fetcher.fetch_count(VERSION_MANIFEST_URL), 1);
+
+        loader.refresh().await.unwrap();
+        loader.query_versions(&McVanillaVersionQuery::All).await;
+        assert_eq!(url_fetcher.fetch_count(VERSION_MANIFEST_URL), 2);
+    }
+
+    #[tokio::test]
+    async fn test_version_manifest_expires() {
+        let url_fetcher = Arc::new(CountingUrlFetcher {
+            inner: version_fetcher("1.20.1", None),
+            fetches: std::sync::Mutex::new(Vec::new()),
+        });
+        let loader = VanillaVersionLoader::new(url_fetcher.clone(), Arc::new(DummyJavaLoader))
+            .with_manifest_ttl(Duration::ZERO);
+
+        loader.query_versions(&McVanillaVersionQuery::All).await;
+        loader.query_versions(&McVanillaVersionQuery::All).await;
+        assert_eq!(url_fetcher.fetch_count(VERSION_MANIFEST_URL), 2);
     }
 }
@@ -1,3 +1,5 @@
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
 use std::{
     collections::{HashMap, hash_map},
     path,
@@ -92,7 +94,7 @@ impl From<&Path> for Path {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum FileContent {
     Inline(Vec<u8>),
     Url(Url),
@@ -138,10 +140,14 @@ impl From<u16> for Permission {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct File {
     pub content: FileContent,
     pub permission: Permission,
+    /// Expected SHA1 of the content as a lowercase hex string
+    pub sha1: Option<String>,
+    /// Expected SHA256 of the content as a lowercase hex string
+    pub sha256: Option<String>,
 }
 
 impl File {
@@ -149,9 +155,49 @@ impl File {
         File {
             content,
             permission: permission.into(),
+            sha1: None,
+            sha256: None,
         }
     }
 
+    pub fn with_sha1(mut self, sha1: impl Into<String>) -> Self {
+        self.sha1 = Some(sha1.into().to_ascii_lowercase());
+        self
+    }
+
+    pub fn with_sha256(mut self, sha256: impl Into<String>) -> Self {
+        self.sha256 = Some(sha256.into().to_ascii_lowercase());
+        self
+    }
+
+    /// Checks that the loaded content matches the expected checksums, if any
+    pub fn verify_content(&self, data: &[u8]) -> Result<(), String> {
+        let mut digest = self.digest();
+        digest.update(data);
+        self.verify_digest(digest)
+    }
+
+    /// Starts hashing content for the checksums this file expects
+    pub fn digest(&self) -> ContentDigest {
+        ContentDigest {
+            sha1: self.sha1.as_ref().map(|_| Sha1::new()),
+            sha256: self.sha256.as_ref().map(|_| Sha256::new()),
+            size: 0,
+        }
+    }
+
+    /// Checks a digest fed with the whole content against the expected checksums, if any.
+    /// Lets content that is never held in memory be verified while it is streamed
+    pub fn verify_digest(&self, digest: ContentDigest) -> Result<(), String> {
+        if let (Some(expected), Some(hasher)) = (&self.sha1, digest.sha1) {
+            check_checksum("SHA1", expected, &hasher.finalize(), digest.size)?;
+        }
+        if let (Some(expected), Some(hasher)) = (&self.sha256, digest.sha256) {
+            check_checksum("SHA256", expected, &hasher.finalize(), digest.size)?;
+        }
+        Ok(())
+    }
+
     pub fn inline(data: Vec<u8>, permission: impl Into<Permission>) -> Self {
         File::new(FileContent::Inline(data), permission)
     }
@@ -165,7 +211,40 @@ impl File {
     }
 }
 
-#[derive(Debug, Clone)]
+/// Hashes content as it is streamed, for [`File::verify_digest`]
+pub struct ContentDigest {
+    sha1: Option<Sha1>,
+    sha256: Option<Sha256>,
+    size: u64,
+}
+
+impl ContentDigest {
+    pub fn update(&mut self, data: &[u8]) {
+        if let Some(hasher) = &mut self.sha1 {
+            hasher.update(data);
+        }
+        if let Some(hasher) = &mut self.sha256 {
+            hasher.update(data);
+        }
+        self.size += data.len() as u64;
+    }
+}
+
+fn check_checksum(algorithm: &str, expected: &str, actual: &[u8], size: u64) -> Result<(), String> {
+    let actual = actual
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+    if actual != expected {
+        return Err(format!(
+            "{} mismatch: expected {}, got {} ({} bytes)",
+            algorithm, expected, actual, size
+        ));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Dir(HashMap<String, Entry>);
 
 impl Dir {
@@ -173,12 +252,14 @@ impl Dir {
         Dir(HashMap::new())
     }
 
+    /// Returns the entry at `path`. The directory itself is not an `Entry`, so an empty path
+    /// returns `None`; use `get_dir` or `get_root` to treat the empty path as this directory
     pub fn get(&self, path: impl Into<Path>) -> Option<&Entry> {
         let vpath = path.into();
         let components = vpath.components();
 
         if components.is_empty() {
-            return None; // Root directory access not supported this way
+            return None;
         }
 
         let first = &components[0];
@@ -202,12 +283,20 @@ impl Dir {
             _ => None,
         }
     }
+    /// Returns the directory at `path`, or this directory itself for an empty path
     pub fn get_dir(&self, path: impl Into<Path>) -> Option<&Dir> {
+        let path = path.into();
+        if path.is_empty() {
+            return Some(self.get_root());
+        }
         match self.get(path) {
             Some(Entry::Dir(dir)) => Some(dir),
             _ => None,
         }
     }
+    pub fn get_root(&self) -> &Dir {
+        self
+    }
 
     pub fn put(&mut self, path: impl Into<Path>, entry: Entry) -> Result<(), Error> {
         let vpath: Path = path.into();
@@ -256,29 +345,61 @@ impl Dir {
     }
 
     pub fn delete(&mut self, path: impl Into<Path>) -> bool {
+        self.remove(path).is_some()
+    }
+
+    fn remove(&mut self, path: impl Into<Path>) -> Option<Entry> {
         let vpath = path.into();
         let components = vpath.components();
 
         if components.is_empty() {
-            return false; // Cannot delete root
+            return None; // Cannot delete root
         }
 
         if components.len() == 1 {
             let key = &components[0];
-            self.0.remove(key).is_some()
+            self.0.remove(key)
         } else {
             let first = &components[0];
 
             match self.0.get_mut(first) {
                 Some(Entry::Dir(dir)) => {
                     let remaining_path = Path(components[1..].to_vec());
-                    dir.delete(remaining_path)
+                    dir.remove(remaining_path)
                 }
-                _ => false, // Path doesn't exist or is a file
+                _ => None, // Path doesn't exist or is a file
             }
         }
     }
 
+    /// Moves the entry at `from` to `to`, creating intermediate directories and replacing
+    /// any entry already at `to`
+    pub fn move_entry(&mut self, from: impl Into<Path>, to: impl Into<Path>) -> Result<(), Error> {
+        let from: Path = from.into();
+        let to: Path = to.into();
+
+        if to.components().starts_with(from.components()) {
+            self.get(&from).ok_or(Error::NotFound)?;
+            // Moving onto itself is a no-op, but a directory cannot be moved into itself
+            return if to == from {
+                Ok(())
+            } else {
+                Err(Error::PathConflict)
+            };
+        }
+        // Check the destination before removing so that a failed move leaves the trie unchanged
+        let parents = &to.components()[..to.components().len().saturating_sub(1)];
+        for depth in 1..=parents.len() {
+            match self.get(&parents[..depth]) {
+                Some(Entry::Dir(_)) | None => {}
+                Some(_) => return Err(Error::PathConflict),
+            }
+        }
+
+        let entry = self.remove(&from).ok_or(Error::NotFound)?;
+        self.put(&to, entry)
+    }
+
     pub fn iter<'a>(&'a self) -> hash_map::Iter<'a, String, Entry> {
         self.0.iter()
     }
@@ -294,6 +415,114 @@ impl Dir {
     pub fn iter_all<'a>(&'a self) -> DirIterator<'a> {
         DirIterator::new(self, Path::new())
     }
+
+    /// Iterates over entries whose path matches `pattern`. `*` and `?` match any characters
+    /// within a path component and `**` matches any number of components
+    pub fn glob<'a>(&'a self, pattern: &str) -> impl Iterator<Item = (Path, &'a Entry)> + use<'a> {
+        let pattern = Path::from_str(pattern);
+        self.iter_all()
+            .filter(move |(path, _)| glob_match(pattern.components(), path.components()))
+    }
+
+    /// Compares this directory with `other`, treating `other` as the newer version.
+    /// Files are equal when their content and permission are, where inline files are compared
+    /// by bytes and URL or path files by their source. A directory present on only one side
+    /// is reported as a single path rather than file by file
+    pub fn diff(&self, other: &Dir) -> DirDiff {
+        let mut diff = DirDiff::default();
+        self.diff_into(other, &Path::new(), &mut diff);
+        diff.added.sort();
+        diff.removed.sort();
+        diff.changed.sort();
+        diff
+    }
+
+    fn diff_into(&self, other: &Dir, base: &Path, diff: &mut DirDiff) {
+        for (name, entry) in self.0.iter() {
+            let path = base.join(name.as_str());
+            match (entry, other.0.get(name)) {
+                (_, None) => diff.removed.push(path),
+                (Entry::Dir(dir), Some(Entry::Dir(other_dir))) => {
+                    dir.diff_into(other_dir, &path, diff)
+                }
+                (entry, Some(other_entry)) if entry != other_entry => diff.changed.push(path),
+                _ => {}
+            }
+        }
+        for name in other.0.keys() {
+            if !self.0.contains_key(name) {
+                diff.added.push(base.join(name.as_str()));
+            }
+        }
+    }
+
+    /// Whether this directory has no direct entries
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Number of files in this directory and all subdirectories
+    pub fn count_files(&self) -> usize {
+        self.iter_all().filter(|(_, entry)| entry.is_file()).count()
+    }
+
+    /// Number of files, directories and links in this directory and all subdirectories
+    pub fn count_entries(&self) -> usize {
+        self.iter_all().count()
+    }
+
+    /// Overlays `other` onto this directory. Directories present in both are merged recursively;
+    /// any other collision (file vs file, file vs dir, links) is resolved in favor of `other`
+    pub fn merge(&mut self, other: Dir) {
+        for (name, entry) in other.0 {
+            match (self.0.get_mut(&name), entry) {
+                (Some(Entry::Dir(dir)), Entry::Dir(other_dir)) => dir.merge(other_dir),
+                (_, entry) => {
+                    self.0.insert(name, entry);
+                }
+            }
+        }
+    }
+}
+
+fn glob_match(pattern: &[String], path: &[String]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((first, rest)) if first == "**" => {
+            (0..=path.len()).any(|skip| glob_match(rest, &path[skip..]))
+        }
+        Some((first, rest)) => match path.split_first() {
+            Some((name, path_rest)) => {
+                let pattern: Vec<char> = first.chars().collect();
+                let name: Vec<char> = name.chars().collect();
+                glob_match_component(&pattern, &name) && glob_match(rest, path_rest)
+            }
+            None => false,
+        },
+    }
+}
+
+fn glob_match_component(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) => (0..=name.len()).any(|skip| glob_match_component(rest, &name[skip..])),
+        Some(('?', rest)) => !name.is_empty() && glob_match_component(rest, &name[1..]),
+        Some((c, rest)) => name.first() == Some(c) && glob_match_component(rest, &name[1..]),
+    }
+}
+
+/// Paths that differ between two directories, as returned by `Dir::diff`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DirDiff {
+    pub added: Vec<Path>,
+    pub removed: Vec<Path>,
+    pub changed: Vec<Path>,
+}
+
+impl DirDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
 }
 
 pub struct DirIterator<'a> {
@@ -333,9 +562,10 @@ impl<'a> Iterator for DirIterator<'a> {
 #[derive(Debug)]
 pub enum Error {
     PathConflict,
+    NotFound,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Entry {
     File(File),
     Dir(Dir),
@@ -423,6 +653,40 @@ mod tests {
         assert!(nested_dir.is_some());
     }
 
+    #[test]
+    fn test_get_dir_root() {
+        // Copies the subtree at `from` in `src` to `to` in `dst`, with the empty path as the root
+        fn copy_tree(src: &Dir, from: &Path, dst: &mut Dir, to: &Path) {
+            for (name, entry) in src.get_dir(from).unwrap().iter() {
+                match entry {
+                    Entry::Dir(_) => {
+                        copy_tree(src, &from.join(name.as_str()), dst, &to.join(name.as_str()))
+                    }
+                    _ => dst.put(to.join(name.as_str()), entry.clone()).unwrap(),
+                }
+            }
+        }
+
+        let mut root = Dir::new();
+        let file = || File::inline(Vec::new(), Permission::read_write());
+        root.put_file("server.jar", file()).unwrap();
+        root.put_file("world/level.dat", file()).unwrap();
+        root.put_file("world/region/r.0.0.mca", file()).unwrap();
+
+        assert!(root.get("").is_none());
+        assert!(std::ptr::eq(root.get_dir("").unwrap(), root.get_root()));
+
+        let mut whole = Dir::new();
+        copy_tree(&root, &Path::new(), &mut whole, &Path::from_str("backup"));
+        assert_eq!(whole.count_files(), 3);
+        assert!(whole.get_file("backup/world/region/r.0.0.mca").is_some());
+
+        let mut world = Dir::new();
+        copy_tree(&root, &Path::from_str("world"), &mut world, &Path::new());
+        assert_eq!(world.count_files(), 2);
+        assert!(world.get_file("region/r.0.0.mca").is_some());
+    }
+
     #[test]
     fn test_vdir_deletion() {
         let mut root = Dir::new();
@@ -470,6 +734,209 @@ mod tests {
         assert!(root.put_dir("overwrite", dir).is_ok());
     }
 
+    #[test]
+    fn test_move_entry() {
+        let mut root = Dir::new();
+        let file =
+            |content: &str| File::inline(content.as_bytes().to_vec(), Permission::read_write());
+        root.put_file("world/level.dat", file("level")).unwrap();
+        root.put_file("world/region/r.0.0.mca", file("r00"))
+            .unwrap();
+        root.put_file("world/region/r.0.1.mca", file("r01"))
+            .unwrap();
+        root.put_file("server.properties", file("props")).unwrap();
+
+        // Whole subtree
+        root.move_entry("world/region", "world/DIM0/region")
+            .unwrap();
+        assert!(root.get("world/region").is_none());
+        assert!(root.get_file("world/DIM0/region/r.0.0.mca").is_some());
+        assert!(root.get_file("world/DIM0/region/r.0.1.mca").is_some());
+        assert!(root.get_file("world/level.dat").is_some());
+
+        // Single file
+        root.move_entry("server.properties", "config/server.properties")
+            .unwrap();
+        assert!(root.get("server.properties").is_none());
+        match &root.get_file("config/server.properties").unwrap().content {
+            FileContent::Inline(data) => assert_eq!(data, b"props"),
+            _ => panic!("Expected inline file"),
+        }
+    }
+
+    #[test]
+    fn test_move_entry_errors() {
+        let mut root = Dir::new();
+        let file = || File::inline(Vec::new(), Permission::read_write());
+        root.put_file("world/level.dat", file()).unwrap();
+        root.put_file("server.jar", file()).unwrap();
+
+        assert!(matches!(
+            root.move_entry("missing", "moved"),
+            Err(Error::NotFound)
+        ));
+        assert!(matches!(
+            root.move_entry("world", "world/backup"),
+            Err(Error::PathConflict)
+        ));
+        // Cannot create a directory below a file; the source stays in place
+        assert!(matches!(
+            root.move_entry("world/level.dat", "server.jar/level.dat"),
+            Err(Error::PathConflict)
+        ));
+        assert!(root.get_file("world/level.dat").is_some());
+        assert!(root.get_file("server.jar").is_some());
+    }
+
+    #[test]
+    fn test_glob() {
+        let mut root = Dir::new();
+        let file = || File::inline(Vec::new(), Permission::read_write());
+        for path in [
+            "level.dat",
+            "region/r.0.0.mca",
+            "region/r.-1.0.mca",
+            "region/r.0.0.mca.bak",
+            "DIM-1/region/r.0.0.mca",
+            "DIM-1/data/raids.dat",
+            "playerdata/uuid.dat",
+        ] {
+            root.put_file(path, file()).unwrap();
+        }
+        let glob = |pattern: &str| {
+            let mut paths: Vec<String> = root
+                .glob(pattern)
+                .map(|(path, _)| path.components().join("/"))
+                .collect();
+            paths.sort();
+            paths
+        };
+
+        assert_eq!(
+            glob("region/*.mca"),
+            vec!["region/r.-1.0.mca", "region/r.0.0.mca"]
+        );
+        assert_eq!(
+            glob("**/*.dat"),
+            vec!["DIM-1/data/raids.dat", "level.dat", "playerdata/uuid.dat"]
+        );
+        assert_eq!(
+            glob("**/region/r.?.0.mca"),
+            vec!["DIM-1/region/r.0.0.mca", "region/r.0.0.mca"]
+        );
+        assert_eq!(glob("DIM-1/*"), vec!["DIM-1/data", "DIM-1/region"]);
+        assert!(glob("region/*.mcc").is_empty());
+    }
+
+    #[test]
+    fn test_diff() {
+        let inline =
+            |content: &str| File::inline(content.as_bytes().to_vec(), Permission::read_write());
+        let url = |url: &str| File::url(Url::parse(url).unwrap(), Permission::read_write());
+        let mut old = Dir::new();
+        old.put_file("server.jar", url("https://example.com/1.20.jar"))
+            .unwrap();
+        old.put_file("world/level.dat", inline("level")).unwrap();
+        old.put_file("world/region/r.0.0.mca", inline("r00"))
+            .unwrap();
+        old.put_file("world/region/r.0.1.mca", inline("r01"))
+            .unwrap();
+        old.put_file("logs/latest.log", inline("log")).unwrap();
+
+        let mut new = old.clone();
+        assert!(old.diff(&new).is_empty());
+        assert_eq!(old, new);
+
+        new.put_file("server.jar", url("https://example.com/1.21.jar"))
+            .unwrap();
+        new.put_file("world/region/r.0.1.mca", inline("r01 edited"))
+            .unwrap();
+        new.put_file("world/region/r.1.0.mca", inline("r10"))
+            .unwrap();
+        new.put_file("world/DIM-1/region/r.0.0.mca", inline("nether"))
+            .unwrap();
+        new.delete("logs");
+
+        let diff = old.diff(&new);
+        assert_eq!(
+            diff.added,
+            vec![
+                Path::from_str("world/DIM-1"),
+                Path::from_str("world/region/r.1.0.mca"),
+            ]
+        );
+        assert_eq!(diff.removed, vec![Path::from_str("logs")]);
+        assert_eq!(
+            diff.changed,
+            vec![
+                Path::from_str("server.jar"),
+                Path::from_str("world/region/r.0.1.mca"),
+            ]
+        );
+        assert_ne!(old, new);
+    }
+
+    #[test]
+    fn test_count_entries() {
+        let mut root = Dir::new();
+        assert!(root.is_empty());
+        assert_eq!(root.count_files(), 0);
+        assert_eq!(root.count_entries(), 0);
+
+        let file = || File::inline(Vec::new(), Permission::read_write());
+        root.put_file("server.jar", file()).unwrap();
+        root.put_file("world/level.dat", file()).unwrap();
+        root.put_file("world/region/r.0.0.mca", file()).unwrap();
+        root.put_file("world/region/r.0.1.mca", file()).unwrap();
+        root.put_dir("logs", Dir::new()).unwrap();
+        root.put_link("libraries", path::PathBuf::from("/cache/libraries"))
+            .unwrap();
+
+        assert!(!root.is_empty());
+        assert!(root.get_dir("logs").unwrap().is_empty());
+        assert_eq!(root.count_files(), 4);
+        // 4 files, 3 directories (world, world/region, logs) and 1 link
+        assert_eq!(root.count_entries(), 8);
+        assert_eq!(root.get_dir("world").unwrap().count_files(), 3);
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut base = Dir::new();
+        let mut overlay = Dir::new();
+        let file =
+            |content: &str| File::inline(content.as_bytes().to_vec(), Permission::read_write());
+        base.put_file("server.jar", file("base jar")).unwrap();
+        base.put_file("config/base.toml", file("base")).unwrap();
+        base.put_file("config/shared.toml", file("base shared"))
+            .unwrap();
+        base.put_file("mods", file("not a directory")).unwrap();
+        base.put_dir("logs/old", Dir::new()).unwrap();
+        overlay
+            .put_file("config/shared.toml", file("overlay shared"))
+            .unwrap();
+        overlay
+            .put_file("config/nested/mod.toml", file("mod config"))
+            .unwrap();
+        overlay.put_file("mods/mod.jar", file("mod")).unwrap();
+        overlay.put_file("logs", file("logs file")).unwrap();
+
+        base.merge(overlay);
+
+        let content = |dir: &Dir, path: &str| match &dir.get_file(path).unwrap().content {
+            FileContent::Inline(data) => String::from_utf8(data.clone()).unwrap(),
+            _ => panic!("Expected inline file"),
+        };
+        assert_eq!(content(&base, "server.jar"), "base jar");
+        assert_eq!(content(&base, "config/base.toml"), "base");
+        assert_eq!(content(&base, "config/shared.toml"), "overlay shared");
+        assert_eq!(content(&base, "config/nested/mod.toml"), "mod config");
+        // File vs dir conflicts take the entry from `other`
+        assert_eq!(content(&base, "mods/mod.jar"), "mod");
+        assert_eq!(content(&base, "logs"), "logs file");
+        assert!(base.get_dir("logs/old").is_none());
+    }
+
     #[test]
     fn test_path_conversion() {
         let mut root = Dir::new();
@@ -1,8 +1,14 @@
 use anyhow::Result;
-use std::path::Path as StdPath;
+use futures::{StreamExt, TryStreamExt};
+use std::path::{Path as StdPath, PathBuf};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
 
-use crate::infra::{fs_handler::FsHandler, url_fetcher::UrlFetcher};
+use crate::infra::{
+    fs_handler::{FsHandler, is_busy_error},
+    url_fetcher::UrlFetcher,
+};
 use crate::util::file_trie::{Dir, Entry, File, FileContent, Path, Permission};
 
 /// 物理ファイルシステムからfile_trieを作成するハンドラ
@@ -22,12 +28,15 @@ impl FsToTrieConverter {
         Ok(dir)
     }
 
-    /// 単一ファイルを読み込んでFileを作成
+    /// 単一ファイルを読み込んでFileを作成する。
+    /// 物理ファイルシステムからは実行権限のみを読み取り、それ以外は読み書き可能とする
     pub fn load_file(&self, physical_path: &StdPath) -> Result<File> {
-        Ok(File::path(
-            physical_path.to_path_buf(),
-            Permission::read_write(),
-        ))
+        let permission = if self.fs_handler.is_executable(physical_path) {
+            Permission::executable()
+        } else {
+            Permission::read_write()
+        };
+        Ok(File::path(physical_path.to_path_buf(), permission))
     }
 
     fn load_directory_recursive(
@@ -52,7 +61,7 @@ impl FsToTrieConverter {
             // Check if this is a file or directory using efficient existence check
             if self.fs_handler.is_file(&entry_path) {
                 // It's a file
-                let file = File::path(entry_path.to_path_buf(), Permission::read_write());
+                let file = self.load_file(&entry_path)?;
                 dir.put_file(virtual_path, file)
                     .map_err(|_| anyhow::anyhow!("Failed to add file to trie"))?;
             } else if self.fs_handler.is_dir(&entry_path) {
@@ -68,10 +77,57 @@ impl FsToTrieConverter {
     }
 }
 
+/// 同時に書き込むファイルの最大数を上書きする環境変数
+pub const MAX_CONCURRENCY_ENV: &str = "SSMC_MAX_CONCURRENCY";
+
+// 環境変数で指定されていればその値を、なければCPU数の2倍を4〜32に収めた値を使う
+fn default_max_concurrency(env_value: Option<&str>, cpus: usize) -> usize {
+    match env_value.and_then(|value| value.trim().parse::<usize>().ok()) {
+        Some(max_concurrency) => max_concurrency.max(1),
+        None => (cpus * 2).clamp(4, 32),
+    }
+}
+
+/// `TrieToFsConverter::plan` が返す、書き込み時に行う操作
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlannedOp {
+    Mkdir {
+        path: std::path::PathBuf,
+    },
+    /// インラインのデータを書き込む
+    Write {
+        path: std::path::PathBuf,
+        size: u64,
+        executable: bool,
+    },
+    /// 物理ファイルをコピーする。`size` はコピー元のファイルのサイズで、読み取れなければ `None`
+    Copy {
+        from: std::path::PathBuf,
+        path: std::path::PathBuf,
+        size: Option<u64>,
+        executable: bool,
+    },
+    /// URLからダウンロードして書き込む。
+    /// 計画はネットワークにアクセスせずに作るため、`size` はContent-Lengthを問い合わせず常に `None`
+    Download {
+        url: url::Url,
+        path: std::path::PathBuf,
+        size: Option<u64>,
+        executable: bool,
+    },
+    Link {
+        path: std::path::PathBuf,
+        target: std::path::PathBuf,
+    },
+}
+
 /// file_trieから物理ファイルシステムに書き込むハンドラ
 pub struct TrieToFsConverter {
     fs_handler: Arc<dyn FsHandler + Send + Sync>,
     url_fetcher: Arc<dyn UrlFetcher + Send + Sync>,
+    max_write_retries: u32,
+    initial_write_backoff: Duration,
+    max_concurrency: usize,
 }
 
 impl TrieToFsConverter {
@@ -82,11 +138,89 @@ impl TrieToFsConverter {
         Self {
             fs_handler,
             url_fetcher,
+            max_write_retries: 5,
+            initial_write_backoff: Duration::from_millis(200),
+            max_concurrency: default_max_concurrency(
+                std::env::var(MAX_CONCURRENCY_ENV).ok().as_deref(),
+                num_cpus::get(),
+            ),
+        }
+    }
+
+    /// 同時に書き込む（ダウンロードする）ファイルの最大数。0は1として扱う。
+    /// 既定では環境変数 `SSMC_MAX_CONCURRENCY` の値、なければCPU数の2倍を4〜32に収めた値
+    pub fn with_max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.max_concurrency = max_concurrency.max(1);
+        self
+    }
+
+    /// 使用中のファイルへの書き込みに失敗した場合の再試行回数と、最初の再試行までの待機時間。
+    /// 待機時間は再試行のたびに倍になる
+    pub fn with_write_retry(mut self, max_retries: u32, initial_backoff: Duration) -> Self {
+        self.max_write_retries = max_retries;
+        self.initial_write_backoff = initial_backoff;
+        self
+    }
+
+    /// `write_directory` で行う操作を、ディレクトリ・ファイル・リンクの順に返す。
+    /// 何も書き込まず、ネットワークにもアクセスしない。サイズはインラインのデータと
+    /// コピー元のファイルのメタデータから求め、ダウンロードするファイルのサイズは含めない
+    pub fn plan(&self, dir: &Dir, base_path: &StdPath) -> Vec<PlannedOp> {
+        let mut entries: Vec<_> = dir.iter_all().collect();
+        entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let mut dirs = Vec::new();
+        let mut files = Vec::new();
+        let mut links = Vec::new();
+        for (path, entry) in entries {
+            let path = self.get_physical_path(base_path, &path);
+            match entry {
+                Entry::Dir(_) => dirs.push(PlannedOp::Mkdir { path }),
+                Entry::File(file) => {
+                    let executable = file.permission.is_executable();
+                    files.push(match &file.content {
+                        FileContent::Inline(data) => PlannedOp::Write {
+                            path,
+                            size: data.len() as u64,
+                            executable,
+                        },
+                        FileContent::Path(from) => PlannedOp::Copy {
+                            from: from.clone(),
+                            path,
+                            size: self.fs_handler.file_size(from),
+                            executable,
+                        },
+                        FileContent::Url(url) => PlannedOp::Download {
+                            url: url.clone(),
+                            path,
+                            size: None,
+                            executable,
+                        },
+                    });
+                }
+                Entry::Link(target) => links.push(PlannedOp::Link {
+                    path,
+                    target: target.clone(),
+                }),
+            }
         }
+        dirs.into_iter().chain(files).chain(links).collect()
     }
 
     /// DirをベースパスからPhysical FSに書き込み
     pub async fn write_directory(&self, dir: &Dir, base_path: &StdPath) -> Result<()> {
+        self.write_directory_with_progress(dir, base_path, &|_, _| {})
+            .await
+    }
+
+    /// DirをベースパスからPhysical FSに書き込み、ファイルを1つ書き込むたびに
+    /// `on_progress(書き込み済みのファイル数, ファイルの総数)` を呼ぶ
+    pub async fn write_directory_with_progress(
+        &self,
+        dir: &Dir,
+        base_path: &StdPath,
+        on_progress: &(dyn Fn(usize, usize) + Send + Sync),
+    ) -> Result<()> {
         let mut dirs = Vec::new();
         let mut files = Vec::new();
         let mut links = Vec::new();
@@ -115,12 +249,26 @@ impl TrieToFsConverter {
             })?;
         }
 
-        // 次にファイルを書き込み
-        for (path, file) in files {
-            let physical_path = self.get_physical_path(base_path, &path);
-            self.write_file(file, &physical_path, file.permission.is_executable())
-                .await?;
-        }
+        // 次にファイルを並行して書き込み
+        let total = files.len();
+        let done = AtomicUsize::new(0);
+        let writes: Vec<_> = files
+            .into_iter()
+            .map(|(path, file)| {
+                let physical_path = self.get_physical_path(base_path, &path);
+                let done = &done;
+                async move {
+                    self.write_file(file, &physical_path, file.permission.is_executable())
+                        .await?;
+                    on_progress(done.fetch_add(1, Ordering::SeqCst) + 1, total);
+                    Ok::<_, anyhow::Error>(())
+                }
+            })
+            .collect();
+        futures::stream::iter(writes)
+            .buffer_unordered(self.max_concurrency)
+            .try_collect::<()>()
+            .await?;
 
         // 最後にリンクを作成
         for (path, target) in links {
@@ -140,34 +288,106 @@ impl TrieToFsConverter {
         Ok(())
     }
 
-    /// 単一ファイルを物理パスに書き込み
+    /// 単一ファイルを物理パスに書き込み。
+    /// コピー元が物理ファイルの場合はメモリに読み込まずにコピーする
     pub async fn write_file(
         &self,
         file: &File,
         physical_path: &StdPath,
         executable: bool,
     ) -> Result<()> {
-        let data = self.get_file_data(file).await?;
-        self.fs_handler
-            .write(physical_path, &data, executable)
-            .map_err(|e| {
-                anyhow::anyhow!("Failed to write file {}: {}", physical_path.display(), e)
-            })?;
-        Ok(())
+        // コピーでは実行権限もコピー元のものになるため、異なる場合は書き込みで設定する
+        if let FileContent::Path(source) = &file.content
+            && self.fs_handler.is_executable(source) == executable
+        {
+            return self
+                .retry_while_busy(physical_path, || {
+                    self.fs_handler.copy(source, physical_path)
+                })
+                .await;
+        }
+        let data = match &file.content {
+            FileContent::Inline(data) => data.clone(),
+            FileContent::Path(source) => self.fs_handler.read(source).map_err(|e| {
+                anyhow::anyhow!("Failed to read source file {}: {}", source.display(), e)
+            })?,
+            FileContent::Url(url) => {
+                return self
+                    .download_file(file, url, physical_path, executable)
+                    .await;
+            }
+        };
+        self.retry_while_busy(physical_path, || {
+            self.fs_handler.write(physical_path, &data, executable)
+        })
+        .await
     }
 
-    async fn get_file_data(&self, file: &File) -> Result<Vec<u8>> {
-        match &file.content {
-            FileContent::Inline(data) => Ok(data.clone()),
-            FileContent::Path(path) => self.fs_handler.read(path).map_err(|e| {
-                anyhow::anyhow!("Failed to read source file {}: {}", path.display(), e)
-            }),
-            FileContent::Url(url) => self
-                .url_fetcher
-                .fetch_binary(url)
-                .await
-                .map_err(|e| anyhow::anyhow!("Failed to fetch URL {}: {}", url, e)),
+    async fn retry_while_busy(
+        &self,
+        physical_path: &StdPath,
+        operation: impl Fn() -> Result<(), String>,
+    ) -> Result<()> {
+        let mut backoff = self.initial_write_backoff;
+        let mut retries = 0;
+        loop {
+            match operation() {
+                Ok(()) => return Ok(()),
+                // 直前のサーバープロセスがファイルを解放するまで待って再試行する
+                Err(e) if is_busy_error(&e) && retries < self.max_write_retries => {
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                    retries += 1;
+                }
+                Err(e) => {
+                    return Err(anyhow::anyhow!(
+                        "Failed to write file {}: {}",
+                        physical_path.display(),
+                        e
+                    ));
+                }
+            }
+        }
+    }
+
+    /// URLの内容をメモリに載せずに隣の一時ファイルへ書き込みながらチェックサムを計算し、
+    /// 一致した場合のみ配置する。失敗時は一時ファイルを削除し、既存のファイルは置き換えない
+    async fn download_file(
+        &self,
+        file: &File,
+        url: &url::Url,
+        physical_path: &StdPath,
+        executable: bool,
+    ) -> Result<()> {
+        let mut temp_path = physical_path.as_os_str().to_owned();
+        temp_path.push(".download");
+        let temp_path = PathBuf::from(temp_path);
+        self.fs_handler
+            .write(&temp_path, &[], executable)
+            .map_err(|e| anyhow::anyhow!("Failed to write file {}: {}", temp_path.display(), e))?;
+
+        let mut digest = file.digest();
+        let fetched = self
+            .url_fetcher
+            .fetch_binary_chunks(url, &mut |chunk| {
+                digest.update(chunk);
+                self.fs_handler.append(&temp_path, chunk)
+            })
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to fetch URL {}: {}", url, e))
+            .and_then(|()| {
+                file.verify_digest(digest)
+                    .map_err(|e| anyhow::anyhow!("Corrupted download from {}: {}", url, e))
+            });
+        if let Err(e) = fetched {
+            let _ = self.fs_handler.delete(&temp_path);
+            return Err(e);
         }
+
+        self.retry_while_busy(physical_path, || {
+            self.fs_handler.rename(&temp_path, physical_path)
+        })
+        .await
     }
 
     fn get_physical_path(&self, base_path: &StdPath, virtual_path: &Path) -> std::path::PathBuf {
@@ -179,7 +399,7 @@ impl TrieToFsConverter {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::infra::fs_handler::OnMemoryFsHandler;
+    use crate::infra::fs_handler::{DefaultFsHandler, OnMemoryFsHandler};
     use crate::infra::url_fetcher::DummyUrlFetcher;
     use std::path::PathBuf;
     use std::sync::Arc;
@@ -227,4 +447,538 @@ mod tests {
             .unwrap();
         assert_eq!(nested_content, b"nested content");
     }
+
+    #[tokio::test]
+    async fn test_roundtrip_permissions_on_disk() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let fs_handler = Arc::new(DefaultFsHandler::new());
+
+        let mut dir = Dir::new();
+        dir.put_file(
+            Path::from_str("bin/java"),
+            File::inline(b"java".to_vec(), Permission::executable()),
+        )
+        .unwrap();
+        dir.put_file(
+            Path::from_str("conf/security/java.policy"),
+            File::inline(b"policy".to_vec(), Permission::read_only()),
+        )
+        .unwrap();
+
+        let trie_to_fs =
+            TrieToFsConverter::new(fs_handler.clone(), Arc::new(DummyUrlFetcher::new()));
+        trie_to_fs
+            .write_directory(&dir, temp_dir.path())
+            .await
+            .unwrap();
+
+        let loaded = FsToTrieConverter::new(fs_handler.clone())
+            .load_directory(temp_dir.path())
+            .unwrap();
+        let java = loaded.get_file(Path::from_str("bin/java")).unwrap();
+        let policy = loaded
+            .get_file(Path::from_str("conf/security/java.policy"))
+            .unwrap();
+        assert_eq!(
+            java.content,
+            FileContent::Path(temp_dir.path().join("bin/java"))
+        );
+        assert_eq!(
+            fs_handler.read(&temp_dir.path().join("bin/java")).unwrap(),
+            b"java"
+        );
+        // 読み取り専用は物理ファイルシステムに反映しないため、読み書き可能として読み込まれる
+        assert_eq!(policy.permission, Permission::read_write());
+        #[cfg(unix)]
+        assert_eq!(java.permission, Permission::executable());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_write_directory_keeps_executable_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut dir = Dir::new();
+        dir.put_file(
+            Path::from_str("bin/java"),
+            File::inline(b"java".to_vec(), Permission::executable()),
+        )
+        .unwrap();
+        dir.put_file(
+            Path::from_str("lib/modules"),
+            File::inline(b"modules".to_vec(), Permission::read_write()),
+        )
+        .unwrap();
+
+        TrieToFsConverter::new(
+            Arc::new(DefaultFsHandler::new()),
+            Arc::new(DummyUrlFetcher::new()),
+        )
+        .write_directory(&dir, temp_dir.path())
+        .await
+        .unwrap();
+
+        let mode = |path: &str| {
+            std::fs::metadata(temp_dir.path().join(path))
+                .unwrap()
+                .permissions()
+                .mode()
+        };
+        assert_ne!(mode("bin/java") & 0o111, 0);
+        assert_eq!(mode("lib/modules") & 0o111, 0);
+    }
+
+    #[tokio::test]
+    async fn test_write_directory_copies_path_files_without_reading() {
+        let fs_handler = Arc::new(FailingFsHandler::new("", 0));
+        let region = vec![7u8; 8 * 1024 * 1024];
+        fs_handler
+            .inner
+            .write(&PathBuf::from("/old/region/r.0.0.mca"), &region, false)
+            .unwrap();
+        fs_handler
+            .inner
+            .write(&PathBuf::from("/old/start.sh"), b"java -jar", false)
+            .unwrap();
+
+        let mut dir = Dir::new();
+        dir.put_file(
+            Path::from_str("region/r.0.0.mca"),
+            File::path(
+                PathBuf::from("/old/region/r.0.0.mca"),
+                Permission::read_write(),
+            ),
+        )
+        .unwrap();
+        // 実行権限が異なるファイルは読み込んで書き込む
+        dir.put_file(
+            Path::from_str("start.sh"),
+            File::path(PathBuf::from("/old/start.sh"), Permission::executable()),
+        )
+        .unwrap();
+
+        TrieToFsConverter::new(fs_handler.clone(), Arc::new(DummyUrlFetcher::new()))
+            .write_directory(&dir, &PathBuf::from("/new"))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            fs_handler
+                .inner
+                .read(&PathBuf::from("/new/region/r.0.0.mca"))
+                .unwrap(),
+            region
+        );
+        assert_eq!(*fs_handler.bytes_read.lock().unwrap(), b"java -jar".len());
+        assert_eq!(*fs_handler.write_attempts.lock().unwrap(), 1);
+        assert!(fs_handler.is_executable(&PathBuf::from("/new/start.sh")));
+    }
+
+    #[test]
+    fn test_plan() {
+        let mut dir = Dir::new();
+        let url = url::Url::parse("https://example.com/server.jar").unwrap();
+        dir.put_file(
+            Path::from_str("server.jar"),
+            File::url(url.clone(), Permission::read_write()),
+        )
+        .unwrap();
+        dir.put_file(
+            Path::from_str("world/level.dat"),
+            File::path(PathBuf::from("/old/level.dat"), Permission::read_write()),
+        )
+        .unwrap();
+        dir.put_file(
+            Path::from_str("start.sh"),
+            File::inline(b"java -jar server.jar".to_vec(), Permission::executable()),
+        )
+        .unwrap();
+        dir.put_dir(Path::from_str("world/region"), Dir::new())
+            .unwrap();
+        dir.put_link(
+            Path::from_str("libraries"),
+            PathBuf::from("/cache/libraries"),
+        )
+        .unwrap();
+
+        // 計画するだけで何も書き込まない
+        let fs_handler = Arc::new(OnMemoryFsHandler::new());
+        fs_handler
+            .write(&PathBuf::from("/old/level.dat"), &[0; 1234], false)
+            .unwrap();
+        let plan = TrieToFsConverter::new(fs_handler.clone(), Arc::new(DummyUrlFetcher::new()))
+            .plan(&dir, &PathBuf::from("/server"));
+
+        assert_eq!(
+            plan,
+            vec![
+                PlannedOp::Mkdir {
+                    path: PathBuf::from("/server/world"),
+                },
+                PlannedOp::Mkdir {
+                    path: PathBuf::from("/server/world/region"),
+                },
+                PlannedOp::Download {
+                    url,
+                    path: PathBuf::from("/server/server.jar"),
+                    size: None,
+                    executable: false,
+                },
+                PlannedOp::Write {
+                    path: PathBuf::from("/server/start.sh"),
+                    size: 20,
+                    executable: true,
+                },
+                PlannedOp::Copy {
+                    from: PathBuf::from("/old/level.dat"),
+                    path: PathBuf::from("/server/world/level.dat"),
+                    size: Some(1234),
+                    executable: false,
+                },
+                PlannedOp::Link {
+                    path: PathBuf::from("/server/libraries"),
+                    target: PathBuf::from("/cache/libraries"),
+                },
+            ]
+        );
+        assert_eq!(
+            plan.iter()
+                .filter(|op| !matches!(op, PlannedOp::Mkdir { .. } | PlannedOp::Link { .. }))
+                .count(),
+            dir.count_files()
+        );
+        assert!(!fs_handler.is_dir(&PathBuf::from("/server")));
+
+        // コピー元が読めなければサイズは分からない
+        fs_handler.delete(&PathBuf::from("/old/level.dat")).unwrap();
+        let plan = TrieToFsConverter::new(fs_handler, Arc::new(DummyUrlFetcher::new()))
+            .plan(&dir, &PathBuf::from("/server"));
+        assert!(
+            plan.iter()
+                .any(|op| matches!(op, PlannedOp::Copy { size: None, .. }))
+        );
+    }
+
+    #[test]
+    fn test_default_max_concurrency() {
+        assert_eq!(default_max_concurrency(None, 1), 4);
+        assert_eq!(default_max_concurrency(None, 8), 16);
+        assert_eq!(default_max_concurrency(None, 64), 32);
+        // 環境変数は上限・下限に関わらず優先するが、0は1にする
+        assert_eq!(default_max_concurrency(Some("2"), 8), 2);
+        assert_eq!(default_max_concurrency(Some(" 64 "), 8), 64);
+        assert_eq!(default_max_concurrency(Some("0"), 8), 1);
+        assert_eq!(default_max_concurrency(Some("many"), 8), 16);
+    }
+
+    #[tokio::test]
+    async fn test_zero_max_concurrency_is_clamped() {
+        let fs_handler = Arc::new(OnMemoryFsHandler::new());
+        let mut dir = Dir::new();
+        dir.put_file(
+            Path::from_str("a.txt"),
+            File::inline(b"a".to_vec(), Permission::read_write()),
+        )
+        .unwrap();
+
+        let converter =
+            TrieToFsConverter::new(fs_handler.clone(), Arc::new(DummyUrlFetcher::new()))
+                .with_max_concurrency(0);
+        assert_eq!(converter.max_concurrency, 1);
+        // buffer_unordered(0) のように止まらずに書き込める
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            converter.write_directory(&dir, &PathBuf::from("/out")),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(fs_handler.read(&PathBuf::from("/out/a.txt")).unwrap(), b"a");
+    }
+
+    // 同時に実行中の取得数の最大値を記録するUrlFetcher
+    #[derive(Default)]
+    struct ConcurrencyTrackingUrlFetcher {
+        in_flight: AtomicUsize,
+        max_in_flight: AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl UrlFetcher for ConcurrencyTrackingUrlFetcher {
+        async fn fetch_binary(&self, url: &url::Url) -> Result<Vec<u8>, String> {
+            let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            Ok(url.path().as_bytes().to_vec())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_write_directory_concurrently() {
+        let fs_handler = Arc::new(OnMemoryFsHandler::new());
+        let url_fetcher = Arc::new(ConcurrencyTrackingUrlFetcher::default());
+        let mut dir = Dir::new();
+        for i in 0..100 {
+            let url = url::Url::parse(&format!("https://example.com/lib/{}.jar", i)).unwrap();
+            dir.put_file(
+                Path::from_str(&format!("lib/{}/{}.jar", i % 10, i)),
+                File::url(url, Permission::read_write()),
+            )
+            .unwrap();
+        }
+        let progress = std::sync::Mutex::new(Vec::new());
+
+        TrieToFsConverter::new(fs_handler.clone(), url_fetcher.clone())
+            .with_max_concurrency(4)
+            .write_directory_with_progress(&dir, &PathBuf::from("/runtime"), &|done, total| {
+                progress.lock().unwrap().push((done, total))
+            })
+            .await
+            .unwrap();
+
+        for i in 0..100 {
+            assert_eq!(
+                fs_handler
+                    .read(&PathBuf::from(format!("/runtime/lib/{}/{}.jar", i % 10, i)))
+                    .unwrap(),
+                format!("/lib/{}.jar", i).into_bytes()
+            );
+        }
+        assert_eq!(url_fetcher.max_in_flight.load(Ordering::SeqCst), 4);
+        let progress = progress.into_inner().unwrap();
+        assert_eq!(progress.len(), 100);
+        assert_eq!(progress.last(), Some(&(100, 100)));
+    }
+
+    // 本文を数バイトずつ渡し、まとめて取得されると失敗するUrlFetcher
+    struct ChunkedUrlFetcher {
+        data: Vec<u8>,
+    }
+
+    #[async_trait::async_trait]
+    impl UrlFetcher for ChunkedUrlFetcher {
+        async fn fetch_binary(&self, url: &url::Url) -> Result<Vec<u8>, String> {
+            Err(format!("{} was buffered", url))
+        }
+
+        async fn fetch_binary_chunks(
+            &self,
+            _url: &url::Url,
+            on_chunk: &mut (dyn for<'a> FnMut(&'a [u8]) -> Result<(), String> + Send),
+        ) -> Result<(), String> {
+            for chunk in self.data.chunks(3) {
+                on_chunk(chunk)?;
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_write_url_file_streams_content() {
+        let fs_handler = Arc::new(OnMemoryFsHandler::new());
+        let url_fetcher = Arc::new(ChunkedUrlFetcher {
+            data: b"hello world".to_vec(),
+        });
+        let url = url::Url::parse("https://example.com/server.jar").unwrap();
+        let file = File::url(url, Permission::executable())
+            .with_sha1("2AAE6C35C94FCFB415DBE95F408B9CE91EE846ED")
+            .with_sha256("B94D27B9934D3E08A52E52D7DA7DABFAC484EFE37A5380EE9088F7ACE2EFCDE9");
+
+        TrieToFsConverter::new(fs_handler.clone(), url_fetcher)
+            .write_file(&file, &PathBuf::from("/out/server.jar"), true)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            fs_handler.read(&PathBuf::from("/out/server.jar")).unwrap(),
+            b"hello world"
+        );
+        assert!(fs_handler.is_executable(&PathBuf::from("/out/server.jar")));
+        assert!(!fs_handler.is_file(&PathBuf::from("/out/server.jar.download")));
+    }
+
+    #[tokio::test]
+    async fn test_write_url_file_rejects_sha256_mismatch() {
+        let fs_handler = Arc::new(OnMemoryFsHandler::new());
+        let url_fetcher = Arc::new(ChunkedUrlFetcher {
+            data: b"corrupted".to_vec(),
+        });
+        let url = url::Url::parse("https://example.com/paper.jar").unwrap();
+        // "hello world" のSHA256
+        let file = File::url(url, Permission::read_write())
+            .with_sha256("B94D27B9934D3E08A52E52D7DA7DABFAC484EFE37A5380EE9088F7ACE2EFCDE9");
+
+        let error = TrieToFsConverter::new(fs_handler.clone(), url_fetcher)
+            .write_file(&file, &PathBuf::from("/out/paper.jar"), false)
+            .await
+            .unwrap_err();
+
+        assert!(error.to_string().contains("SHA256 mismatch"), "{}", error);
+        assert!(!fs_handler.is_file(&PathBuf::from("/out/paper.jar")));
+        assert!(!fs_handler.is_file(&PathBuf::from("/out/paper.jar.download")));
+    }
+
+    #[tokio::test]
+    async fn test_write_url_file_rejects_sha1_mismatch() {
+        let fs_handler = Arc::new(OnMemoryFsHandler::new());
+        fs_handler
+            .write(&PathBuf::from("/out/server.jar"), b"old", false)
+            .unwrap();
+        let url_fetcher = Arc::new(ChunkedUrlFetcher {
+            data: b"corrupted".to_vec(),
+        });
+        let url = url::Url::parse("https://example.com/server.jar").unwrap();
+        let file = File::url(url, Permission::read_write())
+            .with_sha1("2aae6c35c94fcfb415dbe95f408b9ce91ee846ed");
+
+        let error = TrieToFsConverter::new(fs_handler.clone(), url_fetcher)
+            .write_file(&file, &PathBuf::from("/out/server.jar"), false)
+            .await
+            .unwrap_err();
+
+        assert!(error.to_string().contains("SHA1 mismatch"), "{}", error);
+        // 既存のファイルは置き換えず、一時ファイルも残さない
+        assert_eq!(
+            fs_handler.read(&PathBuf::from("/out/server.jar")).unwrap(),
+            b"old"
+        );
+        assert!(!fs_handler.is_file(&PathBuf::from("/out/server.jar.download")));
+    }
+
+    // 指定した回数だけ書き込みに失敗し、読み込んだバイト数を記録するFsHandler
+    struct FailingFsHandler {
+        inner: OnMemoryFsHandler,
+        error: String,
+        failures_left: std::sync::Mutex<u32>,
+        write_attempts: std::sync::Mutex<u32>,
+        bytes_read: std::sync::Mutex<usize>,
+    }
+
+    impl FailingFsHandler {
+        fn new(error: &str, failures: u32) -> Self {
+            Self {
+                inner: OnMemoryFsHandler::new(),
+                error: error.to_string(),
+                failures_left: std::sync::Mutex::new(failures),
+                write_attempts: std::sync::Mutex::new(0),
+                bytes_read: std::sync::Mutex::new(0),
+            }
+        }
+    }
+
+    impl FsHandler for FailingFsHandler {
+        fn list_entries(&self, path: &StdPath) -> Result<Vec<PathBuf>, String> {
+            self.inner.list_entries(path)
+        }
+        fn mkdir(&self, path: &StdPath) -> Result<(), String> {
+            self.inner.mkdir(path)
+        }
+        fn create_symlink(&self, path: &StdPath, target: &StdPath) -> Result<(), String> {
+            self.inner.create_symlink(path, target)
+        }
+        fn read(&self, path: &StdPath) -> Result<Vec<u8>, String> {
+            let data = self.inner.read(path)?;
+            *self.bytes_read.lock().unwrap() += data.len();
+            Ok(data)
+        }
+        fn write(&self, path: &StdPath, data: &[u8], executable: bool) -> Result<(), String> {
+            *self.write_attempts.lock().unwrap() += 1;
+            let mut failures_left = self.failures_left.lock().unwrap();
+            if *failures_left > 0 {
+                *failures_left -= 1;
+                return Err(self.error.clone());
+            }
+            self.inner.write(path, data, executable)
+        }
+        fn append(&self, path: &StdPath, data: &[u8]) -> Result<(), String> {
+            self.inner.append(path, data)
+        }
+        fn copy(&self, from: &StdPath, to: &StdPath) -> Result<(), String> {
+            self.inner.copy(from, to)
+        }
+        fn rename(&self, from: &StdPath, to: &StdPath) -> Result<(), String> {
+            self.inner.rename(from, to)
+        }
+        fn delete(&self, path: &StdPath) -> Result<(), String> {
+            self.inner.delete(path)
+        }
+        fn is_file(&self, path: &StdPath) -> bool {
+            self.inner.is_file(path)
+        }
+        fn is_dir(&self, path: &StdPath) -> bool {
+            self.inner.is_dir(path)
+        }
+        fn is_executable(&self, path: &StdPath) -> bool {
+            self.inner.is_executable(path)
+        }
+        fn file_size(&self, path: &StdPath) -> Option<u64> {
+            self.inner.file_size(path)
+        }
+    }
+
+    fn single_file_trie() -> Dir {
+        let mut dir = Dir::new();
+        dir.put_file(
+            Path::from_str("world/level.dat"),
+            File::inline(b"level".to_vec(), Permission::read_write()),
+        )
+        .unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn test_write_directory_retries_busy_file() {
+        let fs_handler = Arc::new(FailingFsHandler::new(
+            "Resource busy: Failed to write file /output/world/level.dat: in use",
+            1,
+        ));
+        let trie_to_fs =
+            TrieToFsConverter::new(fs_handler.clone(), Arc::new(DummyUrlFetcher::new()))
+                .with_write_retry(3, Duration::from_millis(1));
+
+        trie_to_fs
+            .write_directory(&single_file_trie(), &PathBuf::from("/output"))
+            .await
+            .unwrap();
+
+        assert_eq!(*fs_handler.write_attempts.lock().unwrap(), 2);
+        assert_eq!(
+            fs_handler
+                .read(&PathBuf::from("/output/world/level.dat"))
+                .unwrap(),
+            b"level"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_write_directory_does_not_retry_genuine_failure() {
+        let fs_handler = Arc::new(FailingFsHandler::new("Disk full", 1));
+        let trie_to_fs =
+            TrieToFsConverter::new(fs_handler.clone(), Arc::new(DummyUrlFetcher::new()))
+                .with_write_retry(3, Duration::from_millis(1));
+
+        let result = trie_to_fs
+            .write_directory(&single_file_trie(), &PathBuf::from("/output"))
+            .await;
+
+        assert!(result.unwrap_err().to_string().contains("Disk full"));
+        assert_eq!(*fs_handler.write_attempts.lock().unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_write_directory_gives_up_after_max_retries() {
+        let fs_handler = Arc::new(FailingFsHandler::new("Resource busy: in use", 10));
+        let trie_to_fs =
+            TrieToFsConverter::new(fs_handler.clone(), Arc::new(DummyUrlFetcher::new()))
+                .with_write_retry(2, Duration::from_millis(1));
+
+        let result = trie_to_fs
+            .write_directory(&single_file_trie(), &PathBuf::from("/output"))
+            .await;
+
+        assert!(result.is_err());
+        assert_eq!(*fs_handler.write_attempts.lock().unwrap(), 3);
+    }
 }